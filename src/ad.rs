@@ -0,0 +1,288 @@
+use std::future::Future;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::pin::Pin;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Result};
+use futures::future::FutureExt;
+
+use crate::ctf::{Credentials, FlagSubmission};
+use crate::flag;
+
+/// Maximum number of flags submitted at once.
+pub const BATCH_SIZE: usize = 100;
+
+/// Expands octet ranges in an IPv4 address, e.g. 10.60.1-3.1 to 10.60.1.1, 10.60.2.1 and
+/// 10.60.3.1.
+pub fn expand_range(range: &str) -> Result<Vec<String>> {
+    let mut result = vec![String::new()];
+    let octets: Vec<&str> = range.trim().split('.').collect();
+    if octets.len() != 4 {
+        bail!("{} is not an IPv4 address range", range);
+    }
+    for octet in octets {
+        let (first, last) = match octet.split_once('-') {
+            Some((first, last)) => (first.parse::<u8>()?, last.parse::<u8>()?),
+            None => {
+                let value = octet.parse::<u8>()?;
+                (value, value)
+            }
+        };
+        if first > last {
+            bail!("Empty octet range {} in {}", octet, range);
+        }
+        result = result
+            .iter()
+            .flat_map(|prefix| {
+                (first..=last).map(move |value| match prefix.is_empty() {
+                    true => value.to_string(),
+                    false => format!("{}.{}", prefix, value),
+                })
+            })
+            .collect();
+    }
+    Ok(result)
+}
+
+fn team_address(team: &serde_yaml::Value) -> Option<String> {
+    match team {
+        serde_yaml::Value::String(address) => Some(address.clone()),
+        serde_yaml::Value::Mapping(_) => ["ip", "host", "address"]
+            .iter()
+            .find_map(|key| team.get(key)?.as_str().map(str::to_string)),
+        _ => None,
+    }
+}
+
+/// Parses the game server team list: either a list of addresses or of objects with an `ip`,
+/// `host` or `address`, possibly wrapped into an object with a `teams` key.
+pub fn parse_teams(json: &str) -> Result<Vec<String>> {
+    let value: serde_yaml::Value = serde_yaml::from_str(json)?;
+    let teams = match value.get("teams") {
+        Some(teams) => teams,
+        None => &value,
+    };
+    let teams = teams
+        .as_sequence()
+        .ok_or_else(|| anyhow!("Team list is not a JSON array"))?;
+    Ok(teams.iter().filter_map(team_address).collect())
+}
+
+/// Outcome of running an exploit against a single target.
+pub enum Status {
+    Ok,
+    Failed(String),
+    Timeout,
+}
+
+pub struct Attack {
+    pub target: String,
+    pub status: Status,
+    pub flags: Vec<String>,
+}
+
+fn attack(
+    target: &str,
+    command: &mut Command,
+    timeout: Duration,
+    detector: &flag::Detector,
+) -> Result<Attack> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+    let mut stdout = child.stdout.take().unwrap();
+    let reader = thread::spawn(move || {
+        let mut output = Vec::new();
+        let _ = stdout.read_to_end(&mut output);
+        output
+    });
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(exit_status) = child.try_wait()? {
+            break match exit_status.success() {
+                true => Status::Ok,
+                false => Status::Failed(exit_status.to_string()),
+            };
+        }
+        if Instant::now() >= deadline {
+            child.kill()?;
+            child.wait()?;
+            break Status::Timeout;
+        }
+        thread::sleep(Duration::from_millis(50));
+    };
+    /* Grandchildren may keep stdout open, do not wait for them for too long. */
+    let deadline = Instant::now() + Duration::from_secs(1);
+    while !reader.is_finished() && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(50));
+    }
+    let output = match reader.is_finished() {
+        true => reader.join().unwrap_or_default(),
+        false => Vec::new(),
+    };
+    Ok(Attack {
+        target: target.to_string(),
+        status,
+        flags: detector.find(&String::from_utf8_lossy(&output)),
+    })
+}
+
+/// Runs the exploit against all targets, at most `jobs` at a time; `command` creates the exploit
+/// command for a target. The attacks are returned in the order of the targets.
+pub fn attack_all<F>(
+    targets: &[String],
+    command: F,
+    timeout: Duration,
+    detector: &flag::Detector,
+    jobs: usize,
+) -> Vec<Attack>
+where
+    F: Fn(&str) -> Result<Command> + Sync,
+{
+    let next = AtomicUsize::new(0);
+    let results: Vec<Mutex<Option<Attack>>> = targets.iter().map(|_| Mutex::new(None)).collect();
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..jobs.clamp(1, targets.len().max(1)))
+            .map(|_| {
+                scope.spawn(|| loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(target) = targets.get(i) else {
+                        break;
+                    };
+                    let result = command(target)
+                        .and_then(|mut command| attack(target, &mut command, timeout, detector))
+                        .unwrap_or_else(|e| Attack {
+                            target: target.clone(),
+                            status: Status::Failed(e.to_string()),
+                            flags: Vec::new(),
+                        });
+                    *results[i].lock().unwrap() = Some(result);
+                })
+            })
+            .collect();
+        for worker in workers {
+            /* The other workers take over the remaining targets. */
+            let _ = worker.join();
+        }
+    });
+    results
+        .into_iter()
+        .zip(targets)
+        .map(|(result, target)| {
+            result
+                .into_inner()
+                .ok()
+                .flatten()
+                .unwrap_or_else(|| Attack {
+                    target: target.clone(),
+                    status: Status::Failed("panicked".into()),
+                    flags: Vec::new(),
+                })
+        })
+        .collect()
+}
+
+type SubmitResult<'a> = Pin<Box<dyn Future<Output = Result<Vec<String>>> + 'a>>;
+
+/// Flag submission backend of a game server.
+pub trait Submitter {
+    /// Submits a batch of flags and returns a verdict for each of them.
+    fn submit<'a>(&'a self, flags: &'a [String]) -> SubmitResult<'a>;
+}
+
+pub struct TcpSubmitter {
+    address: String,
+}
+
+fn submit_tcp(address: &str, flags: &[String]) -> Result<Vec<String>> {
+    let mut stream = TcpStream::connect(address)?;
+    stream.set_read_timeout(Some(Duration::from_secs(1)))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    /* Skip the banner, which the server may or may not send. */
+    let mut banner = Vec::new();
+    let _ = reader.read_until(0, &mut banner);
+    stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+    let mut result = Vec::new();
+    for flag in flags {
+        stream.write_all(format!("{}\n", flag).as_bytes())?;
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            bail!("{} closed the connection", address);
+        }
+        let verdict = line.trim();
+        result.push(
+            verdict
+                .strip_prefix(flag.as_str())
+                .unwrap_or(verdict)
+                .trim()
+                .to_string(),
+        );
+    }
+    Ok(result)
+}
+
+impl Submitter for TcpSubmitter {
+    fn submit<'a>(&'a self, flags: &'a [String]) -> SubmitResult<'a> {
+        async move { submit_tcp(&self.address, flags) }.boxed()
+    }
+}
+
+pub struct HttpSubmitter {
+    url: String,
+    token: Option<String>,
+}
+
+fn verdict(item: &serde_yaml::Value) -> String {
+    ["msg", "message", "status"]
+        .iter()
+        .find_map(|key| item.get(key)?.as_str())
+        .map(str::to_string)
+        .unwrap_or_else(|| {
+            serde_yaml::to_string(item)
+                .map(|s| s.trim().to_string())
+                .unwrap_or_default()
+        })
+}
+
+async fn submit_http(submitter: &HttpSubmitter, flags: &[String]) -> Result<Vec<String>> {
+    let client = reqwest::Client::new();
+    let mut request = client.put(&submitter.url).json(flags);
+    if let Some(token) = &submitter.token {
+        request = request.header("X-Team-Token", token);
+    }
+    let response = request.send().await?;
+    response.error_for_status_ref()?;
+    let text = response.text().await?;
+    /* Usually a list with a verdict for each flag, otherwise the same verdict for all of them. */
+    match serde_yaml::from_str::<serde_yaml::Value>(&text) {
+        Ok(serde_yaml::Value::Sequence(items)) if items.len() == flags.len() => {
+            Ok(items.iter().map(verdict).collect())
+        }
+        _ => Ok(vec![text.trim().to_string(); flags.len()]),
+    }
+}
+
+impl Submitter for HttpSubmitter {
+    fn submit<'a>(&'a self, flags: &'a [String]) -> SubmitResult<'a> {
+        submit_http(self, flags).boxed()
+    }
+}
+
+pub fn submitter(submission: &FlagSubmission, credentials: &Credentials) -> Box<dyn Submitter> {
+    match submission {
+        FlagSubmission::Tcp { address } => Box::new(TcpSubmitter {
+            address: address.clone(),
+        }),
+        FlagSubmission::Http { url } => Box::new(HttpSubmitter {
+            url: url.clone(),
+            token: credentials.team_token.clone(),
+        }),
+    }
+}
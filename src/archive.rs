@@ -0,0 +1,158 @@
+use anyhow::{anyhow, bail, Result};
+use std::io::Read;
+
+use crate::kernel;
+
+/// File extracted from an archive.
+pub struct Entry {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+const TAR_BLOCK_SIZE: usize = 512;
+
+fn is_tar(bytes: &[u8]) -> bool {
+    bytes.get(257..262) == Some(b"ustar")
+}
+
+fn is_zip(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"PK\x03\x04")
+}
+
+fn is_cpio(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"070701") || bytes.starts_with(b"070702")
+}
+
+fn c_string(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes.split(|b| *b == 0).next().unwrap()).into_owned()
+}
+
+fn parse_octal(bytes: &[u8]) -> Result<usize> {
+    let s = c_string(bytes);
+    let s = s.trim();
+    if s.is_empty() {
+        return Ok(0);
+    }
+    Ok(usize::from_str_radix(s, 8)?)
+}
+
+/// Parses a ustar or GNU tar archive, skipping everything except regular files.
+fn parse_tar(bytes: &[u8]) -> Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    let mut pos = 0;
+    while let Some(header) = bytes.get(pos..pos + TAR_BLOCK_SIZE) {
+        if header.iter().all(|b| *b == 0) {
+            break;
+        }
+        let size = parse_octal(&header[124..136])?;
+        let data_start = pos + TAR_BLOCK_SIZE;
+        let data = bytes
+            .get(data_start..data_start + size)
+            .ok_or_else(|| anyhow!("Truncated tar file data at 0x{:x}", pos))?;
+        pos = data_start + size.div_ceil(TAR_BLOCK_SIZE) * TAR_BLOCK_SIZE;
+        if header[156] != b'0' && header[156] != 0 {
+            continue;
+        }
+        let prefix = c_string(&header[345..500]);
+        let name = c_string(&header[..100]);
+        entries.push(Entry {
+            name: if prefix.is_empty() {
+                name
+            } else {
+                format!("{}/{}", prefix, name)
+            },
+            data: data.to_vec(),
+        });
+    }
+    Ok(entries)
+}
+
+fn u16_at(bytes: &[u8], pos: usize) -> Result<usize> {
+    let field = bytes
+        .get(pos..pos + 2)
+        .ok_or_else(|| anyhow!("Truncated zip header at 0x{:x}", pos))?;
+    Ok(u16::from_le_bytes([field[0], field[1]]) as usize)
+}
+
+fn u32_at(bytes: &[u8], pos: usize) -> Result<usize> {
+    let field = bytes
+        .get(pos..pos + 4)
+        .ok_or_else(|| anyhow!("Truncated zip header at 0x{:x}", pos))?;
+    Ok(u32::from_le_bytes([field[0], field[1], field[2], field[3]]) as usize)
+}
+
+/// Parses a zip archive using its central directory, which has the sizes even when the local
+/// headers do not.
+fn parse_zip(bytes: &[u8]) -> Result<Vec<Entry>> {
+    let eocd = (0..bytes.len().saturating_sub(21))
+        .rev()
+        .find(|pos| bytes[*pos..].starts_with(b"PK\x05\x06"))
+        .ok_or_else(|| anyhow!("No zip end of central directory record"))?;
+    let count = u16_at(bytes, eocd + 10)?;
+    let mut pos = u32_at(bytes, eocd + 16)?;
+    let mut entries = Vec::new();
+    for _ in 0..count {
+        if !bytes
+            .get(pos..)
+            .is_some_and(|entry| entry.starts_with(b"PK\x01\x02"))
+        {
+            bail!("Bad zip central directory entry at 0x{:x}", pos);
+        }
+        let method = u16_at(bytes, pos + 10)?;
+        let compressed_size = u32_at(bytes, pos + 20)?;
+        let name_size = u16_at(bytes, pos + 28)?;
+        let extra_size = u16_at(bytes, pos + 30)?;
+        let comment_size = u16_at(bytes, pos + 32)?;
+        let local = u32_at(bytes, pos + 42)?;
+        let name = bytes
+            .get(pos + 46..pos + 46 + name_size)
+            .ok_or_else(|| anyhow!("Truncated zip file name at 0x{:x}", pos))?;
+        let name = String::from_utf8_lossy(name).into_owned();
+        pos += 46 + name_size + extra_size + comment_size;
+        if !bytes
+            .get(local..)
+            .is_some_and(|header| header.starts_with(b"PK\x03\x04"))
+        {
+            bail!("Bad zip local header for {} at 0x{:x}", name, local);
+        }
+        let data_start = local + 30 + u16_at(bytes, local + 26)? + u16_at(bytes, local + 28)?;
+        let compressed = bytes
+            .get(data_start..data_start + compressed_size)
+            .ok_or_else(|| anyhow!("Truncated zip file data for {}", name))?;
+        let data = match method {
+            0 => compressed.to_vec(),
+            8 => {
+                let mut data = Vec::new();
+                flate2::read::DeflateDecoder::new(compressed).read_to_end(&mut data)?;
+                data
+            }
+            _ => continue,
+        };
+        if !name.ends_with('/') {
+            entries.push(Entry { name, data });
+        }
+    }
+    Ok(entries)
+}
+
+/// Extracts the files from a tar, zip or cpio archive; returns None for other data.
+pub fn extract(bytes: &[u8]) -> Result<Option<Vec<Entry>>> {
+    if is_tar(bytes) {
+        Ok(Some(parse_tar(bytes)?))
+    } else if is_zip(bytes) {
+        Ok(Some(parse_zip(bytes)?))
+    } else if is_cpio(bytes) {
+        Ok(Some(
+            kernel::parse_cpio(bytes)?
+                .into_iter()
+                .filter(kernel::CpioEntry::is_file)
+                .map(|entry| Entry {
+                    name: entry.name,
+                    data: entry.data,
+                })
+                .collect(),
+        ))
+    } else {
+        Ok(None)
+    }
+}
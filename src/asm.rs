@@ -0,0 +1,97 @@
+use anyhow::{anyhow, bail, Result};
+
+/// Parses a hexadecimal (with `0x` prefix) or a decimal number.
+pub fn parse_address(s: &str) -> Result<u64> {
+    let s = s.trim();
+    let result = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    result.map_err(|_| anyhow!("Invalid address: {}", s))
+}
+
+fn condition_code(mnemonic: &str) -> Option<u8> {
+    let cc = match mnemonic {
+        "jo" => 0x0,
+        "jno" => 0x1,
+        "jb" | "jc" | "jnae" => 0x2,
+        "jae" | "jnb" | "jnc" => 0x3,
+        "je" | "jz" => 0x4,
+        "jne" | "jnz" => 0x5,
+        "jbe" | "jna" => 0x6,
+        "ja" | "jnbe" => 0x7,
+        "js" => 0x8,
+        "jns" => 0x9,
+        "jp" | "jpe" => 0xa,
+        "jnp" | "jpo" => 0xb,
+        "jl" | "jnge" => 0xc,
+        "jge" | "jnl" => 0xd,
+        "jle" | "jng" => 0xe,
+        "jg" | "jnle" => 0xf,
+        _ => return None,
+    };
+    Some(cc)
+}
+
+/// Encodes a relative branch, preferring the short form if `short` is present.
+fn branch(vaddr: u64, target: u64, short: Option<&[u8]>, near: &[u8]) -> Result<Vec<u8>> {
+    let displacement = |len: usize| target.wrapping_sub(vaddr.wrapping_add(len as u64)) as i64;
+    if let Some(short) = short {
+        let rel = displacement(short.len() + 1);
+        if let Ok(rel) = i8::try_from(rel) {
+            let mut result = short.to_vec();
+            result.push(rel as u8);
+            return Ok(result);
+        }
+    }
+    let rel = displacement(near.len() + 4);
+    let rel = i32::try_from(rel).map_err(|_| anyhow!("Branch target 0x{:x} is too far", target))?;
+    let mut result = near.to_vec();
+    result.extend_from_slice(&rel.to_le_bytes());
+    Ok(result)
+}
+
+/// Assembles a single x86 instruction located at `vaddr`.
+fn assemble_one(vaddr: u64, insn: &str) -> Result<Vec<u8>> {
+    let mut parts = insn.split_whitespace();
+    let mnemonic = parts
+        .next()
+        .ok_or_else(|| anyhow!("Empty instruction"))?
+        .to_lowercase();
+    let operand = parts.next();
+    if parts.next().is_some() {
+        bail!("Too many operands: {}", insn);
+    }
+    let target = || -> Result<u64> {
+        parse_address(operand.ok_or_else(|| anyhow!("Missing branch target: {}", insn))?)
+    };
+    match (mnemonic.as_str(), operand) {
+        ("nop", None) => Ok(vec![0x90]),
+        ("ret", None) => Ok(vec![0xc3]),
+        ("int3", None) => Ok(vec![0xcc]),
+        ("jmp", Some(_)) => branch(vaddr, target()?, Some(&[0xeb]), &[0xe9]),
+        ("call", Some(_)) => branch(vaddr, target()?, None, &[0xe8]),
+        (_, Some(_)) => match condition_code(&mnemonic) {
+            Some(cc) => branch(vaddr, target()?, Some(&[0x70 | cc]), &[0x0f, 0x80 | cc]),
+            None => bail!("Unsupported instruction: {}", insn),
+        },
+        _ => bail!("Unsupported instruction: {}", insn),
+    }
+}
+
+/// Assembles `;`-separated x86 instructions located at `vaddr`.
+///
+/// Only what is commonly needed for patching is supported: `nop`, `ret`, `int3`, `call`, `jmp`
+/// and conditional jumps to absolute addresses.
+pub fn assemble(mut vaddr: u64, s: &str) -> Result<Vec<u8>> {
+    let mut result = Vec::new();
+    for insn in s.split(';').map(str::trim).filter(|insn| !insn.is_empty()) {
+        let bytes = assemble_one(vaddr, insn)?;
+        vaddr = vaddr.wrapping_add(bytes.len() as u64);
+        result.extend(bytes);
+    }
+    if result.is_empty() {
+        bail!("No instructions");
+    }
+    Ok(result)
+}
@@ -0,0 +1,649 @@
+use anyhow::{anyhow, bail, Result};
+use clap::Parser;
+
+use crate::ctf::{AttackDefense, FlagSubmission};
+use crate::exploit::{exploit_command, python_bytes};
+use crate::ui::Format;
+use crate::{ad, ctf, flag, git, http, pcap, Ui};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+#[derive(Parser)]
+pub struct Ad {
+    #[clap(subcommand)]
+    subcmd: SubCommand,
+}
+
+#[derive(Parser)]
+enum SubCommand {
+    /// Configures targets and flag submission
+    #[clap(name = "init")]
+    Init(Init),
+
+    /// Prints the targets
+    #[clap(name = "targets")]
+    Targets(Targets),
+
+    /// Runs a challenge exploit against all targets each tick
+    #[clap(name = "run")]
+    Run(Run),
+
+    /// Captures the service traffic, starting a new pcap each tick
+    #[clap(name = "capture")]
+    Capture(Capture),
+
+    /// Lists the TCP streams in the captured traffic
+    #[clap(name = "streams")]
+    Streams(Streams),
+
+    /// Turns a captured TCP stream into a pwntools script
+    #[clap(name = "replay")]
+    Replay(Replay),
+}
+
+impl Ad {
+    /// Whether the subcommand prints records, which `--format` applies to.
+    pub fn prints_records(&self) -> bool {
+        matches!(self.subcmd, SubCommand::Targets(_) | SubCommand::Streams(_))
+    }
+}
+
+#[derive(Parser)]
+pub struct Init {
+    /// Team addresses with octet ranges, e.g. 10.60.1-40.1
+    #[clap(long)]
+    pub teams: Option<String>,
+
+    /// Game server URL with a JSON list of team addresses
+    #[clap(long)]
+    pub teams_url: Option<String>,
+
+    /// Address that is not attacked, e.g. our own
+    #[clap(long)]
+    pub exclude: Vec<String>,
+
+    /// Seconds between attacks
+    #[clap(long)]
+    pub tick: Option<u64>,
+
+    /// Seconds after which the exploit is killed
+    #[clap(long)]
+    pub timeout: Option<u64>,
+
+    /// Submit flags line by line over TCP, e.g. to 10.10.0.1:31337
+    #[clap(long, conflicts_with = "submit_http")]
+    pub submit_tcp: Option<String>,
+
+    /// Submit flags as JSON lists over HTTP
+    #[clap(long)]
+    pub submit_http: Option<String>,
+
+    /// X-Team-Token header for --submit-http, stored in .ctfcredentials
+    #[clap(long, requires = "submit_http")]
+    pub token: Option<String>,
+}
+
+#[derive(Parser)]
+pub struct Targets {}
+
+#[derive(Parser)]
+pub struct Run {
+    /// Challenge name
+    pub challenge: String,
+
+    /// Service port, the port of the first challenge service by default
+    #[clap(long)]
+    pub port: Option<u16>,
+
+    /// Attack only once instead of each tick
+    #[clap(long)]
+    pub once: bool,
+
+    /// Number of exploits that run at the same time
+    #[clap(long, default_value = "16", value_parser = clap::value_parser!(u16).range(1..))]
+    pub jobs: u16,
+
+    /// Additional exploit arguments
+    #[clap(last = true)]
+    pub args: Vec<String>,
+}
+
+#[derive(Parser)]
+pub struct Capture {
+    /// Network interface
+    #[clap(long, default_value = "any")]
+    pub interface: String,
+
+    /// Service port, the ports of all challenge services by default
+    #[clap(long)]
+    pub port: Vec<u16>,
+}
+
+#[derive(Parser)]
+pub struct Streams {
+    /// Captures, all of them by default
+    pub captures: Vec<String>,
+
+    /// List only the streams that contain flags
+    #[clap(long)]
+    pub flags: bool,
+}
+
+#[derive(Parser)]
+pub struct Replay {
+    /// Stream ID, e.g. 20261019-120000/3
+    pub stream: String,
+
+    /// Script path, replay-ID.py in the challenge directory by default
+    #[clap(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+/// Captured flags, one per line: time, target, flag and verdict separated by tabs.
+static FLAGS_LOG: &str = "ad-flags.log";
+
+/// Directory with the pcaps, one per tick.
+static CAPTURES_DIR: &str = "captures";
+
+static REPLAY_TEMPLATE: &str = include_str!("ad/replay.py.tmpl");
+
+fn attack_defense(ctf: &ctf::CTF) -> Result<&AttackDefense> {
+    ctf.attack_defense
+        .as_ref()
+        .ok_or_else(|| anyhow!("Not an attack-defense CTF, do ctf ad init first"))
+}
+
+/// Returns the team addresses from the range and the game server, except the excluded ones.
+async fn targets(attack_defense: &AttackDefense) -> Result<Vec<String>> {
+    let mut result = Vec::new();
+    if let Some(teams) = &attack_defense.teams {
+        result.extend(ad::expand_range(teams)?);
+    }
+    if let Some(teams_url) = &attack_defense.teams_url {
+        let client = http::mk_client(&[])?;
+        let response = client.execute(client.get(teams_url).build()?).await?;
+        response.error_for_status_ref()?;
+        result.extend(ad::parse_teams(&response.text().await?)?);
+    }
+    let mut seen = HashSet::new();
+    result.retain(|target| !attack_defense.exclude.contains(target) && seen.insert(target.clone()));
+    Ok(result)
+}
+
+fn service_port(challenge: &ctf::Challenge) -> Option<u16> {
+    challenge
+        .services
+        .iter()
+        .filter_map(|service| url::Url::parse(&service.url).ok())
+        .find_map(|url| url.port_or_known_default())
+}
+
+/// Returns the challenges that own the service ports.
+fn service_ports(ctf: &ctf::CTF) -> BTreeMap<u16, &str> {
+    ctf.challenges
+        .iter()
+        .flat_map(|challenge| {
+            challenge
+                .services
+                .iter()
+                .filter_map(|service| url::Url::parse(&service.url).ok())
+                .filter_map(|url| url.port_or_known_default())
+                .map(move |port| (port, challenge.name.as_str()))
+        })
+        .collect()
+}
+
+/// Returns the captures sorted by time, which is also their name order.
+fn list_captures(captures_dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut result = Vec::new();
+    match fs::read_dir(captures_dir) {
+        Ok(entries) => {
+            for entry in entries {
+                let path = entry?.path();
+                if path
+                    .extension()
+                    .is_some_and(|extension| extension == "pcap")
+                {
+                    result.push(path);
+                }
+            }
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+    result.sort();
+    Ok(result)
+}
+
+fn capture_name(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+fn load_streams(path: &Path, ports: &BTreeMap<u16, &str>) -> Result<Vec<pcap::Stream>> {
+    let segments = pcap::parse(&fs::read(path)?)?;
+    let server_ports: Vec<u16> = ports.keys().copied().collect();
+    Ok(pcap::split_streams(&segments, &server_ports))
+}
+
+fn format_endpoint(endpoint: &(std::net::IpAddr, u16)) -> String {
+    std::net::SocketAddr::from(*endpoint).to_string()
+}
+
+const STREAM_HEADERS: &[&str] = &["ID", "SERVICE", "CLIENT", "BYTES", "FLAGS"];
+
+/// TCP stream as listed by `ctf ad streams`.
+#[derive(Serialize)]
+struct StreamRecord {
+    id: String,
+    service: String,
+    client: String,
+    bytes: usize,
+    /// Whether data is missing from the capture
+    damaged: bool,
+    /// Flags sent by the server, which suggest an attack
+    flags: Vec<String>,
+}
+
+/// Returns a record for each stream in a capture.
+fn stream_records(
+    path: &Path,
+    ports: &BTreeMap<u16, &str>,
+    detector: &flag::Detector,
+    flags_only: bool,
+) -> Result<Vec<StreamRecord>> {
+    let name = capture_name(path);
+    let mut records = Vec::new();
+    for (i, stream) in load_streams(path, ports)?.iter().enumerate() {
+        let flags = detector.find(&String::from_utf8_lossy(&stream.data(false)));
+        if flags_only && flags.is_empty() {
+            continue;
+        }
+        records.push(StreamRecord {
+            id: format!("{}/{}", name, i + 1),
+            service: ports
+                .get(&stream.server.1)
+                .copied()
+                .unwrap_or("?")
+                .to_string(),
+            client: format_endpoint(&stream.client),
+            bytes: stream.messages.iter().map(|(_, data)| data.len()).sum(),
+            damaged: stream.damaged,
+            flags,
+        });
+    }
+    Ok(records)
+}
+
+fn stream_rows(records: &[StreamRecord]) -> Vec<Vec<String>> {
+    records
+        .iter()
+        .map(|record| {
+            vec![
+                record.id.clone(),
+                record.service.clone(),
+                record.client.clone(),
+                match record.damaged {
+                    true => format!("{} (damaged)", record.bytes),
+                    false => record.bytes.to_string(),
+                },
+                record.flags.join(" "),
+            ]
+        })
+        .collect()
+}
+
+fn capture(context: &ctf::Context, capture: Capture, ui: &dyn Ui) -> Result<()> {
+    let ports = service_ports(&context.ctf);
+    let capture_ports: Vec<u16> = match capture.port.is_empty() {
+        true => ports.keys().copied().collect(),
+        false => capture.port,
+    };
+    if capture_ports.is_empty() {
+        bail!("No challenges have services, use --port");
+    }
+    let tick = context
+        .ctf
+        .attack_defense
+        .as_ref()
+        .map(|attack_defense| attack_defense.tick)
+        .unwrap_or_else(ctf::default_tick);
+    let detector = flag::Detector::for_ctf(&context.ctf)?;
+    let captures_dir = context.root.join(CAPTURES_DIR);
+    fs::create_dir_all(&captures_dir)?;
+    let mut summarized: HashSet<PathBuf> = list_captures(&captures_dir)?.into_iter().collect();
+    let filter = capture_ports
+        .iter()
+        .map(|port| format!("port {}", port))
+        .collect::<Vec<_>>()
+        .join(" or ");
+    let mut tcpdump = Command::new("tcpdump")
+        .args([
+            "-i",
+            &capture.interface,
+            "-n",
+            "-U",
+            "-G",
+            &tick.to_string(),
+            "-w",
+        ])
+        .arg(captures_dir.join("%Y%m%d-%H%M%S.pcap"))
+        .arg(format!("tcp and ({})", filter))
+        .spawn()?;
+    loop {
+        thread::sleep(Duration::from_secs(1));
+        let exit_status = tcpdump.try_wait()?;
+        let mut captures = list_captures(&captures_dir)?;
+        /* tcpdump is still writing the newest capture. */
+        if exit_status.is_none() {
+            captures.pop();
+        }
+        for path in captures {
+            if summarized.insert(path.clone()) {
+                match stream_records(&path, &ports, &detector, true) {
+                    Ok(records) if records.is_empty() => {}
+                    Ok(records) => ui.table(STREAM_HEADERS, stream_rows(&records)),
+                    Err(e) => ui.warn(&format!("Could not read {}: {}", path.display(), e)),
+                }
+            }
+        }
+        if let Some(exit_status) = exit_status {
+            if !exit_status.success() {
+                bail!("tcpdump exited with {}", exit_status);
+            }
+            return Ok(());
+        }
+    }
+}
+
+fn streams(context: &ctf::Context, streams: Streams, ui: &dyn Ui, format: Format) -> Result<()> {
+    let ports = service_ports(&context.ctf);
+    let detector = flag::Detector::for_ctf(&context.ctf)?;
+    let captures_dir = context.root.join(CAPTURES_DIR);
+    let paths = match streams.captures.is_empty() {
+        true => list_captures(&captures_dir)?,
+        false => streams
+            .captures
+            .iter()
+            .map(|name| captures_dir.join(format!("{}.pcap", name)))
+            .collect(),
+    };
+    let mut records = Vec::new();
+    for path in paths {
+        records.extend(stream_records(&path, &ports, &detector, streams.flags)?);
+    }
+    format.show(ui, &records, || {
+        ui.table(STREAM_HEADERS, stream_rows(&records));
+        Ok(())
+    })
+}
+
+#[derive(Serialize)]
+struct ReplayParams {
+    stream: String,
+    client: String,
+    host: String,
+    port: u16,
+    steps: Vec<String>,
+}
+
+/// Turns stream messages into pwntools calls. Server messages are awaited by their last few
+/// bytes, which are usually a prompt, and the ones with flags are printed for `ctf ad run`.
+fn replay_steps(stream: &pcap::Stream, detector: &flag::Detector) -> Vec<String> {
+    let mut steps = Vec::new();
+    for (from_client, data) in &stream.messages {
+        if *from_client {
+            steps.push(format!("tube.send({})", python_bytes(data)));
+        } else if detector.find(&String::from_utf8_lossy(data)).is_empty() {
+            let tail = &data[data.len().saturating_sub(16)..];
+            steps.push(format!("tube.recvuntil({})", python_bytes(tail)));
+        } else {
+            steps.push("print(tube.recvrepeat(1).decode(errors=\"replace\"))".into());
+        }
+    }
+    if let Some((true, _)) = stream.messages.last() {
+        steps.push("print(tube.recvall(timeout=1).decode(errors=\"replace\"))".into());
+    }
+    steps
+}
+
+fn replay(context: &ctf::Context, replay: Replay, ui: &dyn Ui) -> Result<()> {
+    let (name, index) = replay
+        .stream
+        .rsplit_once('/')
+        .and_then(|(name, index)| Some((name, index.parse::<usize>().ok()?)))
+        .ok_or_else(|| anyhow!("{} is not a stream ID, see ctf ad streams", replay.stream))?;
+    let ports = service_ports(&context.ctf);
+    let path = context
+        .root
+        .join(CAPTURES_DIR)
+        .join(format!("{}.pcap", name));
+    let streams = load_streams(&path, &ports)?;
+    let stream = index
+        .checked_sub(1)
+        .and_then(|index| streams.get(index))
+        .ok_or_else(|| anyhow!("{} has only {} streams", name, streams.len()))?;
+    if stream.damaged {
+        ui.warn(&format!(
+            "{} misses data from the capture, the script has gaps",
+            replay.stream
+        ));
+    }
+    let output = match replay.output {
+        Some(output) => output,
+        None => {
+            let dir = match ports.get(&stream.server.1) {
+                Some(challenge) => context.root.join(challenge),
+                None => context.cwd.clone(),
+            };
+            dir.join(format!("replay-{}-{}.py", name, index))
+        }
+    };
+    let params = ReplayParams {
+        stream: replay.stream.clone(),
+        client: format_endpoint(&stream.client),
+        host: stream.server.0.to_string(),
+        port: stream.server.1,
+        steps: replay_steps(stream, &flag::Detector::for_ctf(&context.ctf)?),
+    };
+    let mut templates = tinytemplate::TinyTemplate::new();
+    templates.set_default_formatter(&tinytemplate::format_unescaped);
+    templates.add_template("replay", REPLAY_TEMPLATE)?;
+    fs::write(&output, templates.render("replay", &params)?)?;
+    let mut permissions = fs::metadata(&output)?.permissions();
+    permissions.set_mode(permissions.mode() | 0o111);
+    fs::set_permissions(&output, permissions)?;
+    ui.message(&output.display().to_string());
+    Ok(())
+}
+
+/// Returns the flags that were already captured, so that they are not submitted again.
+fn load_flags(path: &Path) -> Result<HashSet<String>> {
+    match fs::read_to_string(path) {
+        Ok(log) => Ok(log
+            .lines()
+            .filter_map(|line| line.split('\t').nth(2))
+            .map(str::to_string)
+            .collect()),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(HashSet::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn format_status(status: &ad::Status) -> String {
+    match status {
+        ad::Status::Ok => "ok".into(),
+        ad::Status::Failed(reason) => format!("failed: {}", reason),
+        ad::Status::Timeout => "timeout".into(),
+    }
+}
+
+async fn run_attacks(context: &ctf::Context, run: Run, ui: &dyn Ui) -> Result<()> {
+    let attack_defense = attack_defense(&context.ctf)?;
+    let challenge = ctf::find_challenge(&context.ctf, &run.challenge)?;
+    let challenge_dir = context.root.join(&challenge.name);
+    let port = match run.port.or_else(|| service_port(challenge)) {
+        Some(port) => port,
+        None => bail!("{} has no services, use --port", challenge.name),
+    };
+    /* Fail early if there is no exploit. */
+    exploit_command(&challenge_dir)?;
+    let detector = flag::Detector::for_ctf(&context.ctf)?;
+    let submitter = attack_defense
+        .submission
+        .as_ref()
+        .map(|submission| ad::submitter(submission, &context.credentials));
+    let log_path = challenge_dir.join(FLAGS_LOG);
+    let mut seen = load_flags(&log_path)?;
+    /* Flags whose submission failed are retried on the next tick. */
+    let mut pending: Vec<(String, String)> = Vec::new();
+    loop {
+        let started = Instant::now();
+        let targets = match targets(attack_defense).await {
+            Ok(targets) => targets,
+            Err(e) => {
+                ui.warn(&format!("Could not get the targets: {}", e));
+                Vec::new()
+            }
+        };
+        ui.message(&format!(
+            "Attacking {} targets on port {}...",
+            targets.len(),
+            port
+        ));
+        let attacks = ad::attack_all(
+            &targets,
+            |target| {
+                let (mut command, _) = exploit_command(&challenge_dir)?;
+                command
+                    .arg(format!("HOST={}", target))
+                    .arg(format!("PORT={}", port))
+                    .args(&run.args);
+                Ok(command)
+            },
+            Duration::from_secs(attack_defense.timeout),
+            &detector,
+            run.jobs.into(),
+        );
+        let mut rows = Vec::new();
+        for attack in attacks {
+            let mut new = 0;
+            for flag in attack.flags {
+                if seen.insert(flag.clone()) {
+                    pending.push((attack.target.clone(), flag));
+                    new += 1;
+                }
+            }
+            rows.push(vec![
+                attack.target,
+                new.to_string(),
+                format_status(&attack.status),
+            ]);
+        }
+        ui.table(&["TARGET", "FLAGS", "STATUS"], rows);
+        let mut log = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&log_path)?;
+        let time = OffsetDateTime::now_utc().format(&Rfc3339)?;
+        let mut verdicts: BTreeMap<String, usize> = BTreeMap::new();
+        let mut failed = Vec::new();
+        for batch in pending.chunks(ad::BATCH_SIZE) {
+            let flags: Vec<String> = batch.iter().map(|(_, flag)| flag.clone()).collect();
+            let batch_verdicts = match &submitter {
+                Some(submitter) => match submitter.submit(&flags).await {
+                    Ok(batch_verdicts) => batch_verdicts,
+                    Err(e) => {
+                        ui.warn(&format!("Could not submit {} flags: {}", flags.len(), e));
+                        failed.extend_from_slice(batch);
+                        continue;
+                    }
+                },
+                None => vec!["not submitted".into(); flags.len()],
+            };
+            for ((target, flag), verdict) in batch.iter().zip(batch_verdicts) {
+                writeln!(log, "{}\t{}\t{}\t{}", time, target, flag, verdict)?;
+                *verdicts.entry(verdict).or_default() += 1;
+            }
+        }
+        pending = failed;
+        for (verdict, count) in verdicts {
+            ui.message(&format!("{}: {}", verdict, count));
+        }
+        if run.once {
+            break;
+        }
+        let tick = Duration::from_secs(attack_defense.tick);
+        thread::sleep(tick.saturating_sub(started.elapsed()));
+    }
+    Ok(())
+}
+
+pub async fn run(ad: Ad, current_dir: PathBuf, ui: &dyn Ui, format: Format) -> Result<()> {
+    let mut context = ctf::load(current_dir)?;
+    match ad.subcmd {
+        SubCommand::Init(init) => {
+            let attack_defense = context
+                .ctf
+                .attack_defense
+                .get_or_insert_with(|| AttackDefense {
+                    tick: ctf::default_tick(),
+                    timeout: ctf::default_timeout(),
+                    ..AttackDefense::default()
+                });
+            if let Some(teams) = init.teams {
+                ad::expand_range(&teams)?;
+                attack_defense.teams = Some(teams);
+            }
+            if init.teams_url.is_some() {
+                attack_defense.teams_url = init.teams_url;
+            }
+            for address in init.exclude {
+                if !attack_defense.exclude.contains(&address) {
+                    attack_defense.exclude.push(address);
+                }
+            }
+            if let Some(tick) = init.tick {
+                attack_defense.tick = tick;
+            }
+            if let Some(timeout) = init.timeout {
+                attack_defense.timeout = timeout;
+            }
+            if let Some(address) = init.submit_tcp {
+                attack_defense.submission = Some(FlagSubmission::Tcp { address });
+            }
+            if let Some(url) = init.submit_http {
+                attack_defense.submission = Some(FlagSubmission::Http { url });
+            }
+            /* The token would leak to everyone who can see the repository. */
+            if init.token.is_some() {
+                context.credentials.team_token = init.token;
+            }
+            git::commit(&context, "Configure attack-defense")?;
+        }
+        SubCommand::Targets(_targets) => {
+            let targets = targets(attack_defense(&context.ctf)?).await?;
+            format.show(ui, &targets, || {
+                for target in &targets {
+                    ui.message(target);
+                }
+                Ok(())
+            })?;
+        }
+        SubCommand::Run(run) => run_attacks(&context, run, ui).await?,
+        SubCommand::Capture(capture_args) => capture(&context, capture_args, ui)?,
+        SubCommand::Streams(streams_args) => streams(&context, streams_args, ui, format)?,
+        SubCommand::Replay(replay_args) => replay(&context, replay_args, ui)?,
+    }
+    Ok(())
+}
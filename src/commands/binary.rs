@@ -1,16 +1,22 @@
 use clap::Parser;
-use console::style;
 use std::fmt::{Display, Formatter};
 
 use anyhow::{anyhow, bail, Result};
 
+use crate::asm::parse_address;
 use crate::ctf;
 use crate::ctf::{resolve_challenge_mut, BinaryAlternative, Challenge, CTF};
+use crate::error::Error;
 use crate::git;
 use crate::option;
-use crate::path::path_to_str;
+use crate::patch;
+use crate::patchelf;
+use crate::path::{path_to_str, relativize};
+use crate::ui::Format;
+use crate::{Ui, Workspace};
 use std::fs;
-use std::path::{Path, PathBuf};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Component, Path, PathBuf};
 
 #[derive(Parser)]
 pub struct Binary {
@@ -35,6 +41,21 @@ enum SubCommand {
     /// Makes a registered binary a default one
     #[clap(name = "default")]
     Default(Default),
+
+    /// Makes a binary use the challenge loader and libc
+    #[clap(name = "patchelf")]
+    Patchelf(Patchelf),
+
+    /// Patches bytes of a binary
+    #[clap(name = "patch")]
+    Patch(Patch),
+}
+
+impl Binary {
+    /// Whether the subcommand prints records, which `--format` applies to.
+    pub fn prints_records(&self) -> bool {
+        matches!(self.subcmd, SubCommand::Show(_))
+    }
 }
 
 #[derive(Parser)]
@@ -60,6 +81,50 @@ pub struct Default {
     pub name: String,
 }
 
+#[derive(Parser)]
+pub struct Patchelf {
+    /// Name
+    pub name: String,
+
+    /// Loader, detected among the challenge files by default
+    #[clap(long)]
+    pub ld: Option<String>,
+
+    /// libc, detected among the challenge files by default
+    #[clap(long)]
+    pub libc: Option<String>,
+
+    /// Name of the patched alternative
+    #[clap(long, default_value = "patched")]
+    pub alternative: String,
+}
+
+#[derive(Parser)]
+pub struct Patch {
+    /// Name; `binary` means its default alternative
+    pub name: String,
+
+    /// Virtual address for ELF files, file offset otherwise
+    #[clap(long, value_parser = parse_address)]
+    pub at: u64,
+
+    /// Treat --at as a file offset
+    #[clap(long)]
+    pub file_offset: bool,
+
+    /// Hex-encoded bytes
+    #[clap(long, required_unless_present = "asm", conflicts_with = "asm")]
+    pub bytes: Option<String>,
+
+    /// x86 instructions separated by `;`, e.g., `nop; jmp 0x401234`
+    #[clap(long)]
+    pub asm: Option<String>,
+
+    /// Name of the patched alternative
+    #[clap(long, default_value = "bytepatched")]
+    pub alternative: String,
+}
+
 fn split(name: &str) -> Result<(&str, &str)> {
     match name.rfind('.') {
         Some(pos) => Ok((&name[..pos], &name[pos + 1..])),
@@ -85,6 +150,40 @@ fn resolve<'a>(
     Ok((challenge, binary_name, binary_path))
 }
 
+/// Returns what `binary patch binary` patches: the default alternative, so that the bytes go on
+/// top of, e.g., `binary patchelf`, unless the patched alternative is the default one already.
+fn patch_base(binary: &ctf::Binary, alternative: &str) -> String {
+    match &binary.default_alternative {
+        Some(default) if default != alternative => default.clone(),
+        _ => ctf::find_alternative(binary, alternative)
+            .ok()
+            .and_then(|alternative| alternative.base.clone())
+            .unwrap_or_else(|| "orig".to_string()),
+    }
+}
+
+/// Resolves a library specified on the command line relative to a challenge directory.
+fn resolve_library(root: &Path, cwd: &Path, challenge_dir: &Path, s: &str) -> Result<PathBuf> {
+    let (canonical_path, _) = relativize(root, cwd, PathBuf::from(s))?;
+    Ok(canonical_path
+        .strip_prefix(challenge_dir.canonicalize()?)
+        .map_err(|_| anyhow!("{} is not in the challenge directory", s))?
+        .to_path_buf())
+}
+
+/// Computes a RUNPATH that points from a binary to a directory, both relative to a challenge
+/// directory.
+fn runpath(binary: &Path, dir: &Path) -> Result<String> {
+    let mut result = PathBuf::from("$ORIGIN");
+    if let Some(parent) = binary.parent() {
+        for _ in parent.components() {
+            result.push(Component::ParentDir);
+        }
+    }
+    result.push(dir);
+    Ok(path_to_str(&result)?.trim_end_matches('/').to_string())
+}
+
 struct FullBinaryAlternativeName {
     challenge_name: String,
     binary_name: String,
@@ -125,6 +224,8 @@ fn try_add_alternative(challenge: &mut Challenge, name: &str) -> Option<FullBina
                             name: alternative_name.to_owned(),
                             url: None,
                             checksum: None,
+                            base: None,
+                            patches: Vec::new(),
                         });
                         binary.alternatives.last_mut().unwrap().name.to_string()
                     }),
@@ -157,7 +258,7 @@ fn alternatives_to_string(mut s: String, alternatives: &[FullBinaryAlternativeNa
     s
 }
 
-pub async fn run(binary: Binary, current_dir: PathBuf) -> Result<()> {
+pub async fn run(binary: Binary, current_dir: PathBuf, ui: &dyn Ui, format: Format) -> Result<()> {
     let mut context = ctf::load(current_dir)?;
     match binary.subcmd {
         SubCommand::Show(_) => {
@@ -166,22 +267,31 @@ pub async fn run(binary: Binary, current_dir: PathBuf) -> Result<()> {
                 .first()
                 .ok_or_else(|| anyhow!("Not in a challenge directory"))?;
             let challenge = ctf::find_challenge(&context.ctf, challenge_name)?;
-            for binary in &challenge.binaries {
-                for alternative in &binary.alternatives {
-                    let mut text = style(format!("{}.{}", binary.name, alternative.name));
-                    if option::contains(&binary.default_alternative, &alternative.name) {
-                        text = text.bold();
+            format.show(ui, &challenge.binaries, || {
+                let mut rows = Vec::new();
+                for binary in &challenge.binaries {
+                    for alternative in &binary.alternatives {
+                        let default =
+                            option::contains(&binary.default_alternative, &alternative.name);
+                        rows.push(vec![
+                            format!("{}.{}", binary.name, alternative.name),
+                            if default { "yes" } else { "" }.to_string(),
+                            alternative.base.clone().unwrap_or_default(),
+                        ]);
                     }
-                    println!("{}", text);
                 }
-            }
+                ui.table(&["NAME", "DEFAULT", "BASE"], rows);
+                Ok(())
+            })?;
         }
         SubCommand::Add(add) => {
             let mut alternatives = vec![];
             let mut todo_copy = vec![];
+            let mut paths = vec![];
             for name in &add.names {
                 let (challenge, name, path) =
                     resolve(&mut context.ctf, &context.root, &context.cwd, name)?;
+                paths.push(path.clone());
                 alternatives.push(try_add_alternative(challenge, &name).unwrap_or_else(|| {
                     let mut orig = path.as_os_str().to_os_string();
                     orig.push(".orig");
@@ -192,6 +302,8 @@ pub async fn run(binary: Binary, current_dir: PathBuf) -> Result<()> {
                             name: "orig".to_string(),
                             url: None,
                             checksum: None,
+                            base: None,
+                            patches: Vec::new(),
                         }],
                         default_alternative: Some("orig".to_string()),
                     });
@@ -205,16 +317,19 @@ pub async fn run(binary: Binary, current_dir: PathBuf) -> Result<()> {
                 }))
             }
             for (src, dst) in todo_copy {
-                fs::copy(src, dst)?;
+                fs::copy(src, &dst)?;
+                paths.push(PathBuf::from(dst));
             }
-            git::commit(
+            git::commit_files(
                 &context,
                 &alternatives_to_string("Add ".to_string(), &alternatives),
+                &paths,
             )?;
         }
         SubCommand::Rm(rm) => {
             let mut alternatives = vec![];
             let mut todo_rm = vec![];
+            let mut removed = vec![];
             for name in rm.names {
                 let (challenge, s, _) =
                     resolve(&mut context.ctf, &context.root, &context.cwd, &name)?;
@@ -229,11 +344,11 @@ pub async fn run(binary: Binary, current_dir: PathBuf) -> Result<()> {
                 }
                 if option::contains(&binary.default_alternative, &alternative_name) {
                     binary.default_alternative = None;
-                    fs::remove_file(ctf::default_alternative_path(
+                    todo_rm.push(ctf::default_alternative_path(
                         &context.root,
                         &challenge.name,
                         binary_name,
-                    ))?;
+                    ));
                 }
                 todo_rm.push(ctf::alternative_path(
                     &context.root,
@@ -252,12 +367,28 @@ pub async fn run(binary: Binary, current_dir: PathBuf) -> Result<()> {
                     alternative_name: alternative_name.to_string(),
                 })
             }
+            /* Patched alternatives cannot be rebuilt without their bases, unless they go too. */
+            for alternative in &alternatives {
+                let challenge = ctf::find_challenge(&context.ctf, &alternative.challenge_name)?;
+                let binary = match ctf::find_binary(challenge, &alternative.binary_name) {
+                    Ok(binary) => binary,
+                    Err(_) => continue,
+                };
+                if let Some(patched) =
+                    patch::dependents(binary, &alternative.alternative_name).next()
+                {
+                    let patched = format!("{}/{}.{}", challenge.name, binary.name, patched);
+                    return Err(Error::BaseOfPatched(alternative.to_string(), patched).into());
+                }
+            }
             for path in todo_rm {
-                fs::remove_file(path)?;
+                fs::remove_file(&path)?;
+                removed.push(path);
             }
-            git::commit(
+            git::commit_files(
                 &context,
                 &alternatives_to_string("Remove ".to_string(), &alternatives),
+                &removed,
             )?;
         }
         SubCommand::Default(default) => {
@@ -265,10 +396,178 @@ pub async fn run(binary: Binary, current_dir: PathBuf) -> Result<()> {
                 resolve(&mut context.ctf, &context.root, &context.cwd, &default.name)?;
             let (binary_name, alternative_name) = split(&s)?;
             let challenge_name = challenge.name.to_owned();
-            let binary = ctf::find_binary_mut(&mut challenge.binaries, binary_name)?;
-            ctf::find_alternative_mut(binary, alternative_name)?;
-            ctf::set_default_alternative(&context.root, &challenge_name, binary, alternative_name)?;
-            git::commit(&context, &format!("Select binary {}", default.name))?;
+            Workspace::from(context).set_default_alternative(
+                &challenge_name,
+                binary_name,
+                alternative_name,
+            )?;
+        }
+        SubCommand::Patchelf(patchelf) => {
+            let (challenge, s, path) = resolve(
+                &mut context.ctf,
+                &context.root,
+                &context.cwd,
+                &patchelf.name,
+            )?;
+            let challenge_name = challenge.name.to_owned();
+            let challenge_dir = context.root.join(&challenge_name);
+            let binary_name = if ctf::try_find_binary_mut(&mut challenge.binaries, &s).is_some() {
+                s
+            } else {
+                split(&s)?.0.to_string()
+            };
+            let ld = match &patchelf.ld {
+                Some(ld) => resolve_library(&context.root, &context.cwd, &challenge_dir, ld)?,
+                None => patchelf::find_library(&challenge_dir, &patchelf::LD_REGEX, "ld")?,
+            };
+            let libc = match &patchelf.libc {
+                Some(libc) => resolve_library(&context.root, &context.cwd, &challenge_dir, libc)?,
+                None => patchelf::find_library(&challenge_dir, &patchelf::LIBC_REGEX, "libc")?,
+            };
+            let interpreter = format!("./{}", path_to_str(&ld)?);
+            let runpath = runpath(
+                Path::new(&binary_name),
+                libc.parent().unwrap_or_else(|| Path::new("")),
+            )?;
+            let bytes = patchelf::patch(&fs::read(&path)?, Some(&interpreter), Some(&runpath))?;
+            let binary = ctf::find_binary_mut(&mut challenge.binaries, &binary_name)?;
+            if ctf::try_find_alternative_mut(binary, &patchelf.alternative).is_none() {
+                binary.alternatives.push(BinaryAlternative {
+                    name: patchelf.alternative.to_owned(),
+                    url: None,
+                    checksum: None,
+                    base: None,
+                    patches: Vec::new(),
+                });
+            }
+            let alternative_path = ctf::alternative_path(
+                &context.root,
+                &challenge_name,
+                &binary_name,
+                &patchelf.alternative,
+            );
+            fs::write(&alternative_path, bytes)?;
+            let mut permissions = fs::metadata(&alternative_path)?.permissions();
+            permissions.set_mode(permissions.mode() | 0o111);
+            fs::set_permissions(&alternative_path, permissions)?;
+            ctf::set_default_alternative(
+                &context.root,
+                &challenge_name,
+                binary,
+                &patchelf.alternative,
+            )?;
+            let paths = [
+                alternative_path,
+                ctf::default_alternative_path(&context.root, &challenge_name, &binary_name),
+            ];
+            let alternative = FullBinaryAlternativeName {
+                challenge_name,
+                binary_name,
+                alternative_name: patchelf.alternative,
+            };
+            git::commit_files(
+                &context,
+                &format!(
+                    "Patch {} to use {} and {}",
+                    alternative,
+                    path_to_str(&ld)?,
+                    path_to_str(&libc)?
+                ),
+                &paths,
+            )?;
+        }
+        SubCommand::Patch(patch) => {
+            let (challenge, s, _) =
+                resolve(&mut context.ctf, &context.root, &context.cwd, &patch.name)?;
+            let challenge_name = challenge.name.to_owned();
+            let (binary_name, base_name) =
+                match ctf::try_find_binary_mut(&mut challenge.binaries, &s) {
+                    Some(binary) => {
+                        let base_name = patch_base(binary, &patch.alternative);
+                        (s, base_name)
+                    }
+                    None => {
+                        let (binary_name, base_name) = split(&s)?;
+                        (binary_name.to_string(), base_name.to_string())
+                    }
+                };
+            if base_name == patch.alternative {
+                bail!(
+                    "Cannot patch {}.{} in place, use --alternative",
+                    binary_name,
+                    base_name
+                );
+            }
+            let binary = ctf::find_binary_mut(&mut challenge.binaries, &binary_name)?;
+            ctf::find_alternative_mut(binary, &base_name)?;
+            let base_bytes = fs::read(ctf::alternative_path(
+                &context.root,
+                &challenge_name,
+                &binary_name,
+                &base_name,
+            ))?;
+            let location = patch::locate(&base_bytes, patch.at, patch.file_offset)?;
+            let bytes = match (&patch.bytes, &patch.asm) {
+                (Some(bytes), _) => hex::decode(bytes.replace(char::is_whitespace, ""))?,
+                (None, Some(asm)) => patch::assemble(&base_bytes, &location, asm)?,
+                (None, None) => bail!("Either --bytes or --asm must be specified"),
+            };
+            let byte_patch = ctf::BytePatch {
+                offset: location.offset,
+                bytes: hex::encode(bytes),
+            };
+            match ctf::try_find_alternative_mut(binary, &patch.alternative) {
+                Some(alternative) => {
+                    if !option::contains(&alternative.base, &base_name) {
+                        bail!(
+                            "Alternative {}.{} is not based on {}.{}",
+                            binary_name,
+                            patch.alternative,
+                            binary_name,
+                            base_name
+                        );
+                    }
+                    alternative.patches.push(byte_patch);
+                }
+                None => binary.alternatives.push(BinaryAlternative {
+                    name: patch.alternative.to_owned(),
+                    url: None,
+                    checksum: None,
+                    base: Some(base_name),
+                    patches: vec![byte_patch],
+                }),
+            }
+            patch::rebuild(
+                &context.root,
+                &challenge_name,
+                binary,
+                ctf::find_alternative(binary, &patch.alternative)?,
+            )?;
+            ctf::set_default_alternative(
+                &context.root,
+                &challenge_name,
+                binary,
+                &patch.alternative,
+            )?;
+            let paths = [
+                ctf::alternative_path(
+                    &context.root,
+                    &challenge_name,
+                    &binary_name,
+                    &patch.alternative,
+                ),
+                ctf::default_alternative_path(&context.root, &challenge_name, &binary_name),
+            ];
+            let alternative = FullBinaryAlternativeName {
+                challenge_name,
+                binary_name,
+                alternative_name: patch.alternative,
+            };
+            git::commit_files(
+                &context,
+                &format!("Patch {} at 0x{:x}", alternative, patch.at),
+                &paths,
+            )?;
         }
     }
     Ok(())
@@ -1,10 +1,14 @@
-use anyhow::{bail, Result};
+use anyhow::{anyhow, bail, Result};
 use clap::Parser;
 
 use crate::path::path_to_str;
 use crate::path::relativize;
-use crate::{ctf, git};
+use crate::ui::Format;
+use crate::{ctf, flag, git, Ui, Workspace};
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
 
 #[derive(Parser)]
 pub struct Challenge {
@@ -29,6 +33,25 @@ enum SubCommand {
     /// Removes an existing challenge
     #[clap(name = "rm")]
     Rm(Rm),
+
+    /// Marks a challenge as being worked on by the git author
+    #[clap(name = "claim")]
+    Claim(Claim),
+
+    /// Sets a challenge status: todo, wip, solved or abandoned
+    #[clap(name = "status")]
+    Status(Status),
+
+    /// Adds a note to a challenge
+    #[clap(name = "note")]
+    Note(Note),
+}
+
+impl Challenge {
+    /// Whether the subcommand prints records, which `--format` applies to.
+    pub fn prints_records(&self) -> bool {
+        matches!(self.subcmd, SubCommand::Show(_))
+    }
 }
 
 #[derive(Parser)]
@@ -55,64 +78,186 @@ pub struct Rm {
     pub name: String,
 }
 
+#[derive(Parser)]
+pub struct Claim {
+    /// Challenge name, the current challenge by default
+    pub name: Option<String>,
+
+    /// Stop working on the challenge instead
+    #[clap(long)]
+    pub release: bool,
+}
+
+#[derive(Parser)]
+pub struct Status {
+    /// New status
+    pub status: ctf::Status,
+
+    /// Challenge name, the current challenge by default
+    pub name: Option<String>,
+}
+
+#[derive(Parser)]
+pub struct Note {
+    /// Note text
+    pub text: String,
+
+    /// Challenge name, the current challenge by default
+    pub name: Option<String>,
+}
+
 /// Resolve challenge name
 fn resolve(root: &Path, cwd: &Path, s: String) -> Result<String> {
     let (_, relative_path) = relativize(root, cwd, PathBuf::from(s))?;
     path_to_str(&relative_path).map(|s| s.into())
 }
 
-pub fn run(challenge: Challenge, current_dir: PathBuf) -> Result<()> {
+/// Resolve challenge name, or use the current challenge if it is not specified
+fn resolve_or_current(context: &ctf::Context, cwd: &Path, s: Option<String>) -> Result<String> {
+    match s {
+        Some(s) => resolve(&context.root, cwd, s),
+        None => context
+            .path
+            .first()
+            .cloned()
+            .ok_or_else(|| anyhow!("Not in a challenge directory")),
+    }
+}
+
+fn sign(context: &ctf::Context) -> Result<ctf::Signature> {
+    Ok(ctf::Signature {
+        author: git::author(&context.root)?,
+        time: OffsetDateTime::now_utc().format(&Rfc3339)?,
+    })
+}
+
+fn set_status(challenge: &mut ctf::Challenge, value: ctf::Status, signature: ctf::Signature) {
+    challenge.status = Some(ctf::StatusChange { value, signature });
+}
+
+/// Indents all lines but the first one, so that multi-line text does not break the layout.
+fn indent(text: &str, indent: &str) -> String {
+    text.trim_end()
+        .lines()
+        .collect::<Vec<_>>()
+        .join(&format!("\n{}", indent))
+}
+
+/// Prints the challenges grouped by status, and then by the people working on them.
+fn show(ctf: &ctf::CTF, ui: &dyn Ui) -> Result<()> {
+    let detector = flag::Detector::for_ctf(ctf)?;
+    let mut board: BTreeMap<(ctf::Status, Vec<&str>), Vec<&ctf::Challenge>> = BTreeMap::new();
+    for challenge in &ctf.challenges {
+        let status = challenge
+            .status
+            .as_ref()
+            .map(|status| status.value)
+            .unwrap_or(ctf::Status::Todo);
+        let mut owners: Vec<&str> = challenge.claims.iter().map(|claim| claim.name()).collect();
+        owners.sort();
+        board.entry((status, owners)).or_default().push(challenge);
+    }
+    let mut last_status = None;
+    for ((status, owners), challenges) in board {
+        if last_status != Some(status) {
+            ui.message(&format!("{}:", status.name()));
+            last_status = Some(status);
+        }
+        ui.message(&format!(
+            "  {}:",
+            match owners.is_empty() {
+                true => "nobody".to_string(),
+                false => owners.join(", "),
+            }
+        ));
+        for challenge in challenges {
+            ui.message(&format!(
+                "    {} - {}",
+                challenge.name,
+                indent(&detector.highlight(&challenge.description), "      ")
+            ));
+            for note in &challenge.notes {
+                ui.message(&format!(
+                    "      {} {}: {}",
+                    note.signature.time,
+                    note.signature.name(),
+                    indent(&detector.highlight(&note.text), "        ")
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn run(challenge: Challenge, current_dir: PathBuf, ui: &dyn Ui, format: Format) -> Result<()> {
     let mut context = ctf::load(current_dir.clone())?;
     match challenge.subcmd {
         SubCommand::Show(_show) => {
-            for challenge in context.ctf.challenges {
-                println!("{} - {}", challenge.name, challenge.description)
-            }
+            format.show(ui, &context.ctf.challenges, || show(&context.ctf, ui))?
         }
         SubCommand::Add(add) => {
             let name = resolve(&context.root, &current_dir, add.name)?;
-            let existing = context
-                .ctf
-                .challenges
-                .iter()
-                .find(|challenge| challenge.name == name);
-            if existing.is_some() {
-                bail!("Challenge {} already exists", &name);
-            }
             let challenge_dir = context.root.join(&name);
             if !challenge_dir.exists() {
                 bail!("Directory {} does not exist", challenge_dir.display());
             }
-            let message = format!("Add challenge {}", name);
-            context.ctf.challenges.push(ctf::Challenge {
-                name,
-                description: "".to_string(),
-                binaries: Vec::new(),
-                services: Vec::new(),
-            });
-            git::commit(&context, &message)?;
+            Workspace::from(context).add_challenge(&name)?;
         }
         SubCommand::SetDescription(set_description) => {
             let name = resolve(&context.root, &current_dir, set_description.name)?;
-            let message = format!(
-                "Set challenge {} description to {}",
-                name, set_description.description
-            );
-            let challenge = ctf::find_challenge_mut(&mut context.ctf, &name)?;
-            challenge.description = set_description.description;
-            git::commit(&context, &message)?;
+            Workspace::from(context).set_description(&name, &set_description.description)?;
         }
         SubCommand::Rm(rm) => {
             let name = resolve(&context.root, &current_dir, rm.name)?;
-            let message = format!("Remove challenge {}", name);
-            let n_challenges = context.ctf.challenges.len();
-            context
-                .ctf
-                .challenges
-                .retain(|challenge| challenge.name != name);
-            if context.ctf.challenges.len() + 1 != n_challenges {
-                bail!("Challenge {} does not exist", &name);
-            }
+            Workspace::from(context).remove_challenge(&name)?;
+        }
+        SubCommand::Claim(claim) => {
+            let name = resolve_or_current(&context, &current_dir, claim.name)?;
+            let signature = sign(&context)?;
+            let challenge = ctf::find_challenge_mut(&mut context.ctf, &name)?;
+            let claimed = challenge
+                .claims
+                .iter()
+                .any(|existing| existing.author == signature.author);
+            let message = if claim.release {
+                if !claimed {
+                    bail!("{} did not claim challenge {}", signature.name(), name);
+                }
+                challenge
+                    .claims
+                    .retain(|existing| existing.author != signature.author);
+                format!("Release challenge {}", name)
+            } else {
+                if claimed {
+                    bail!("{} already claimed challenge {}", signature.name(), name);
+                }
+                challenge.claims.push(signature.clone());
+                /* Claiming means starting to work on it. */
+                let status = challenge.status.as_ref().map(|status| status.value);
+                if matches!(status, None | Some(ctf::Status::Todo)) {
+                    set_status(challenge, ctf::Status::Wip, signature);
+                }
+                format!("Claim challenge {}", name)
+            };
+            git::commit(&context, &message)?;
+        }
+        SubCommand::Status(status) => {
+            let name = resolve_or_current(&context, &current_dir, status.name)?;
+            let message = format!("Set challenge {} status to {}", name, status.status.name());
+            let signature = sign(&context)?;
+            let challenge = ctf::find_challenge_mut(&mut context.ctf, &name)?;
+            set_status(challenge, status.status, signature);
+            git::commit(&context, &message)?;
+        }
+        SubCommand::Note(note) => {
+            let name = resolve_or_current(&context, &current_dir, note.name)?;
+            let message = format!("Add note to challenge {}", name);
+            let signature = sign(&context)?;
+            let challenge = ctf::find_challenge_mut(&mut context.ctf, &name)?;
+            challenge.notes.push(ctf::Note {
+                text: note.text,
+                signature,
+            });
             git::commit(&context, &message)?;
         }
     }
@@ -1,12 +1,10 @@
 use std::future::Future;
 use std::path::{Path, PathBuf};
 use std::pin::Pin;
-use std::sync::Arc;
 
 use clap::Parser;
 use futures::future::{join_all, FutureExt};
 use futures::TryFutureExt;
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use sha2::{Digest, Sha256};
 use tokio::fs::{create_dir_all, rename, File};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
@@ -15,6 +13,8 @@ use anyhow::{anyhow, bail, Result};
 
 use crate::ctf;
 use crate::git;
+use crate::patch;
+use crate::ui::{Progress, Ui};
 
 #[derive(Parser)]
 pub struct Checkout {
@@ -22,7 +22,7 @@ pub struct Checkout {
     specs: Vec<String>,
 }
 
-async fn hexdigest_1<H>(path: &Path, progress_bar: &ProgressBar) -> Result<String>
+async fn hexdigest_1<H>(path: &Path, progress: &dyn Progress) -> Result<String>
 where
     H: Digest,
 {
@@ -30,50 +30,41 @@ where
     let mut buf = [0_u8; 8192];
     let mut file = File::open(path).await?;
     let meta = file.metadata().await?;
-    progress_bar.set_length(meta.len());
+    progress.set_length(meta.len());
     loop {
         let n = file.read(&mut buf).await?;
         if n == 0 {
             break Ok(hex::encode(hash.finalize()));
         }
         hash.update(&buf[0..n]);
-        progress_bar.inc(n as u64);
+        progress.inc(n as u64);
     }
 }
 
-fn mk_progress_bar() -> Result<ProgressBar> {
-    Ok(ProgressBar::with_style(
-        ProgressBar::hidden(),
-        ProgressStyle::default_bar().template("{wide_msg} {bytes}/{total_bytes}")?,
-    ))
-}
-
-async fn hexdigest(path: &Path, algorithm: &str, progress: &MultiProgress) -> Result<String> {
-    let progress_bar = mk_progress_bar()?;
-    progress_bar.set_message(format!("{} {}", algorithm.to_uppercase(), path.display()));
-    let progress_bar = progress.add(progress_bar);
+async fn hexdigest(path: &Path, algorithm: &str, ui: &dyn Ui) -> Result<String> {
+    let progress = ui.progress(format!("{} {}", algorithm.to_uppercase(), path.display()));
     let result = match algorithm {
-        "sha256" => hexdigest_1::<Sha256>(path, &progress_bar).await,
+        "sha256" => hexdigest_1::<Sha256>(path, progress.as_ref()).await,
         _ => Err(anyhow!("Unsupported checksum algorithm: {}", algorithm)),
     };
     if let Err(e) = &result {
-        progress_bar.finish_with_message(format!(
+        progress.finish(Some(format!(
             "ERROR {} {}: {}",
             algorithm.to_uppercase(),
             path.display(),
             e,
-        ));
+        )));
     } else {
-        progress_bar.finish_and_clear();
+        progress.finish(None);
     }
     result
 }
 
-async fn download_1(path: &Path, url: &str, progress_bar: &ProgressBar) -> Result<()> {
+async fn download_1(path: &Path, url: &str, progress: &dyn Progress) -> Result<()> {
     let mut response = reqwest::get(url).await?;
     response.error_for_status_ref()?;
     if let Some(content_length) = response.content_length() {
-        progress_bar.set_length(content_length);
+        progress.set_length(content_length);
     }
     if let Some(parent) = path.parent() {
         create_dir_all(parent).await?;
@@ -83,22 +74,20 @@ async fn download_1(path: &Path, url: &str, progress_bar: &ProgressBar) -> Resul
         let chunk = response.chunk().await?;
         if let Some(chunk) = chunk {
             file.write_all(&chunk).await?;
-            progress_bar.inc(chunk.len() as u64);
+            progress.inc(chunk.len() as u64);
         } else {
             break Ok(());
         }
     }
 }
 
-async fn download(path: &Path, url: &str, progress: &MultiProgress) -> Result<()> {
-    let progress_bar = mk_progress_bar()?;
-    progress_bar.set_message(format!("DOWNLOAD {}", url));
-    let progress_bar = progress.add(progress_bar);
-    let result = download_1(path, url, &progress_bar).await;
+async fn download(path: &Path, url: &str, ui: &dyn Ui) -> Result<()> {
+    let progress = ui.progress(format!("DOWNLOAD {}", url));
+    let result = download_1(path, url, progress.as_ref()).await;
     if let Err(e) = &result {
-        progress_bar.finish_with_message(format!("ERROR DOWNLOAD {}: {}", url, e));
+        progress.finish(Some(format!("ERROR DOWNLOAD {}: {}", url, e)));
     } else {
-        progress_bar.finish_and_clear();
+        progress.finish(None);
     }
     result
 }
@@ -106,10 +95,10 @@ async fn download(path: &Path, url: &str, progress: &MultiProgress) -> Result<()
 async fn checkout_alternative(
     alternative: &ctf::BinaryAlternative,
     path: PathBuf,
-    progress: &MultiProgress,
+    ui: &dyn Ui,
 ) -> Result<Option<ctf::Checksum>> {
     if let Some(checksum) = &alternative.checksum {
-        match hexdigest(&path, &checksum.algorithm, progress).await {
+        match hexdigest(&path, &checksum.algorithm, ui).await {
             Ok(value) if value == checksum.value => return Ok(None),
             Ok(_) => {}
             Err(_) => {}
@@ -117,18 +106,18 @@ async fn checkout_alternative(
     }
     let tmp_path = path.clone();
     match &alternative.url {
-        Some(url) => download(&tmp_path, url, progress).await?,
+        Some(url) => download(&tmp_path, url, ui).await?,
         None => return Ok(None),
     }
     let result = if let Some(checksum) = &alternative.checksum {
-        let value = hexdigest(&path, &checksum.algorithm, progress).await?;
+        let value = hexdigest(&path, &checksum.algorithm, ui).await?;
         if value != checksum.value {
             bail!("Checksum error");
         }
         None
     } else {
         let algorithm = "sha256".to_string();
-        let value = hexdigest(&path, &algorithm, progress).await?;
+        let value = hexdigest(&path, &algorithm, ui).await?;
         Some(ctf::Checksum { algorithm, value })
     };
     rename(tmp_path, path).await?;
@@ -146,7 +135,7 @@ type CheckoutFuture<'a> = Pin<Box<dyn Future<Output = Result<Option<ctf::Checksu
 fn checkout_challenge<'a>(
     context: &'a ctf::Context,
     challenge: &'a ctf::Challenge,
-    progress: &'a MultiProgress,
+    ui: &'a dyn Ui,
 ) -> Vec<(Key, CheckoutFuture<'a>)> {
     let mut checkouts = Vec::new();
     for binary in &challenge.binaries {
@@ -161,7 +150,7 @@ fn checkout_challenge<'a>(
                     binary: binary.name.clone(),
                     alternative: alternative.name.clone(),
                 },
-                checkout_alternative(alternative, path.clone(), progress)
+                checkout_alternative(alternative, path.clone(), ui)
                     .map_err(move |e| e.context(format!("Could not checkout {}", path.display())))
                     .boxed(),
             ));
@@ -170,37 +159,36 @@ fn checkout_challenge<'a>(
     checkouts
 }
 
-pub async fn run(checkout: Checkout, current_dir: PathBuf) -> Result<()> {
-    let progress = Arc::new(MultiProgress::new());
+pub async fn run(checkout: Checkout, current_dir: PathBuf, ui: &dyn Ui) -> Result<()> {
     let mut context = ctf::load(current_dir)?;
     let mut checkouts = Vec::new();
-    match context.path.as_slice() {
+    let challenge_names = match context.path.as_slice() {
         [] => {
             if checkout.specs.is_empty() {
-                for challenge in &context.ctf.challenges {
-                    checkouts.extend(checkout_challenge(&context, challenge, &progress));
-                }
+                context
+                    .ctf
+                    .challenges
+                    .iter()
+                    .map(|challenge| challenge.name.clone())
+                    .collect()
             } else {
-                for spec in checkout.specs {
-                    checkouts.extend(checkout_challenge(
-                        &context,
-                        ctf::find_challenge(&context.ctf, &spec)?,
-                        &progress,
-                    ));
-                }
+                checkout.specs
             }
         }
         [challenge_name] => {
             if !checkout.specs.is_empty() {
                 bail!("ctf checkout does not take arguments when called from challenge directory");
             }
-            checkouts.extend(checkout_challenge(
-                &context,
-                ctf::find_challenge(&context.ctf, challenge_name)?,
-                &progress,
-            ));
+            vec![challenge_name.clone()]
         }
         _ => bail!("ctf checkout must be called from top or challenge directory"),
+    };
+    for challenge_name in &challenge_names {
+        checkouts.extend(checkout_challenge(
+            &context,
+            ctf::find_challenge(&context.ctf, challenge_name)?,
+            ui,
+        ));
     }
     let (keys, futures): (Vec<Key>, Vec<CheckoutFuture>) = checkouts.into_iter().unzip();
     let results = join_all(futures).await;
@@ -217,6 +205,33 @@ pub async fn run(checkout: Checkout, current_dir: PathBuf) -> Result<()> {
             Err(e) => result = Err(e),
         }
     }
-    git::commit(&context, "Checkout")?;
+    /* Patched alternatives are rebuilt after their bases are downloaded or rebuilt. */
+    for challenge_name in &challenge_names {
+        let challenge = ctf::find_challenge(&context.ctf, challenge_name)?;
+        for binary in &challenge.binaries {
+            let alternatives = match patch::rebuild_order(binary) {
+                Ok(alternatives) => alternatives,
+                Err(e) => {
+                    result = Err(e);
+                    continue;
+                }
+            };
+            for alternative in alternatives {
+                if let Err(e) = patch::rebuild(&context.root, &challenge.name, binary, alternative)
+                {
+                    result = Err(e.context(format!(
+                        "Could not rebuild {}/{}.{}",
+                        challenge.name, binary.name, alternative.name
+                    )));
+                }
+            }
+        }
+    }
+    let mut paths = Vec::new();
+    for challenge_name in &challenge_names {
+        let challenge = ctf::find_challenge(&context.ctf, challenge_name)?;
+        paths.extend(ctf::binary_paths(&context.root, challenge));
+    }
+    git::commit_files(&context, "Checkout", &paths)?;
     result
 }
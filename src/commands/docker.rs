@@ -1,10 +1,17 @@
 use anyhow::{anyhow, bail, Result};
 use clap::Parser;
 
-use crate::{ctf, distro, git, subprocess};
+use crate::config::Config;
+use crate::container::Backend;
+use crate::ctf::Context;
+use crate::dockerfile::Deployment;
+use crate::ui::Format;
+use crate::{config, container, ctf, distro, dockerfile, git, package_cache, qemu, subprocess, Ui};
+use serde::Serialize;
+use std::env;
 use std::fs;
-use std::path::PathBuf;
-use std::process::Command;
+use std::path::{Path, PathBuf};
+use time::OffsetDateTime;
 
 #[derive(Parser)]
 pub struct Docker {
@@ -22,6 +29,22 @@ enum SubCommand {
     #[clap(name = "exec")]
     Exec(Exec),
 
+    /// Starts the challenge service in a Docker container
+    #[clap(name = "serve")]
+    Serve(Serve),
+
+    /// Opens a shell inside a Docker container
+    #[clap(name = "shell")]
+    Shell(Shell),
+
+    /// Prints Docker containers of all challenges
+    #[clap(name = "status")]
+    Status(Status),
+
+    /// Updates the Dockerfile args if the binaries changed and rebuilds the image
+    #[clap(name = "rebuild")]
+    Rebuild(Rebuild),
+
     /// Removes the Docker container
     #[clap(name = "rm")]
     Rm(Rm),
@@ -31,8 +54,19 @@ enum SubCommand {
     Rmi(Rmi),
 }
 
+impl Docker {
+    /// Whether the subcommand prints records, which `--format` applies to.
+    pub fn prints_records(&self) -> bool {
+        matches!(self.subcmd, SubCommand::Status(_))
+    }
+}
+
 #[derive(Parser)]
-pub struct Init {}
+pub struct Init {
+    /// Do not set up X11 forwarding and GUI tools
+    #[clap(long)]
+    pub no_gui: bool,
+}
 
 #[derive(Parser)]
 pub struct Exec {
@@ -42,12 +76,33 @@ pub struct Exec {
     pub argv: Vec<String>,
 }
 
+#[derive(Parser)]
+pub struct Serve {}
+
+#[derive(Parser)]
+pub struct Shell {
+    /// Shell to run
+    #[clap(long, default_value = "bash")]
+    pub shell: String,
+}
+
+#[derive(Parser)]
+pub struct Status {}
+
+#[derive(Parser)]
+pub struct Rebuild {}
+
 #[derive(Parser)]
 pub struct Rm {}
 
 #[derive(Parser)]
 pub struct Rmi {}
 
+static COMPOSE_YML: &str = "docker-compose.yml";
+
+/// Distros that have Dockerfile templates.
+static SUPPORTED_DISTROS: &[&str] = &["alpine", "debian", "ubuntu"];
+
 fn get_mapping<'a>(
     mapping: &'a mut serde_yaml::Mapping,
     key: &str,
@@ -59,120 +114,612 @@ fn get_mapping<'a>(
         .ok_or_else(|| anyhow!("\"{}\" is not a mapping", key))
 }
 
-pub async fn run(docker: Docker, current_dir: PathBuf) -> Result<()> {
-    let context = ctf::load(current_dir)?;
-    let challenge_name = match context.path.as_slice() {
-        [challenge_name, ..] => challenge_name,
-        _ => bail!("Not in a challenge directory"),
+/// Uses the image that the challenge is deployed in, keeping the libc version only if the
+/// heuristics agree with it.
+fn packages_from_deployment(
+    deployment: &Deployment,
+    heuristic: distro::Packages,
+) -> distro::Packages {
+    let distro = deployment.image.official_name().map(str::to_string);
+    let version = deployment
+        .image
+        .tag
+        .clone()
+        .unwrap_or(distro::DEFAULT_DISTRO_VERSION.into());
+    let libc_version = if heuristic.distro == distro
+        && heuristic.distro_version.as_deref() == Some(version.as_str())
+    {
+        heuristic.libc_version
+    } else {
+        None
     };
-    let challenge = ctf::find_challenge(&context.ctf, challenge_name)?;
-    let challenge_dir = context.root.join(challenge_name);
-    let yml = "docker-compose.yml";
-    match docker.subcmd {
-        SubCommand::Init(_init) => {
-            let mut packages_variants = vec![];
-            for binary in &challenge.binaries {
-                println!("Analyzing {}...", binary.name);
-                let binary_path = challenge_dir.join(&binary.name);
-                if let Some(packages) = distro::get_packages(&binary_path)? {
-                    println!("  Arch: {}", packages.arch.unwrap_or("?"));
-                    println!(
-                        "  Distro: {} {}",
-                        packages.distro.unwrap_or("?"),
-                        packages.distro_version.unwrap_or("?")
-                    );
-                    println!(
-                        "  Libc: {}",
-                        &packages.libc_version.as_deref().unwrap_or("?")
-                    );
-                    packages_variants.push(packages);
+    distro::Packages {
+        arch: deployment.arch.or(heuristic.arch),
+        distro,
+        image: Some(deployment.image.name.clone()),
+        distro_version: Some(version),
+        libc_version,
+        digest: deployment.image.digest.clone(),
+        extra: deployment.packages.clone(),
+        confidence: 1.0,
+    }
+}
+
+/// Removes X11 forwarding from a docker-compose.yml service.
+fn remove_gui(service: &mut serde_yaml::Mapping) {
+    let is_gui = |value: &serde_yaml::Value| {
+        matches!(value.as_str(), Some(value)
+            if value.starts_with("/tmp/.X11-unix") || value == "DISPLAY" || value.starts_with("NO_AT_BRIDGE="))
+    };
+    for key in ["volumes", "environment"] {
+        if let Some(serde_yaml::Value::Sequence(values)) = service.get_mut(key) {
+            values.retain(|value| !is_gui(value));
+        }
+    }
+}
+
+/// Returns the architecture of a binary that needs to run under qemu-user.
+fn foreign_arch(challenge_dir: &Path, binary: &str) -> Result<Option<qemu::Arch>> {
+    let info = distro::BinaryInfo::analyze(&challenge_dir.join(binary))?;
+    Ok(info
+        .ehdr
+        .as_ref()
+        .and_then(qemu::get_arch)
+        .filter(|arch| arch.qemu.is_some()))
+}
+
+/// Analyzes the challenge and generates the Dockerfile and docker-compose.yml contents.
+fn generate(
+    challenge: &ctf::Challenge,
+    challenge_dir: &Path,
+    gui: bool,
+    package_cache: &Path,
+    ui: &dyn Ui,
+) -> Result<(&'static str, serde_yaml::Mapping)> {
+    let distros = distro::load_distros()?;
+    let mut packages_variants = vec![];
+    /* Used when no distro release matches, e.g. for foreign binaries without version strings. */
+    let mut arch = None;
+    for binary in &challenge.binaries {
+        ui.message(&format!("Analyzing {}...", binary.name));
+        let binary_path = challenge_dir.join(&binary.name);
+        let info = distro::BinaryInfo::analyze_with_distros(&binary_path, &distros)?;
+        if info.is_executable() {
+            arch = arch.or(info.ehdr.as_ref().and_then(distro::get_debian_arch_str));
+        }
+        if let Some(libc) = info.libc {
+            ui.message(&format!(
+                "  Libc: {}{}{}",
+                libc.name(),
+                info.musl_version
+                    .as_ref()
+                    .map(|version| format!(" {}", version))
+                    .unwrap_or_default(),
+                if info.is_static == Some(true) {
+                    " (static)"
+                } else {
+                    ""
                 }
+            ));
+        }
+        if let Some(runtime) = &info.runtime {
+            ui.message(&format!(
+                "  Runtime: {} {}",
+                runtime.name(),
+                runtime.version().unwrap_or("?")
+            ));
+        }
+        for packages in distro::get_packages_for(&info, &distros) {
+            ui.message(&format!(
+                "  {} {} ({:.0}%): arch {}, libc {}",
+                packages.distro.as_deref().unwrap_or("?"),
+                packages.distro_version.as_deref().unwrap_or("?"),
+                packages.confidence * 100.0,
+                packages.arch.unwrap_or("?"),
+                packages.libc_version.as_deref().unwrap_or("?")
+            ));
+            if SUPPORTED_DISTROS.contains(&packages.distro.as_deref().unwrap_or("")) {
+                packages_variants.push(packages);
             }
-            let packages = distro::merge_packages_variants(packages_variants);
-            let image = challenge_dir.join("image");
-            fs::create_dir_all(&image)?;
-            fs::write(
-                image.join("Dockerfile"),
-                include_str!("docker/image/Dockerfile").as_bytes(),
-            )?;
-            let mut compose: serde_yaml::Mapping =
-                serde_yaml::from_str(include_str!("docker/docker-compose.yml"))?;
-            let services = get_mapping(&mut compose, "services")?;
-            let main = get_mapping(services, "main")?;
-            let build = get_mapping(main, "build")?;
-            let mut args = serde_yaml::Mapping::new();
-            args.insert(
-                serde_yaml::Value::String("arch".into()),
-                serde_yaml::Value::String(packages.arch.unwrap_or(distro::DEFAULT_ARCH).into()),
-            );
-            args.insert(
-                serde_yaml::Value::String("distro".into()),
-                serde_yaml::Value::String(format!(
-                    "{}:{}",
-                    packages.distro.unwrap_or(distro::DEFAULT_DISTRO),
+        }
+    }
+    let mut packages = distro::merge_packages_variants(packages_variants);
+    packages.arch = packages.arch.or(arch);
+    let image = challenge_dir.join("image");
+    /* Skip the files generated by a previous run, unless they came with the challenge. */
+    let exclude: Vec<PathBuf> = [image.join("Dockerfile"), challenge_dir.join(COMPOSE_YML)]
+        .into_iter()
+        .filter(|path| {
+            !challenge
+                .binaries
+                .iter()
+                .any(|binary| challenge_dir.join(&binary.name) == *path)
+        })
+        .collect();
+    let deployments = dockerfile::find_deployments(challenge_dir, &exclude, ui)?;
+    for deployment in &deployments {
+        ui.message(&format!(
+            "Found {}: {}, arch {}, packages: {}",
+            deployment
+                .path
+                .strip_prefix(challenge_dir)
+                .unwrap_or(&deployment.path)
+                .display(),
+            deployment.image,
+            deployment.arch.unwrap_or("?"),
+            deployment.packages.join(" ")
+        ));
+    }
+    let deployment = deployments.iter().find(|deployment| {
+        deployment
+            .image
+            .official_name()
+            .map(|name| SUPPORTED_DISTROS.contains(&name))
+            .unwrap_or(false)
+    });
+    if let Some(deployment) = deployment {
+        packages = packages_from_deployment(deployment, packages);
+    }
+    let dockerfile = match packages.distro.as_deref() {
+        Some("alpine") => {
+            /* Unlike qemu-user on Debian, Alpine packages each emulator separately. */
+            if let Some(binary) = distro::find_main_binary(challenge_dir, &challenge.binaries)? {
+                if let Some(arch) = foreign_arch(challenge_dir, &binary)? {
                     packages
-                        .distro_version
-                        .unwrap_or(distro::DEFAULT_DISTRO_VERSION)
-                )),
+                        .extra
+                        .extend(arch.qemu.map(|qemu| format!("qemu-{}", qemu)));
+                }
+            }
+            include_str!("docker/image/Dockerfile.alpine")
+        }
+        _ => include_str!("docker/image/Dockerfile"),
+    };
+    let mut compose: serde_yaml::Mapping =
+        serde_yaml::from_str(include_str!("docker/docker-compose.yml"))?;
+    let services = get_mapping(&mut compose, "services")?;
+    let main = get_mapping(services, "main")?;
+    let build = get_mapping(main, "build")?;
+    let mut args = serde_yaml::Mapping::new();
+    args.insert(
+        serde_yaml::Value::String("arch".into()),
+        serde_yaml::Value::String(packages.arch.unwrap_or(distro::DEFAULT_ARCH).into()),
+    );
+    args.insert(
+        serde_yaml::Value::String("distro".into()),
+        serde_yaml::Value::String(format!(
+            "{}:{}{}",
+            packages.image.as_deref().unwrap_or(distro::DEFAULT_DISTRO),
+            packages
+                .distro_version
+                .as_deref()
+                .unwrap_or(distro::DEFAULT_DISTRO_VERSION),
+            packages
+                .digest
+                .as_ref()
+                .map(|digest| format!("@{}", digest))
+                .unwrap_or_default()
+        )),
+    );
+    args.insert(
+        serde_yaml::Value::String("libc_version".into()),
+        serde_yaml::Value::String(
+            packages
+                .libc_version
+                .unwrap_or(distro::DEFAULT_LIBC_VERSION.into()),
+        ),
+    );
+    if !gui {
+        args.insert(
+            serde_yaml::Value::String("gui".into()),
+            serde_yaml::Value::String("0".into()),
+        );
+    }
+    if !packages.extra.is_empty() {
+        args.insert(
+            serde_yaml::Value::String("packages".into()),
+            serde_yaml::Value::String(packages.extra.join(" ")),
+        );
+    }
+    build.insert(
+        serde_yaml::Value::String("args".into()),
+        serde_yaml::Value::Mapping(args),
+    );
+    let mut additional_contexts = serde_yaml::Mapping::new();
+    additional_contexts.insert(
+        serde_yaml::Value::String(package_cache::BUILD_CONTEXT.into()),
+        serde_yaml::Value::String(format_path(package_cache)),
+    );
+    build.insert(
+        serde_yaml::Value::String("additional_contexts".into()),
+        serde_yaml::Value::Mapping(additional_contexts),
+    );
+    let build = build.clone();
+    if !gui {
+        remove_gui(main);
+    }
+    match distro::find_main_binary(challenge_dir, &challenge.binaries)? {
+        Some(binary) => {
+            let port = ctf::local_service_port(challenge);
+            ui.message(&format!("Serving {} on port {}", binary, port));
+            let service = get_mapping(services, "service")?;
+            service.insert(
+                serde_yaml::Value::String("build".into()),
+                serde_yaml::Value::Mapping(build),
             );
-            args.insert(
-                serde_yaml::Value::String("libc_version".into()),
-                serde_yaml::Value::String(
-                    packages
-                        .libc_version
-                        .unwrap_or(distro::DEFAULT_LIBC_VERSION.into()),
-                ),
+            service.insert(
+                serde_yaml::Value::String("ports".into()),
+                serde_yaml::Value::Sequence(vec![serde_yaml::Value::String(format!(
+                    "{}:{}",
+                    port, port
+                ))]),
             );
-            build.insert(
-                serde_yaml::Value::String("args".into()),
-                serde_yaml::Value::Mapping(args),
+            service.insert(
+                serde_yaml::Value::String("command".into()),
+                serde_yaml::Value::Sequence(vec![
+                    serde_yaml::Value::String("socat".into()),
+                    serde_yaml::Value::String(format!("TCP-LISTEN:{},reuseaddr,fork", port)),
+                    serde_yaml::Value::String(match foreign_arch(challenge_dir, &binary)? {
+                        Some(_) => format!("EXEC:./{},stderr", qemu::LAUNCHER),
+                        None => format!("EXEC:./{},stderr", binary),
+                    }),
+                ]),
             );
-            fs::write(challenge_dir.join(yml), serde_yaml::to_string(&compose)?)?;
-            git::commit(
+        }
+        None => {
+            services.remove("service");
+        }
+    }
+    Ok((dockerfile, compose))
+}
+
+/// Writes the Dockerfile, the compose file and the qemu launcher, and returns their paths.
+fn write(
+    challenge: &ctf::Challenge,
+    challenge_dir: &Path,
+    dockerfile: &str,
+    compose: &serde_yaml::Mapping,
+) -> Result<Vec<PathBuf>> {
+    let mut result = Vec::new();
+    if let Some(binary) = distro::find_main_binary(challenge_dir, &challenge.binaries)? {
+        if let Some(arch) = foreign_arch(challenge_dir, &binary)? {
+            result = qemu::write_launcher(challenge_dir, &format!("./{}", binary), &arch)?;
+        }
+    }
+    let image = challenge_dir.join("image");
+    fs::create_dir_all(&image)?;
+    fs::write(image.join("Dockerfile"), dockerfile.as_bytes())?;
+    fs::write(
+        challenge_dir.join(COMPOSE_YML),
+        serde_yaml::to_string(compose)?,
+    )?;
+    result.push(image.join("Dockerfile"));
+    result.push(challenge_dir.join(COMPOSE_YML));
+    Ok(result)
+}
+
+/// Returns the Dockerfile args of the main service.
+fn build_args(compose: &serde_yaml::Mapping) -> serde_yaml::Mapping {
+    compose
+        .get("services")
+        .and_then(|services| services.get("main"))
+        .and_then(|main| main.get("build"))
+        .and_then(|build| build.get("args"))
+        .and_then(serde_yaml::Value::as_mapping)
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// Makes paths in the home directory independent of the user, so that they can be committed.
+fn format_path(path: &Path) -> String {
+    match env::var_os("HOME").and_then(|home| path.strip_prefix(home).ok().map(Path::to_path_buf)) {
+        Some(relative) => format!("${{HOME}}/{}", relative.display()),
+        None => path.display().to_string(),
+    }
+}
+
+/// Fills the package cache before building the image; failures are not fatal, since the build
+/// can download packages itself.
+async fn fetch_packages(challenge_dir: &Path, package_cache: &Path, ui: &dyn Ui) {
+    let args = match fs::read_to_string(challenge_dir.join(COMPOSE_YML)) {
+        Ok(compose) => match serde_yaml::from_str(&compose) {
+            Ok(compose) => build_args(&compose),
+            Err(_) => return,
+        },
+        Err(_) => return,
+    };
+    let arg = |key: &str| {
+        args.get(key)
+            .and_then(serde_yaml::Value::as_str)
+            .unwrap_or_default()
+            .to_string()
+    };
+    if let Err(e) = package_cache::fetch(
+        package_cache,
+        &arg("distro"),
+        &arg("arch"),
+        &arg("libc_version"),
+        ui,
+    )
+    .await
+    {
+        ui.warn(&format!("Could not fill the package cache: {}", e));
+    }
+}
+
+fn format_value(value: Option<&serde_yaml::Value>) -> String {
+    match value {
+        Some(serde_yaml::Value::String(value)) => value.clone(),
+        Some(value) => serde_yaml::to_string(value)
+            .map(|value| value.trim().to_string())
+            .unwrap_or_default(),
+        None => "<none>".into(),
+    }
+}
+
+/// Starts a service container unless it is already running.
+async fn ensure_running(
+    backend: &dyn Backend,
+    challenge_dir: &Path,
+    package_cache: &Path,
+    service: &str,
+    ui: &dyn Ui,
+) -> Result<()> {
+    let running = backend
+        .ps(challenge_dir, COMPOSE_YML)?
+        .iter()
+        .any(|container| container.service == service && container.state == "running");
+    if !running {
+        fetch_packages(challenge_dir, package_cache, ui).await;
+        subprocess::check_call(
+            backend
+                .compose(COMPOSE_YML)
+                .args(["up", "--detach", service])
+                .current_dir(challenge_dir),
+        )?;
+    }
+    Ok(())
+}
+
+/// Container as listed by `ctf docker status`.
+#[derive(Serialize)]
+struct StatusRecord {
+    challenge: String,
+    /// None if the challenge has no containers
+    service: Option<String>,
+    state: String,
+    /// Age of the image, e.g. 3h
+    age: Option<String>,
+    ports: Vec<String>,
+}
+
+fn status(context: &Context, config: &Config, ui: &dyn Ui, format: Format) -> Result<()> {
+    let backend = container::backend(config)?;
+    let now = OffsetDateTime::now_utc();
+    let mut records = Vec::new();
+    for challenge in &context.ctf.challenges {
+        let challenge_dir = context.root.join(&challenge.name);
+        if !challenge_dir.join(COMPOSE_YML).exists() {
+            continue;
+        }
+        let containers = match backend.ps(&challenge_dir, COMPOSE_YML) {
+            Ok(containers) => containers,
+            Err(e) => {
+                ui.warn(&format!("{}: {}", challenge.name, e));
+                continue;
+            }
+        };
+        if containers.is_empty() {
+            records.push(StatusRecord {
+                challenge: challenge.name.clone(),
+                service: None,
+                state: "absent".into(),
+                age: None,
+                ports: Vec::new(),
+            });
+        }
+        for container in containers {
+            records.push(StatusRecord {
+                challenge: challenge.name.clone(),
+                service: Some(container.service),
+                state: container.state,
+                age: backend
+                    .image_created(&container.image)?
+                    .map(|created| container::format_age((now - created).whole_seconds())),
+                ports: container.ports,
+            });
+        }
+    }
+    format.show(ui, &records, || {
+        let rows = records
+            .iter()
+            .map(|record| {
+                vec![
+                    record.challenge.clone(),
+                    record.service.clone().unwrap_or_else(|| "-".into()),
+                    record.state.clone(),
+                    match (&record.service, &record.age) {
+                        (None, _) => "".into(),
+                        (Some(_), age) => age.clone().unwrap_or_else(|| "-".into()),
+                    },
+                    record.ports.join(", "),
+                ]
+            })
+            .collect();
+        ui.table(&["CHALLENGE", "SERVICE", "STATE", "AGE", "PORTS"], rows);
+        Ok(())
+    })
+}
+
+/// Finds the challenge the current directory belongs to, along with its directory.
+fn current_challenge(context: &Context) -> Result<(&str, &ctf::Challenge, PathBuf)> {
+    let challenge_name = match context.path.as_slice() {
+        [challenge_name, ..] => challenge_name,
+        _ => bail!("Not in a challenge directory"),
+    };
+    let challenge = ctf::find_challenge(&context.ctf, challenge_name)?;
+    Ok((challenge_name, challenge, context.root.join(challenge_name)))
+}
+
+pub async fn run(docker: Docker, current_dir: PathBuf, ui: &dyn Ui, format: Format) -> Result<()> {
+    let context = ctf::load(current_dir)?;
+    let config = config::load()?;
+    let yml = COMPOSE_YML;
+    match docker.subcmd {
+        SubCommand::Init(init) => {
+            let (challenge_name, challenge, challenge_dir) = current_challenge(&context)?;
+            let package_cache = config.package_cache()?;
+            let (dockerfile, compose) = generate(
+                challenge,
+                &challenge_dir,
+                config.gui() && !init.no_gui,
+                &package_cache,
+                ui,
+            )?;
+            let paths = write(challenge, &challenge_dir, dockerfile, &compose)?;
+            git::commit_files(
                 &context,
                 &format!("Add Dockerfile and {} for {}", yml, challenge_name),
+                &paths,
             )?;
         }
         SubCommand::Exec(exec) => {
-            subprocess::check_call(Command::new("xhost").args(["+local:root"]))?;
+            let (_, _, challenge_dir) = current_challenge(&context)?;
+            let package_cache = config.package_cache()?;
+            let backend = container::backend(&config)?;
+            if config.gui() {
+                container::allow_x11(backend.as_ref());
+            }
+            ensure_running(backend.as_ref(), &challenge_dir, &package_cache, "main", ui).await?;
+            subprocess::check_call(
+                backend
+                    .compose(yml)
+                    .args(["exec", "main", &exec.command])
+                    .args(&exec.argv)
+                    .current_dir(&challenge_dir),
+            )?;
+        }
+        SubCommand::Serve(_serve) => {
+            let (_, challenge, challenge_dir) = current_challenge(&context)?;
+            let package_cache = config.package_cache()?;
+            fetch_packages(&challenge_dir, &package_cache, ui).await;
+            subprocess::check_call(
+                container::backend(&config)?
+                    .compose(yml)
+                    .args(["up", "--build", "--detach", "service"])
+                    .current_dir(&challenge_dir),
+            )?;
+            ui.message(&format!(
+                "Listening on localhost:{}",
+                ctf::local_service_port(challenge)
+            ));
+        }
+        SubCommand::Shell(shell) => {
+            let (_, _, challenge_dir) = current_challenge(&context)?;
+            let package_cache = config.package_cache()?;
+            let backend = container::backend(&config)?;
+            if config.gui() {
+                container::allow_x11(backend.as_ref());
+            }
+            ensure_running(backend.as_ref(), &challenge_dir, &package_cache, "main", ui).await?;
             subprocess::check_call(
-                Command::new("docker")
-                    .args([
-                        "compose",
-                        &format!("--file={}", yml),
-                        "up",
-                        "--build",
-                        "--detach",
-                    ])
+                backend
+                    .compose(yml)
+                    .args(["exec", "main", &shell.shell])
                     .current_dir(&challenge_dir),
             )?;
+        }
+        SubCommand::Status(_status) => status(&context, &config, ui, format)?,
+        SubCommand::Rebuild(_rebuild) => {
+            let (challenge_name, challenge, challenge_dir) = current_challenge(&context)?;
+            let package_cache = config.package_cache()?;
+            let old_compose: serde_yaml::Mapping =
+                serde_yaml::from_str(&fs::read_to_string(challenge_dir.join(yml)).map_err(
+                    |e| anyhow!("Could not read {}, do ctf docker init first: {}", yml, e),
+                )?)?;
+            let old_args = build_args(&old_compose);
+            let gui = old_args.get("gui").and_then(serde_yaml::Value::as_str) != Some("0");
+            let (dockerfile, compose) =
+                generate(challenge, &challenge_dir, gui, &package_cache, ui)?;
+            let args = build_args(&compose);
+            let mut outdated = false;
+            for key in old_args
+                .keys()
+                .chain(args.keys().filter(|key| !old_args.contains_key(*key)))
+            {
+                if old_args.get(key) != args.get(key) {
+                    ui.message(&format!(
+                        "{}: {} -> {}",
+                        format_value(Some(key)),
+                        format_value(old_args.get(key)),
+                        format_value(args.get(key))
+                    ));
+                    outdated = true;
+                }
+            }
+            if !outdated && old_compose != compose {
+                ui.message(&format!("{} is out of date", yml));
+                outdated = true;
+            }
+            if fs::read(challenge_dir.join("image").join("Dockerfile"))
+                .ok()
+                .as_deref()
+                != Some(dockerfile.as_bytes())
+            {
+                ui.message("image/Dockerfile is out of date");
+                outdated = true;
+            }
+            if outdated {
+                let paths = write(challenge, &challenge_dir, dockerfile, &compose)?;
+                git::commit_files(
+                    &context,
+                    &format!("Update Dockerfile and {} for {}", yml, challenge_name),
+                    &paths,
+                )?;
+            } else {
+                ui.message("Dockerfile args are up to date");
+            }
+            fetch_packages(&challenge_dir, &package_cache, ui).await;
+            let backend = container::backend(&config)?;
             subprocess::check_call(
-                Command::new("docker")
-                    .args(
-                        [
-                            "compose".to_string(),
-                            format!("--file={}", yml),
-                            "exec".to_string(),
-                            "main".to_string(),
-                            exec.command,
-                        ]
-                        .into_iter()
-                        .chain(exec.argv.into_iter())
-                        .collect::<Vec<String>>(),
-                    )
+                backend
+                    .compose(yml)
+                    .arg("build")
                     .current_dir(&challenge_dir),
             )?;
+            let running: Vec<String> = backend
+                .ps(&challenge_dir, yml)?
+                .into_iter()
+                .filter(|container| container.state == "running")
+                .map(|container| container.service)
+                .collect();
+            if !running.is_empty() {
+                subprocess::check_call(
+                    backend
+                        .compose(yml)
+                        .args(["up", "--detach"])
+                        .args(&running)
+                        .current_dir(&challenge_dir),
+                )?;
+            }
+        }
+        SubCommand::Rm(_rm) => {
+            let (_, _, challenge_dir) = current_challenge(&context)?;
+            subprocess::check_call(
+                container::backend(&config)?
+                    .compose(yml)
+                    .arg("down")
+                    .current_dir(&challenge_dir),
+            )?
+        }
+        SubCommand::Rmi(_rmi) => {
+            let (_, _, challenge_dir) = current_challenge(&context)?;
+            subprocess::check_call(
+                container::backend(&config)?
+                    .compose(yml)
+                    .args(["down", "--rmi=local"])
+                    .current_dir(&challenge_dir),
+            )?
         }
-        SubCommand::Rm(_rm) => subprocess::check_call(
-            Command::new("docker")
-                .args(["compose", &format!("--file={}", yml), "down"])
-                .current_dir(&challenge_dir),
-        )?,
-        SubCommand::Rmi(_rmi) => subprocess::check_call(
-            Command::new("docker")
-                .args(["compose", &format!("--file={}", yml), "down", "--rmi=local"])
-                .current_dir(&challenge_dir),
-        )?,
     }
     Ok(())
 }
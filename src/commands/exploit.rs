@@ -1,14 +1,21 @@
-use anyhow::{bail, Result};
-use clap::Parser;
-use std::io::Write;
+use anyhow::{anyhow, bail, Result};
+use clap::{ArgGroup, Parser};
 
-use crate::ctf::Context;
+use crate::ctf::{Challenge, Context};
 use crate::distro;
-use crate::{ctf, git};
-use elf::abi::{ET_DYN, ET_EXEC};
+use crate::exploit::{exploit_command, python_bytes};
+use crate::menu::{self, Menu, Role};
+use crate::patchelf::{find_library, LD_REGEX, LIBC_REGEX};
+use crate::ui::Format;
+use crate::{config, ctf, engines, flag, git, http, path, qemu, Ui};
 use serde::Serialize;
+use std::fs;
+use std::io::{self, Read};
 use std::os::unix::fs::PermissionsExt;
 use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
 use tinytemplate::TinyTemplate;
 
 #[derive(Parser)]
@@ -22,115 +29,661 @@ enum SubCommand {
     /// Generates an exploit skeleton
     #[clap(name = "init")]
     Init(Init),
+
+    /// Lists exploit templates
+    #[clap(name = "templates")]
+    Templates(Templates),
+
+    /// Runs the exploit and records the flags that it prints
+    #[clap(name = "run")]
+    Run(Run),
+}
+
+impl Exploit {
+    /// Whether the subcommand prints records, which `--format` applies to.
+    pub fn prints_records(&self) -> bool {
+        matches!(self.subcmd, SubCommand::Templates(_))
+    }
+}
+
+#[derive(Parser)]
+pub struct Init {
+    /// Template name, detected from the challenge binaries by default
+    #[clap(long)]
+    pub template: Option<String>,
 }
 
 #[derive(Parser)]
-pub struct Init {}
+pub struct Templates {}
+
+#[derive(Parser)]
+#[clap(group(ArgGroup::new("target").args(["local", "remote", "docker"])))]
+pub struct Run {
+    /// Exploit the local challenge binary
+    #[clap(long)]
+    pub local: bool,
+
+    /// Exploit the challenge service (default)
+    #[clap(long)]
+    pub remote: bool,
+
+    /// Exploit the local copy of the service started by ctf docker serve
+    #[clap(long)]
+    pub docker: bool,
+
+    /// Submit the captured flags to the scoreboard
+    #[clap(long)]
+    pub submit: bool,
+
+    /// Remote to submit the flags to
+    #[clap(long, default_value = "origin")]
+    pub scoreboard: String,
+
+    /// Additional exploit arguments, e.g., DEBUG
+    #[clap(last = true)]
+    pub args: Vec<String>,
+}
+
+/// Directory with user templates inside a CTF repository.
+static REPO_TEMPLATES: &str = ".templates";
+/// Suffix of the template files that are rendered; other files are copied as is.
+static TEMPLATE_SUFFIX: &str = ".tmpl";
+
+type BuiltinTemplate = (
+    &'static str,
+    &'static str,
+    &'static [(&'static str, &'static [u8])],
+);
+
+static BUILTIN_TEMPLATES: &[BuiltinTemplate] = &[
+    (
+        "linux-user",
+        "pwntools skeleton for Linux userspace binaries",
+        &[(
+            "pwnit.py.tmpl",
+            include_bytes!("exploit/linux-user/pwnit.py.tmpl"),
+        )],
+    ),
+    (
+        "linux-kernel",
+        "Static musl-gcc skeleton for Linux kernel challenges",
+        &[
+            ("pwnit.c", include_bytes!("exploit/linux-kernel/pwnit.c")),
+            ("Makefile", include_bytes!("exploit/linux-kernel/Makefile")),
+        ],
+    ),
+    (
+        "web",
+        "requests session skeleton for web challenges",
+        &[("pwnit.py.tmpl", include_bytes!("exploit/web/pwnit.py.tmpl"))],
+    ),
+    (
+        "crypto",
+        "sage and pycryptodome skeleton for crypto challenges",
+        &[(
+            "pwnit.py.tmpl",
+            include_bytes!("exploit/crypto/pwnit.py.tmpl"),
+        )],
+    ),
+    (
+        "format-string",
+        "pwntools skeleton that finds the format string offset",
+        &[(
+            "pwnit.py.tmpl",
+            include_bytes!("exploit/format-string/pwnit.py.tmpl"),
+        )],
+    ),
+    (
+        "heap",
+        "pwntools skeleton with heap menu helpers and safe-linking",
+        &[(
+            "pwnit.py.tmpl",
+            include_bytes!("exploit/heap/pwnit.py.tmpl"),
+        )],
+    ),
+    (
+        "rust",
+        "Rust skeleton without dependencies",
+        &[
+            (
+                "pwnit/Cargo.toml.tmpl",
+                include_bytes!("exploit/rust/pwnit/Cargo.toml.tmpl"),
+            ),
+            (
+                "pwnit/src/main.rs.tmpl",
+                include_bytes!("exploit/rust/pwnit/src/main.rs.tmpl"),
+            ),
+        ],
+    ),
+];
 
 #[derive(Serialize)]
-struct LinuxUser {
-    binary: String,
+struct Template {
+    name: String,
+    description: String,
+    /// Paths relative to the template directory and contents
+    #[serde(skip)]
+    files: Vec<(String, Vec<u8>)>,
+}
+
+impl Template {
+    /// Checks whether the rendered files refer to a parameter.
+    fn uses(&self, param: &str) -> bool {
+        self.files.iter().any(|(path, contents)| {
+            path.ends_with(TEMPLATE_SUFFIX)
+                && String::from_utf8_lossy(contents).contains(&format!("{{{}}}", param))
+        })
+    }
+}
+
+fn builtin_templates() -> Vec<Template> {
+    BUILTIN_TEMPLATES
+        .iter()
+        .map(|(name, description, files)| Template {
+            name: name.to_string(),
+            description: description.to_string(),
+            files: files
+                .iter()
+                .map(|(path, contents)| (path.to_string(), contents.to_vec()))
+                .collect(),
+        })
+        .collect()
+}
+
+/// Loads templates from the subdirectories of a directory.
+fn load_templates(dir: &Path) -> Result<Vec<Template>> {
+    let mut result = Vec::new();
+    if !dir.is_dir() {
+        return Ok(result);
+    }
+    let mut template_dirs = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() && !entry.file_name().to_string_lossy().starts_with('.') {
+            template_dirs.push(entry.path());
+        }
+    }
+    template_dirs.sort();
+    for template_dir in template_dirs {
+        let mut files = Vec::new();
+        for file in path::walk(&template_dir)? {
+            let relative = file.strip_prefix(&template_dir)?.to_string_lossy();
+            files.push((relative.into_owned(), fs::read(&file)?));
+        }
+        result.push(Template {
+            name: template_dir
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+                .into_owned(),
+            description: format!("from {}", template_dir.display()),
+            files,
+        });
+    }
+    Ok(result)
+}
+
+/// Returns built-in templates, overridden by user templates, overridden by CTF repository
+/// templates.
+fn templates(context: &Context) -> Result<Vec<Template>> {
+    let mut result = builtin_templates();
+    let mut dirs = Vec::new();
+    if let Some(config_dir) = config::config_dir() {
+        dirs.push(config_dir.join("templates"));
+    }
+    dirs.push(context.root.join(REPO_TEMPLATES));
+    for dir in dirs {
+        for template in load_templates(&dir)? {
+            result.retain(|existing| existing.name != template.name);
+            result.push(template);
+        }
+    }
+    Ok(result)
+}
+
+#[derive(Serialize)]
+struct ServiceParams {
+    name: Option<String>,
+    url: String,
     host: String,
     port: u16,
 }
 
-fn init_linux_user(
-    context: &Context,
-    challenge_name: &str,
-    challenge_dir: &Path,
+#[derive(Serialize)]
+struct BinaryParams {
+    name: String,
+    path: String,
+    executable: bool,
+}
+
+#[derive(Serialize)]
+struct StepParams {
+    method: &'static str,
+    prompt: String,
+    value: &'static str,
+}
+
+/// Helper function that selects a menu option and sends its arguments.
+#[derive(Serialize)]
+struct FunctionParams {
+    name: String,
+    args: String,
+    prompt: String,
+    number: u32,
+    steps: Vec<StepParams>,
+}
+
+/// Everything that templates know about a challenge.
+#[derive(Serialize)]
+struct Params {
+    challenge: String,
+    description: String,
+    services: Vec<ServiceParams>,
+    binaries: Vec<BinaryParams>,
+    /// Main binary path, /dev/null if there is none
     binary: String,
     host: String,
     port: u16,
-) -> Result<()> {
+    local_port: u16,
+    /// URL of the first HTTP service or of the local service
+    url: String,
+    arch: &'static str,
+    bits: u32,
+    endian: &'static str,
+    libc: Option<String>,
+    ld: Option<String>,
+    launcher: Option<&'static str>,
+    gdb_script: &'static str,
+    gdb_port: u16,
+    /// Menu helpers, if the main binary has a numbered menu
+    menu: Vec<FunctionParams>,
+    /// Architecture of the main binary, which the launcher needs
+    #[serde(skip)]
+    main_arch: Option<qemu::Arch>,
+}
+
+fn relative_path(name: &str) -> String {
+    Path::new(".")
+        .join(name)
+        .into_os_string()
+        .into_string()
+        .unwrap()
+}
+
+/// Converts a menu option label to a Python identifier, e.g., "Buy a flag" to "buy_a_flag".
+fn identifier(label: &str) -> String {
+    let words: Vec<String> = label
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .take(3)
+        .map(str::to_lowercase)
+        .collect();
+    let result = words.join("_");
+    if result.is_empty() || result.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("option_{}", result)
+    } else {
+        result
+    }
+}
+
+/// Generates helpers for the menu options; the arguments depend on what the options do.
+fn menu_functions(menu: &Menu) -> Vec<FunctionParams> {
+    let prompt = |prompt: &Option<String>, default: &str| {
+        python_bytes(prompt.as_deref().unwrap_or(default).as_bytes())
+    };
+    let mut result: Vec<FunctionParams> = Vec::new();
+    for option in &menu.options {
+        let (name, args): (String, &[&str]) = match option.role {
+            Role::Alloc => ("alloc".into(), &["size", "data"]),
+            Role::Free => ("free".into(), &["idx"]),
+            Role::Edit => ("edit".into(), &["idx", "data"]),
+            Role::Show => ("show".into(), &["idx"]),
+            Role::Exit => continue,
+            Role::Other => (identifier(&option.label), &[]),
+        };
+        let name = if result.iter().any(|function| function.name == name) {
+            format!("{}_{}", name, option.number)
+        } else {
+            name
+        };
+        let steps = args
+            .iter()
+            .map(|arg| match *arg {
+                "idx" => StepParams {
+                    method: "sendlineafter",
+                    prompt: prompt(&menu.index_prompt, "Index: "),
+                    value: "str(idx).encode()",
+                },
+                "size" => StepParams {
+                    method: "sendlineafter",
+                    prompt: prompt(&menu.size_prompt, "Size: "),
+                    value: "str(size).encode()",
+                },
+                _ => StepParams {
+                    method: "sendafter",
+                    prompt: prompt(&menu.data_prompt, "Data: "),
+                    value: "data",
+                },
+            })
+            .collect();
+        result.push(FunctionParams {
+            name,
+            args: args.join(", "),
+            prompt: prompt(&menu.prompt, "> "),
+            number: option.number,
+            steps,
+        });
+    }
+    result
+}
+
+/// Collects the template parameters and determines the default template.
+fn params(challenge: &Challenge, challenge_dir: &Path) -> Result<(Params, &'static str)> {
+    let mut services = Vec::new();
+    for service in &challenge.services {
+        let parsed = url::Url::parse(&service.url)?;
+        services.push(ServiceParams {
+            name: service.name.clone(),
+            url: service.url.clone(),
+            host: parsed
+                .host()
+                .map(|host| host.to_string())
+                .unwrap_or_else(|| "localhost".into()),
+            port: parsed.port_or_known_default().unwrap_or(0),
+        });
+    }
+    let local_port = ctf::local_service_port(challenge);
+    let url = services
+        .iter()
+        .find(|service| service.url.starts_with("http://") || service.url.starts_with("https://"))
+        .map(|service| service.url.clone());
+    let mut template = match url {
+        Some(_) => "web",
+        None => "linux-user",
+    };
+    let mut binaries = Vec::new();
+    let mut main_binary = None;
+    let mut menu = None;
+    let distros = distro::load_distros()?;
+    for binary in &challenge.binaries {
+        let info =
+            distro::BinaryInfo::analyze_with_distros(&challenge_dir.join(&binary.name), &distros)?;
+        if main_binary.is_none() {
+            if info.kernel_version.is_some() {
+                template = "linux-kernel";
+                main_binary = Some((binary.name.clone(), None));
+            } else if info.is_executable() {
+                template = "linux-user";
+                let strings = menu::rodata_strings(&fs::read(challenge_dir.join(&binary.name))?);
+                menu = menu::find_menu(&strings);
+                main_binary = Some((
+                    binary.name.clone(),
+                    info.ehdr.as_ref().and_then(qemu::get_arch),
+                ));
+            }
+        }
+        binaries.push(BinaryParams {
+            name: binary.name.clone(),
+            path: relative_path(&binary.name),
+            executable: info.is_executable(),
+        });
+    }
+    let (binary, arch) = match main_binary {
+        Some((name, arch)) => (relative_path(&name), arch),
+        None => ("/dev/null".into(), None),
+    };
+    let first_service = services.first();
+    let params = Params {
+        challenge: challenge.name.clone(),
+        description: challenge.description.clone(),
+        binary,
+        host: first_service
+            .map(|service| service.host.clone())
+            .unwrap_or_else(|| "localhost".into()),
+        port: first_service.map(|service| service.port).unwrap_or(0),
+        local_port,
+        url: url.unwrap_or_else(|| format!("http://localhost:{}/", local_port)),
+        services,
+        binaries,
+        arch: arch.as_ref().map(|arch| arch.pwntools).unwrap_or("amd64"),
+        bits: arch.as_ref().map(|arch| arch.bits).unwrap_or(64),
+        endian: arch.as_ref().map(|arch| arch.endian).unwrap_or("little"),
+        libc: find_library(challenge_dir, &LIBC_REGEX, "libc")
+            .ok()
+            .map(|path| relative_path(&path.to_string_lossy())),
+        ld: find_library(challenge_dir, &LD_REGEX, "ld")
+            .ok()
+            .map(|path| relative_path(&path.to_string_lossy())),
+        launcher: arch
+            .as_ref()
+            .and_then(|arch| arch.qemu)
+            .map(|_| qemu::LAUNCHER),
+        gdb_script: qemu::GDB_SCRIPT,
+        gdb_port: qemu::GDB_PORT,
+        menu: menu.as_ref().map(menu_functions).unwrap_or_default(),
+        main_arch: arch,
+    };
+    Ok((params, template))
+}
+
+/// Renders a template into a challenge directory and returns the created files.
+fn render(template: &Template, challenge_dir: &Path, params: &Params) -> Result<Vec<PathBuf>> {
+    let mut sources = Vec::new();
+    for (path, contents) in &template.files {
+        match path.strip_suffix(TEMPLATE_SUFFIX) {
+            Some(path) => sources.push((path, Some(std::str::from_utf8(contents)?))),
+            None => sources.push((path.as_str(), None)),
+        }
+    }
     let mut templates = TinyTemplate::new();
-    let template_name = "pwnit.py";
-    templates.add_template(
-        template_name,
-        std::str::from_utf8(include_bytes!("exploit/linux-user/pwnit.py"))?,
-    )?;
-    let rendered = templates.render(template_name, &LinuxUser { binary, host, port })?;
-    {
-        let mut file = std::fs::File::create(challenge_dir.join("pwnit.py"))?;
-        let metadata = file.metadata()?;
-        let mut permissions = metadata.permissions();
-        permissions.set_mode(permissions.mode() | 0o111);
-        file.set_permissions(permissions)?;
-        file.write_all(rendered.as_bytes())?;
-    }
-    git::commit(
-        context,
-        &format!("Add a linux-user exploit skeleton for {}", challenge_name),
-    )?;
-    Ok(())
+    templates.set_default_formatter(&tinytemplate::format_unescaped);
+    for (path, source) in &sources {
+        if let Some(source) = source {
+            templates.add_template(path, source)?;
+        }
+    }
+    let mut result = Vec::new();
+    for ((path, source), (_, contents)) in sources.iter().zip(&template.files) {
+        let rendered = match source {
+            Some(_) => templates.render(path, params)?.into_bytes(),
+            None => contents.clone(),
+        };
+        let output = challenge_dir.join(path);
+        if let Some(parent) = output.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&output, &rendered)?;
+        if rendered.starts_with(b"#!") {
+            let mut permissions = fs::metadata(&output)?.permissions();
+            permissions.set_mode(permissions.mode() | 0o111);
+            fs::set_permissions(&output, permissions)?;
+        }
+        result.push(output);
+    }
+    Ok(result)
 }
 
-fn init_linux_kernel(context: &Context, challenge_name: &str, challenge_dir: &Path) -> Result<()> {
-    std::fs::write(
-        challenge_dir.join("pwnit.c"),
-        include_bytes!("exploit/linux-kernel/pwnit.c"),
-    )?;
-    std::fs::write(
-        challenge_dir.join("Makefile"),
-        include_bytes!("exploit/linux-kernel/Makefile"),
-    )?;
-    git::commit(
-        context,
-        &format!("Add a linux-kernel exploit skeleton for {}", challenge_name),
-    )?;
-    Ok(())
+/// Runs a command, showing its stdout as it arrives, and returns the output.
+fn tee(command: &mut Command, ui: &dyn Ui) -> Result<(String, ExitStatus)> {
+    let mut child = command.stdout(Stdio::piped()).spawn()?;
+    let mut stdout = child.stdout.take().unwrap();
+    let mut output = Vec::new();
+    /* Do not wait for newlines, prompts of interactive exploits do not end with them. */
+    let mut chunk = [0; 4096];
+    loop {
+        let n = match stdout.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        ui.output(&chunk[..n]);
+        output.extend_from_slice(&chunk[..n]);
+    }
+    let status = child.wait()?;
+    Ok((String::from_utf8_lossy(&output).into_owned(), status))
 }
 
-pub fn run(exploit: Exploit, current_dir: PathBuf) -> Result<()> {
-    let context = ctf::load(current_dir)?;
-    let challenge_name = match context.path.as_slice() {
-        [challenge_name, ..] => challenge_name,
-        _ => bail!("Not in a challenge directory"),
+async fn submit(
+    context: &Context,
+    remote_name: &str,
+    challenge: &str,
+    flag: &str,
+) -> Result<String> {
+    let remote = ctf::find_remote(&context.ctf, remote_name)?;
+    let client = http::mk_client(&remote.rewrite_rules)?;
+    let cookie_store = ctf::load_cookie_store(&context.credentials, remote_name)?;
+    let engine = match remote.engine.as_str() {
+        "auto" => engines::detect(&client, remote).await?,
+        engine => engine.to_string(),
     };
-    let challenge = ctf::find_challenge(&context.ctf, challenge_name)?;
+    engines::get_engine(&engine)?
+        .submit(&client, &cookie_store, remote, challenge, flag)
+        .await
+}
+
+async fn run_exploit(
+    run: Run,
+    mut context: Context,
+    challenge_name: &str,
+    ui: &dyn Ui,
+) -> Result<()> {
     let challenge_dir = context.root.join(challenge_name);
-    match exploit.subcmd {
-        SubCommand::Init(_init) => {
-            let mut host = "localhost".to_string();
-            let mut port = 0;
-            if let Some(service) = &challenge.services.first() {
-                let parsed = url::Url::parse(&service.url)?;
-                if let Some(_host) = parsed.host() {
-                    host = _host.to_string();
-                }
-                if let Some(_port) = parsed.port() {
-                    port = _port;
-                }
+    let (mut command, files) = exploit_command(&challenge_dir)?;
+    let target = if run.local {
+        command.arg("LOCAL");
+        "local"
+    } else if run.docker {
+        command.arg("DOCKER");
+        "docker"
+    } else {
+        "remote"
+    };
+    command.args(&run.args);
+    let (output, status) = tee(&mut command, ui)?;
+    let flags = flag::Detector::for_ctf(&context.ctf)?.find(&output);
+    if flags.is_empty() {
+        bail!("Exploit exited with {} and printed no flags", status);
+    }
+    if !status.success() {
+        ui.warn(&format!("Exploit exited with {}", status));
+    }
+    let revision = git::revision(&context.root, &files)?;
+    let time = OffsetDateTime::now_utc().format(&Rfc3339)?;
+    let mut captured = Vec::new();
+    for value in flags {
+        let existing = ctf::find_challenge(&context.ctf, challenge_name)?
+            .flags
+            .iter()
+            .find(|flag| flag.value == value);
+        let submission = match existing {
+            Some(flag) if flag.submission.is_some() || !run.submit => {
+                ui.message(&format!("Already captured: {}", value));
+                continue;
             }
-            let mut binary = "/dev/null".into();
-            for _binary in &challenge.binaries {
-                let binary_path = challenge_dir.join(&_binary.name);
-                let info = distro::BinaryInfo::analyze(&binary_path)?;
-                if info.kernel_version.is_some() {
-                    init_linux_kernel(&context, &challenge.name, &challenge_dir)?;
-                    return Ok(());
-                }
-                if let Some(ehdr) = info.ehdr {
-                    if ehdr.e_type == ET_EXEC
-                        || (ehdr.e_type == ET_DYN && info.is_pie == Some(true))
-                    {
-                        binary = Path::new(".")
-                            .join(&_binary.name)
-                            .into_os_string()
-                            .into_string()
-                            .unwrap();
-                        break;
+            /* Record the flag even if the submission fails, so that it is not lost, and the
+             * verdicts that the scoreboard has already given are recorded too. */
+            _ if run.submit => {
+                match submit(&context, &run.scoreboard, challenge_name, &value).await {
+                    Ok(verdict) => {
+                        ui.message(&format!("Submitted {}: {}", value, verdict));
+                        Some(verdict)
+                    }
+                    Err(e) => {
+                        ui.warn(&format!("Could not submit {}: {:#}", value, e));
+                        None
                     }
                 }
             }
-            init_linux_user(
+            _ => {
+                ui.message(&format!("Captured: {}", value));
+                None
+            }
+        };
+        captured.push(ctf::Flag {
+            value,
+            time: time.clone(),
+            revision: revision.clone(),
+            target: target.into(),
+            submission,
+        });
+    }
+    if captured.is_empty() {
+        return Ok(());
+    }
+    let challenge = ctf::find_challenge_mut(&mut context.ctf, challenge_name)?;
+    for flag in captured {
+        challenge
+            .flags
+            .retain(|existing| existing.value != flag.value);
+        challenge.flags.push(flag);
+    }
+    git::commit(&context, &format!("Capture flags for {}", challenge_name))?;
+    Ok(())
+}
+
+/// Finds the challenge the current directory belongs to.
+fn current_challenge(context: &Context) -> Result<&str> {
+    match context.path.as_slice() {
+        [challenge_name, ..] => Ok(challenge_name),
+        _ => bail!("Not in a challenge directory"),
+    }
+}
+
+pub async fn run(
+    exploit: Exploit,
+    current_dir: PathBuf,
+    ui: &dyn Ui,
+    format: Format,
+) -> Result<()> {
+    let context = ctf::load(current_dir)?;
+    match exploit.subcmd {
+        SubCommand::Init(init) => {
+            let challenge_name = current_challenge(&context)?;
+            let challenge = ctf::find_challenge(&context.ctf, challenge_name)?;
+            let challenge_dir = context.root.join(challenge_name);
+            let (params, default_template) = params(challenge, &challenge_dir)?;
+            let name = init.template.as_deref().unwrap_or(default_template);
+            let template = templates(&context)?
+                .into_iter()
+                .find(|template| template.name == name)
+                .ok_or_else(|| {
+                    anyhow!(
+                        "No such template: {}, see ctf exploit templates for the list",
+                        name
+                    )
+                })?;
+            let mut files = render(&template, &challenge_dir, &params)?;
+            if let (Some(arch), true) = (&params.main_arch, template.uses("launcher")) {
+                files.extend(qemu::write_launcher(&challenge_dir, &params.binary, arch)?);
+            }
+            /* Templates may create arbitrary files, which .gitignore does not know about. */
+            git::commit_files(
                 &context,
-                &challenge.name,
-                &challenge_dir,
-                binary,
-                host,
-                port,
+                &format!(
+                    "Add a {} exploit skeleton for {}",
+                    template.name, challenge_name
+                ),
+                &files,
             )?;
         }
+        SubCommand::Templates(_templates) => {
+            let templates = templates(&context)?;
+            format.show(ui, &templates, || {
+                ui.table(
+                    &["NAME", "DESCRIPTION"],
+                    templates
+                        .iter()
+                        .map(|template| vec![template.name.clone(), template.description.clone()])
+                        .collect(),
+                );
+                Ok(())
+            })?;
+        }
+        SubCommand::Run(run) => {
+            let challenge_name = current_challenge(&context)?.to_string();
+            run_exploit(run, context, &challenge_name, ui).await?;
+        }
     }
     Ok(())
 }
@@ -1,23 +0,0 @@
-#!/usr/bin/env python3
-from pwn import *
-
-
-def connect():
-    if args.LOCAL:
-        if args.GDB:
-            return process(["{binary}"])
-        else:
-            tube = gdb.debug(["{binary}"], api=True)
-            tube.gdb.continue_nowait()
-            return tube
-    else:
-        return remote("{host}", {port})
-
-
-def main():
-    with connect() as tube:
-        tube.interactive()
-
-
-if __name__ == "__main__":
-    main()
@@ -0,0 +1,131 @@
+use anyhow::{bail, Result};
+use clap::Parser;
+
+use crate::{ctf, flag, git, ui, Ui};
+use serde::Serialize;
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Parser)]
+pub struct Flag {
+    #[clap(subcommand)]
+    subcmd: SubCommand,
+}
+
+#[derive(Parser)]
+enum SubCommand {
+    /// Finds flags in files, archives or standard input
+    #[clap(name = "scan")]
+    Scan(Scan),
+
+    /// Prints the flag format
+    #[clap(name = "format")]
+    Format(Format),
+
+    /// Sets the flag format
+    #[clap(name = "set-format")]
+    SetFormat(SetFormat),
+}
+
+impl Flag {
+    /// Whether the subcommand prints records, which `--format` applies to.
+    pub fn prints_records(&self) -> bool {
+        matches!(self.subcmd, SubCommand::Scan(_) | SubCommand::Format(_))
+    }
+}
+
+#[derive(Parser)]
+pub struct Scan {
+    /// Files to scan, - for standard input
+    #[clap(required = true)]
+    pub paths: Vec<PathBuf>,
+
+    /// Flag format regex, the CTF one by default
+    #[clap(long)]
+    pub flag_format: Option<String>,
+}
+
+#[derive(Parser)]
+pub struct Format {}
+
+#[derive(Parser)]
+pub struct SetFormat {
+    /// Regex, e.g. hxp\{[^}]+\}; empty means the default one
+    pub format: String,
+}
+
+/// Flag format as printed by `ctf flag format --format json`.
+#[derive(Serialize)]
+struct FormatRecord<'a> {
+    format: &'a str,
+    default: bool,
+}
+
+fn scan_files(scan: Scan, current_dir: PathBuf, ui: &dyn Ui, format: ui::Format) -> Result<()> {
+    /* Scanning does not require a CTF repository. */
+    let detector = match (&scan.flag_format, ctf::load(current_dir.clone())) {
+        (Some(format), _) => flag::Detector::new(format)?,
+        (None, Ok(context)) => flag::Detector::for_ctf(&context.ctf)?,
+        (None, Err(_)) => flag::Detector::new(flag::DEFAULT_FORMAT)?,
+    };
+    let mut matches = Vec::new();
+    for path in scan.paths {
+        let (location, bytes) = if path.as_os_str() == "-" {
+            ("-".to_string(), ui.input()?)
+        } else {
+            (
+                path.display().to_string(),
+                fs::read(current_dir.join(&path))?,
+            )
+        };
+        matches.extend(detector.scan(&location, &bytes));
+    }
+    format.show(ui, &matches, || {
+        for m in &matches {
+            ui.message(&format!("{}: {}", m.location, m.flag));
+        }
+        Ok(())
+    })?;
+    if matches.is_empty() {
+        bail!("No flags found");
+    }
+    Ok(())
+}
+
+pub fn run(flag: Flag, current_dir: PathBuf, ui: &dyn Ui, format: ui::Format) -> Result<()> {
+    match flag.subcmd {
+        SubCommand::Scan(scan) => scan_files(scan, current_dir, ui, format)?,
+        SubCommand::Format(_format) => {
+            let context = ctf::load(current_dir)?;
+            let record = FormatRecord {
+                format: context
+                    .ctf
+                    .flag_format
+                    .as_deref()
+                    .unwrap_or(flag::DEFAULT_FORMAT),
+                default: context.ctf.flag_format.is_none(),
+            };
+            format.show(ui, &record, || {
+                match record.default {
+                    true => ui.message(&format!("{} (default)", record.format)),
+                    false => ui.message(record.format),
+                }
+                Ok(())
+            })?;
+        }
+        SubCommand::SetFormat(set_format) => {
+            let mut context = ctf::load(current_dir)?;
+            let message = if set_format.format.is_empty() {
+                context.ctf.flag_format = None;
+                "Reset flag format".to_string()
+            } else {
+                flag::Detector::new(&set_format.format)?;
+                let message = format!("Set flag format to {}", set_format.format);
+                context.ctf.flag_format = Some(set_format.format);
+                message
+            };
+            git::commit(&context, &message)?;
+        }
+    }
+    Ok(())
+}
@@ -2,7 +2,7 @@ use std::path::{Path, PathBuf};
 
 use clap::Parser;
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Result};
 
 use crate::ctf;
 use crate::git;
@@ -12,20 +12,21 @@ use crate::os_str::os_str_to_str;
 pub struct Init {}
 
 pub fn run(_init: Init, root: PathBuf) -> Result<()> {
+    /* Initializing again would replace .ctf with an empty one, losing the challenges. */
+    if root.join(".ctf").exists() {
+        bail!("{} is already initialized", root.display());
+    }
     let os_name = Path::file_name(&root)
         .ok_or_else(|| anyhow!("Could not obtain the name of the current directory"))?;
     let ctf = ctf::CTF {
         name: os_str_to_str(os_name)?.into(),
+        flag_format: None,
         remotes: vec![],
         challenges: vec![],
+        attack_defense: None,
     };
     git::init(&root)?;
-    if git::get_option(&root, "user.name")?.is_none() {
-        git::set_option(&root, "user.name", "ctf")?;
-    }
-    if git::get_option(&root, "user.email")?.is_none() {
-        git::set_option(&root, "user.email", "ctf@localhost")?;
-    }
+    git::setup_merge_driver(&root)?;
     let cwd = root.clone();
     let context = ctf::Context {
         ctf,
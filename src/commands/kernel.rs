@@ -0,0 +1,361 @@
+use anyhow::{anyhow, bail, Result};
+use clap::Parser;
+
+use crate::compression::{self, Format};
+use crate::{ctf, kernel, subprocess, ui, Ui};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+#[derive(Parser)]
+pub struct Kernel {
+    #[clap(subcommand)]
+    subcmd: SubCommand,
+}
+
+#[derive(Parser)]
+enum SubCommand {
+    /// Prints kernel version, mitigations and modules
+    #[clap(name = "info")]
+    Info(Info),
+
+    /// Builds the exploit, adds it to the initramfs and starts QEMU
+    #[clap(name = "run")]
+    Run(Run),
+}
+
+impl Kernel {
+    /// Whether the subcommand prints records, which `--format` applies to.
+    pub fn prints_records(&self) -> bool {
+        matches!(self.subcmd, SubCommand::Info(_))
+    }
+}
+
+#[derive(Parser)]
+pub struct Info {
+    /// Kernel image, e.g. bzImage
+    #[clap(long)]
+    pub kernel: Option<PathBuf>,
+
+    /// Initial ramdisk
+    #[clap(long)]
+    pub initramfs: Option<PathBuf>,
+
+    /// Script that starts QEMU, e.g. run.sh, whose -cpu determines SMEP and SMAP
+    #[clap(long)]
+    pub script: Option<PathBuf>,
+}
+
+#[derive(Parser)]
+pub struct Run {
+    /// Exploit make target and binary name
+    #[clap(long, default_value = "pwnit")]
+    pub exploit: String,
+
+    /// Exploit path inside the initramfs
+    #[clap(long, default_value = "/pwnit")]
+    pub dest: String,
+
+    /// Script that starts QEMU, e.g. run.sh
+    #[clap(long)]
+    pub script: Option<PathBuf>,
+
+    /// Start a gdb stub on port 1234
+    #[clap(short = 's', long)]
+    pub gdb: bool,
+
+    /// Do not build the exploit
+    #[clap(long)]
+    pub no_build: bool,
+}
+
+fn display(challenge_dir: &Path, path: &Path) -> String {
+    path.strip_prefix(challenge_dir)
+        .unwrap_or(path)
+        .display()
+        .to_string()
+}
+
+/// What `ctf kernel info` found out about a kernel image.
+#[derive(Serialize)]
+struct KernelRecord {
+    path: String,
+    /// Where vmlinux was found, if the image is compressed
+    vmlinux: Option<VmlinuxRecord>,
+    version: Option<String>,
+    /// None if the config is not embedded
+    mitigations: Option<Vec<MitigationRecord>>,
+}
+
+#[derive(Serialize)]
+struct VmlinuxRecord {
+    format: &'static str,
+    offset: usize,
+    extracted_to: String,
+}
+
+#[derive(Serialize)]
+struct MitigationRecord {
+    name: &'static str,
+    /// enabled, disabled or depends on the CPU
+    status: &'static str,
+}
+
+/// What `ctf kernel info` found out about an initramfs.
+#[derive(Serialize)]
+struct InitramfsRecord {
+    path: String,
+    files: usize,
+    modules: Vec<ModuleRecord>,
+}
+
+#[derive(Serialize)]
+struct ModuleRecord {
+    path: String,
+    vermagic: Option<String>,
+    exports: Vec<String>,
+    /// Why the module could not be analyzed
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct InfoRecord {
+    /// Script that starts QEMU, if any
+    script: Option<String>,
+    kernels: Vec<KernelRecord>,
+    initramfses: Vec<InitramfsRecord>,
+}
+
+fn info_kernel(
+    challenge_dir: &Path,
+    path: &Path,
+    qemu_args: Option<&[String]>,
+) -> Result<KernelRecord> {
+    let bytes = fs::read(path)?;
+    let vmlinux = kernel::extract_vmlinux(&bytes)
+        .ok_or_else(|| anyhow!("Could not find vmlinux in {}", path.display()))?;
+    let mut record = KernelRecord {
+        path: display(challenge_dir, path),
+        vmlinux: None,
+        version: kernel::version(&vmlinux.bytes),
+        mitigations: None,
+    };
+    if let Some(format) = vmlinux.format {
+        let vmlinux_path = path.with_file_name("vmlinux");
+        if !vmlinux_path.exists() {
+            fs::write(&vmlinux_path, &vmlinux.bytes)?;
+        }
+        record.vmlinux = Some(VmlinuxRecord {
+            format: format.name(),
+            offset: vmlinux.offset,
+            extracted_to: display(challenge_dir, &vmlinux_path),
+        });
+    }
+    if let Some(config) = kernel::extract_ikconfig(&vmlinux.bytes)? {
+        let config = kernel::parse_config(&config);
+        record.mitigations = Some(
+            kernel::MITIGATIONS
+                .iter()
+                .map(|mitigation| MitigationRecord {
+                    name: mitigation.name,
+                    status: match mitigation.is_enabled(&config, qemu_args) {
+                        Some(true) => "enabled",
+                        Some(false) => "disabled",
+                        None => "depends on the CPU",
+                    },
+                })
+                .collect(),
+        );
+    }
+    Ok(record)
+}
+
+fn info_initramfs(challenge_dir: &Path, path: &Path) -> Result<InitramfsRecord> {
+    let entries = kernel::parse_cpio(&fs::read(path)?)?;
+    let modules = entries
+        .iter()
+        .filter(|entry| entry.is_file() && entry.name.contains(".ko"))
+        .map(|entry| match kernel::analyze_module(&entry.data) {
+            Ok(module) => ModuleRecord {
+                path: entry.name.clone(),
+                vermagic: module.vermagic,
+                exports: module.exports,
+                error: None,
+            },
+            Err(e) => ModuleRecord {
+                path: entry.name.clone(),
+                vermagic: None,
+                exports: Vec::new(),
+                error: Some(e.to_string()),
+            },
+        })
+        .collect();
+    Ok(InitramfsRecord {
+        path: display(challenge_dir, path),
+        files: entries.len(),
+        modules,
+    })
+}
+
+fn show_kernel(record: &KernelRecord, ui: &dyn Ui) {
+    ui.message(&format!("Kernel: {}", record.path));
+    if let Some(vmlinux) = &record.vmlinux {
+        ui.message(&format!(
+            "  vmlinux: {} at 0x{:x}, extracted to {}",
+            vmlinux.format, vmlinux.offset, vmlinux.extracted_to
+        ));
+    }
+    if let Some(version) = &record.version {
+        ui.message(&format!("  Version: {}", version));
+    }
+    match &record.mitigations {
+        Some(mitigations) => {
+            ui.message("  Mitigations:");
+            for mitigation in mitigations {
+                ui.message(&format!("    {}: {}", mitigation.name, mitigation.status));
+            }
+        }
+        None => ui.message("  Config: not embedded"),
+    }
+}
+
+fn show_initramfs(record: &InitramfsRecord, ui: &dyn Ui) {
+    ui.message(&format!("Initramfs: {}", record.path));
+    ui.message(&format!("  Files: {}", record.files));
+    for module in &record.modules {
+        ui.message(&format!("  Module: {}", module.path));
+        if let Some(e) = &module.error {
+            ui.message(&format!("    Could not analyze: {}", e));
+            continue;
+        }
+        if let Some(vermagic) = &module.vermagic {
+            ui.message(&format!("    vermagic: {}", vermagic));
+        }
+        if module.exports.is_empty() {
+            ui.message("    Exports: none");
+        } else {
+            ui.message(&format!("    Exports: {}", module.exports.join(", ")));
+        }
+    }
+}
+
+/// Returns the given script that starts QEMU and its arguments, or looks for one.
+fn run_script(
+    challenge_dir: &Path,
+    cwd: &Path,
+    script: &Option<PathBuf>,
+) -> Result<Option<(PathBuf, Vec<String>)>> {
+    match script {
+        Some(script) => {
+            let script = cwd.join(script);
+            let args = kernel::parse_run_script(&fs::read_to_string(&script)?)
+                .ok_or_else(|| anyhow!("{} does not start QEMU", script.display()))?;
+            Ok(Some((script, args)))
+        }
+        None => kernel::find_run_script(challenge_dir),
+    }
+}
+
+fn run_qemu(challenge_dir: &Path, cwd: &Path, run: Run, ui: &dyn Ui) -> Result<()> {
+    let (script, mut args) = run_script(challenge_dir, cwd, &run.script)?
+        .ok_or_else(|| anyhow!("No script that starts QEMU found"))?;
+    let script_dir = script.parent().unwrap();
+    ui.message(&format!("Using {}", display(challenge_dir, &script)));
+
+    if !run.no_build {
+        subprocess::check_call(
+            Command::new("make")
+                .arg(&run.exploit)
+                .current_dir(challenge_dir),
+        )?;
+    }
+    let exploit = fs::read(challenge_dir.join(&run.exploit))?;
+
+    let initrd_index = args
+        .iter()
+        .position(|arg| arg == "-initrd" || arg == "--initrd")
+        .filter(|i| i + 1 < args.len())
+        .ok_or_else(|| anyhow!("{} does not use -initrd", script.display()))?;
+    let initramfs = script_dir.join(&args[initrd_index + 1]);
+    let (format, bytes) = compression::maybe_decompress(fs::read(&initramfs)?)?;
+    let mut entries = kernel::parse_cpio(&bytes)?;
+    kernel::insert_file(&mut entries, &run.dest, 0o755, exploit);
+    let cpio = kernel::write_cpio(&entries);
+    /* gzip support is ubiquitous, other formats are stored uncompressed. */
+    let (pwnit_initramfs, bytes) = if format == Some(Format::Gzip) {
+        (
+            format!("{}.gz", kernel::PWNIT_INITRAMFS),
+            compression::gzip(&cpio)?,
+        )
+    } else {
+        (kernel::PWNIT_INITRAMFS.to_string(), cpio)
+    };
+    let pwnit_initramfs = challenge_dir.join(pwnit_initramfs);
+    fs::write(&pwnit_initramfs, bytes)?;
+    args[initrd_index + 1] = pwnit_initramfs.to_string_lossy().into_owned();
+
+    if run.gdb {
+        args.push("-s".into());
+    }
+    ui.message(&format!("Running {}", args.join(" ")));
+    subprocess::check_call(
+        Command::new(&args[0])
+            .args(&args[1..])
+            .current_dir(script_dir),
+    )
+}
+
+pub fn run(kernel: Kernel, current_dir: PathBuf, ui: &dyn Ui, format: ui::Format) -> Result<()> {
+    let context = ctf::load(current_dir)?;
+    let challenge_name = match context.path.as_slice() {
+        [challenge_name, ..] => challenge_name,
+        _ => bail!("Not in a challenge directory"),
+    };
+    let challenge_dir = context.root.join(challenge_name);
+    match kernel.subcmd {
+        SubCommand::Info(info) => {
+            let (kernels, initramfses) = kernel::find_files(&challenge_dir)?;
+            let kernels = match info.kernel {
+                Some(path) => vec![context.cwd.join(path)],
+                None => kernels,
+            };
+            let initramfses = match info.initramfs {
+                Some(path) => vec![context.cwd.join(path)],
+                None => initramfses,
+            };
+            if kernels.is_empty() && initramfses.is_empty() {
+                bail!("No kernel images or initramfs archives found");
+            }
+            let script = run_script(&challenge_dir, &context.cwd, &info.script)?;
+            let qemu_args = script.as_ref().map(|(_, args)| args.as_slice());
+            let record = InfoRecord {
+                script: script
+                    .as_ref()
+                    .map(|(path, _)| display(&challenge_dir, path)),
+                kernels: kernels
+                    .iter()
+                    .map(|path| info_kernel(&challenge_dir, path, qemu_args))
+                    .collect::<Result<_>>()?,
+                initramfses: initramfses
+                    .iter()
+                    .map(|path| info_initramfs(&challenge_dir, path))
+                    .collect::<Result<_>>()?,
+            };
+            format.show(ui, &record, || {
+                if let Some(script) = &record.script {
+                    ui.message(&format!("QEMU script: {}", script));
+                }
+                for kernel in &record.kernels {
+                    show_kernel(kernel, ui);
+                }
+                for initramfs in &record.initramfses {
+                    show_initramfs(initramfs, ui);
+                }
+                Ok(())
+            })?;
+        }
+        SubCommand::Run(run) => run_qemu(&challenge_dir, &context.cwd, run, ui)?,
+    }
+    Ok(())
+}
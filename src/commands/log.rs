@@ -0,0 +1,59 @@
+use anyhow::Result;
+use clap::Parser;
+
+use crate::ui::Format;
+use crate::{ctf, git, Ui};
+use serde::Serialize;
+use std::path::PathBuf;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+#[derive(Parser)]
+pub struct Log {
+    /// Maximum number of operations
+    #[clap(short = 'n', long, default_value = "20")]
+    pub max_count: usize,
+}
+
+/// Operation as printed by `ctf log --format json`.
+#[derive(Serialize)]
+struct Operation {
+    id: String,
+    time: String,
+    author: String,
+    summary: String,
+    changes: Vec<String>,
+}
+
+pub fn run(log: Log, current_dir: PathBuf, ui: &dyn Ui, format: Format) -> Result<()> {
+    let context = ctf::load(current_dir)?;
+    /* The operation before the oldest one is needed to see what the latter did. */
+    let operations = git::history(&context.root, log.max_count + 1)?;
+    let mut records = Vec::new();
+    for (i, operation) in operations.iter().take(log.max_count).enumerate() {
+        let before = match operations.get(i + 1) {
+            Some(previous) => ctf::parse(&previous.ctf).unwrap_or_default(),
+            None => ctf::CTF::default(),
+        };
+        let after = ctf::parse(&operation.ctf).unwrap_or_default();
+        records.push(Operation {
+            id: operation.short_id.clone(),
+            time: OffsetDateTime::from_unix_timestamp(operation.time)?.format(&Rfc3339)?,
+            author: operation.author.clone(),
+            summary: operation.summary.clone(),
+            changes: ctf::diff(&before, &after),
+        });
+    }
+    format.show(ui, &records, || {
+        for operation in &records {
+            ui.message(&format!(
+                "{} {} {} {}",
+                operation.id, operation.time, operation.author, operation.summary
+            ));
+            for change in &operation.changes {
+                ui.message(&format!("    {}", change));
+            }
+        }
+        Ok(())
+    })
+}
@@ -1,5 +1,3 @@
-use std::io::{stdin, stdout, Write};
-
 use clap::Parser;
 
 use anyhow::{anyhow, Result};
@@ -8,6 +6,7 @@ use crate::ctf;
 use crate::engines;
 use crate::git;
 use crate::http;
+use crate::Ui;
 use std::path::PathBuf;
 
 #[derive(Parser)]
@@ -17,15 +16,11 @@ pub struct Login {
     pub name: String,
 }
 
-pub async fn run(login: Login, current_dir: PathBuf) -> Result<()> {
+pub async fn run(login: Login, current_dir: PathBuf, ui: &dyn Ui) -> Result<()> {
     let mut context = ctf::load(current_dir)?;
     let remote = ctf::find_remote_mut(&mut context.ctf, &login.name)?;
-    print!("Login: ");
-    stdout().flush()?;
-    let mut username = String::new();
-    stdin().read_line(&mut username)?;
-    username.truncate(username.trim_end().len());
-    let password = rpassword::prompt_password("Password: ")?;
+    let username = ui.prompt("Login: ")?;
+    let password = ui.password("Password: ")?;
     let client = http::mk_client(&remote.rewrite_rules)?;
     if remote.engine == "auto" {
         remote.engine = engines::detect(&client, remote).await?;
@@ -0,0 +1,43 @@
+use anyhow::{anyhow, bail, Result};
+use clap::Parser;
+
+use crate::ctf;
+use crate::Ui;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Parser)]
+pub struct MergeDriver {
+    /// Common ancestor version (%O)
+    pub base: PathBuf,
+
+    /// Our version (%A), which is replaced with the result
+    pub ours: PathBuf,
+
+    /// Their version (%B)
+    pub theirs: PathBuf,
+}
+
+/// Files that are added on both sides have an empty base.
+fn read_ctf(path: &Path) -> Result<ctf::CTF> {
+    ctf::parse(&fs::read(path)?).map_err(|e| anyhow!("Could not parse {}: {}", path.display(), e))
+}
+
+pub fn run(merge_driver: MergeDriver, current_dir: PathBuf, ui: &dyn Ui) -> Result<()> {
+    let base = read_ctf(&current_dir.join(&merge_driver.base))?;
+    let ours_path = current_dir.join(&merge_driver.ours);
+    let ours = read_ctf(&ours_path)?;
+    let theirs = read_ctf(&current_dir.join(&merge_driver.theirs))?;
+    let (ctf, conflicts) = ctf::merge3(base, ours, theirs);
+    fs::write(&ours_path, serde_yaml::to_string(&ctf)?)?;
+    if !conflicts.is_empty() {
+        for conflict in &conflicts {
+            ui.warn(conflict);
+        }
+        bail!(
+            "{} conflicts in .ctf, our version was kept",
+            conflicts.len()
+        );
+    }
+    Ok(())
+}
@@ -1,10 +1,17 @@
+pub mod ad;
 pub mod binary;
 pub mod challenge;
 pub mod checkout;
 pub mod docker;
 pub mod exploit;
 pub mod fetch;
+pub mod flag;
 pub mod init;
+pub mod kernel;
+pub mod log;
 pub mod login;
+pub mod merge_driver;
 pub mod remote;
 pub mod service;
+pub mod sync;
+pub mod undo;
@@ -1,8 +1,10 @@
-use anyhow::{bail, Result};
+use anyhow::Result;
 use clap::Parser;
 
 use crate::ctf;
 use crate::git;
+use crate::ui::Format;
+use crate::{Ui, Workspace};
 use std::path::PathBuf;
 
 #[derive(Parser)]
@@ -34,6 +36,13 @@ enum SubCommand {
     SetEngine(SetEngine),
 }
 
+impl Remote {
+    /// Whether the subcommand prints records, which `--format` applies to.
+    pub fn prints_records(&self) -> bool {
+        matches!(self.subcmd, SubCommand::Show(_) | SubCommand::GetEngine(_))
+    }
+}
+
 #[derive(Parser)]
 pub struct Show {}
 
@@ -67,44 +76,37 @@ pub struct SetEngine {
     pub engine: String,
 }
 
-pub fn run(remote: Remote, current_dir: PathBuf) -> Result<()> {
+pub fn run(remote: Remote, current_dir: PathBuf, ui: &dyn Ui, format: Format) -> Result<()> {
     let mut context = ctf::load(current_dir)?;
     match remote.subcmd {
-        SubCommand::Show(_show) => {
-            for remote in context.ctf.remotes {
-                println!("{}", remote.name)
-            }
-        }
-        SubCommand::Add(add) => {
-            let existing = context
+        SubCommand::Show(_show) => format.show(ui, &context.ctf.remotes, || {
+            let rows = context
                 .ctf
                 .remotes
                 .iter()
-                .find(|remote| remote.name == add.name);
-            if existing.is_some() {
-                bail!("Remote {} already exists", add.name);
-            }
-            let message = format!("Add remote {} pointing to {}", add.name, add.url);
-            context.ctf.remotes.push(ctf::Remote {
-                name: add.name,
-                url: add.url,
-                engine: ctf::default_engine(),
-                rewrite_rules: Vec::new(),
-            });
-            git::commit(&context, &message)?;
+                .map(|remote| {
+                    vec![
+                        remote.name.clone(),
+                        remote.url.clone(),
+                        remote.engine.clone(),
+                    ]
+                })
+                .collect();
+            ui.table(&["NAME", "URL", "ENGINE"], rows);
+            Ok(())
+        })?,
+        SubCommand::Add(add) => {
+            Workspace::from(context).add_remote(&add.name, &add.url)?;
         }
         SubCommand::Rm(rm) => {
-            let message = format!("Remove remote {}", rm.name);
-            let n_remotes = context.ctf.remotes.len();
-            context.ctf.remotes.retain(|remote| remote.name != rm.name);
-            if context.ctf.remotes.len() + 1 != n_remotes {
-                bail!("Remote {} does not exist", rm.name);
-            }
-            git::commit(&context, &message)?;
+            Workspace::from(context).remove_remote(&rm.name)?;
         }
         SubCommand::GetEngine(get_engine) => {
             let remote = ctf::find_remote(&context.ctf, &get_engine.name)?;
-            println!("{}", remote.engine);
+            format.show(ui, &remote.engine, || {
+                ui.message(&remote.engine);
+                Ok(())
+            })?;
         }
         SubCommand::SetEngine(set_engine) => {
             let message = format!(
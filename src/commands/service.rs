@@ -1,9 +1,9 @@
-use anyhow::{bail, Result};
+use anyhow::Result;
 use clap::Parser;
 
-use crate::ctf::{find_service_mut, resolve_challenge_mut, try_find_service_mut};
-use crate::option;
-use crate::{ctf, git};
+use crate::ctf::resolve_challenge_mut;
+use crate::ui::Format;
+use crate::{ctf, Ui, Workspace};
 use std::path::PathBuf;
 
 #[derive(Parser)]
@@ -31,6 +31,13 @@ enum SubCommand {
     Rm(Rm),
 }
 
+impl Service {
+    /// Whether the subcommand prints records, which `--format` applies to.
+    pub fn prints_records(&self) -> bool {
+        matches!(self.subcmd, SubCommand::Show(_))
+    }
+}
+
 #[derive(Parser)]
 pub struct Show {}
 
@@ -58,7 +65,7 @@ pub struct Rm {
     pub name: String,
 }
 
-pub fn run(service: Service, current_dir: PathBuf) -> Result<()> {
+pub fn run(service: Service, current_dir: PathBuf, ui: &dyn Ui, format: Format) -> Result<()> {
     let mut context = ctf::load(current_dir)?;
     let (challenge, _) = resolve_challenge_mut(
         &mut context.ctf,
@@ -66,51 +73,30 @@ pub fn run(service: Service, current_dir: PathBuf) -> Result<()> {
         &context.cwd,
         PathBuf::new(),
     )?;
+    let challenge_name = challenge.name.clone();
+    let mut workspace = Workspace::from(context);
     match service.subcmd {
         SubCommand::Show(_show) => {
-            for service in &challenge.services {
-                println!(
-                    "{} - {}",
-                    &service.name.as_deref().unwrap_or("<none>"),
-                    service.url
-                )
-            }
-        }
-        SubCommand::Add(add) => {
-            let existing = try_find_service_mut(challenge, &add.name);
-            if existing.is_some() {
-                bail!("Service {} already exists", &add.name);
-            }
-            let message = format!("Add service {} to challenge {}", &add.name, &challenge.name);
-            challenge.services.push(ctf::Service {
-                name: Some(add.name),
-                url: add.url,
-            });
-            git::commit(&context, &message)?;
+            let services = workspace.services(&challenge_name)?;
+            format.show(ui, services, || {
+                let rows = services
+                    .iter()
+                    .map(|service| {
+                        vec![
+                            service.name.as_deref().unwrap_or("<none>").to_string(),
+                            service.url.clone(),
+                        ]
+                    })
+                    .collect();
+                ui.table(&["NAME", "URL"], rows);
+                Ok(())
+            })?
         }
+        SubCommand::Add(add) => workspace.add_service(&challenge_name, &add.name, &add.url)?,
         SubCommand::SetUrl(set_url) => {
-            let message = format!(
-                "Set service {} URL to {} in challenge {}",
-                set_url.name, set_url.url, challenge.name
-            );
-            let service = find_service_mut(challenge, &set_url.name)?;
-            service.url = set_url.url;
-            git::commit(&context, &message)?;
-        }
-        SubCommand::Rm(rm) => {
-            let message = format!(
-                "Remove service {} from challenge {}",
-                &rm.name, &challenge.name
-            );
-            let n_services = challenge.services.len();
-            challenge
-                .services
-                .retain(|service| !option::contains(&service.name, &rm.name));
-            if challenge.services.len() + 1 != n_services {
-                bail!("Service {} does not exist", &rm.name);
-            }
-            git::commit(&context, &message)?;
+            workspace.set_service_url(&challenge_name, &set_url.name, &set_url.url)?
         }
+        SubCommand::Rm(rm) => workspace.remove_service(&challenge_name, &rm.name)?,
     }
     Ok(())
 }
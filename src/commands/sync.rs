@@ -0,0 +1,35 @@
+use anyhow::{bail, Result};
+use clap::Parser;
+
+use crate::{ctf, git};
+use std::path::PathBuf;
+
+#[derive(Parser)]
+pub struct Sync {
+    /// Git remote shared by the team
+    #[clap(default_value = "origin")]
+    pub remote: String,
+}
+
+pub fn run(sync: Sync, current_dir: PathBuf) -> Result<()> {
+    let context = ctf::load(current_dir)?;
+    /* Clones do not have the git config, and older repositories do not have .gitattributes. */
+    git::setup_merge_driver(&context.root)?;
+    git::commit(&context, "Configure .ctf merge driver")?;
+    let branch = git::current_branch(&context.root)?;
+    git::fetch(&context.root, &sync.remote)?;
+    let upstream = format!("{}/{}", sync.remote, branch);
+    if git::rev_exists(&context.root, &upstream)? && !git::rebase(&context.root, &upstream)? {
+        let conflicts = git::conflicts(&context.root)?;
+        if conflicts.is_empty() {
+            bail!("Could not rebase onto {}", upstream);
+        }
+        bail!(
+            "Could not rebase onto {} because of conflicts in {}; resolve them, do git rebase \
+             --continue and ctf sync again",
+            upstream,
+            conflicts.join(", ")
+        );
+    }
+    git::push(&context.root, &sync.remote, &branch)
+}
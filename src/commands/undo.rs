@@ -0,0 +1,93 @@
+use anyhow::{bail, Result};
+use clap::Parser;
+
+use crate::{ctf, git, Ui};
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Parser)]
+pub struct Undo {
+    /// Number of operations to undo
+    #[clap(default_value = "1")]
+    pub count: usize,
+}
+
+pub fn run(undo: Undo, current_dir: PathBuf, ui: &dyn Ui) -> Result<()> {
+    let mut context = ctf::load(current_dir)?;
+    if undo.count == 0 {
+        return Ok(());
+    }
+    let operations = git::history(&context.root, undo.count + 1)?;
+    if operations.len() <= undo.count {
+        bail!("Cannot undo the initial commit");
+    }
+    let target = &operations[undo.count];
+    let ctf = ctf::parse(&target.ctf)?;
+    /* Default alternatives may be the original files, which ctf binary add did not create. */
+    let defaults: HashSet<PathBuf> = context
+        .ctf
+        .challenges
+        .iter()
+        .flat_map(|challenge| {
+            challenge
+                .binaries
+                .iter()
+                .map(move |binary| PathBuf::from(&challenge.name).join(&binary.name))
+        })
+        .collect();
+    let mut paths = git::restore(&context.root, target.id, |path| defaults.contains(path))?;
+    let old = std::mem::replace(&mut context.ctf, ctf);
+    /* Binaries that are not tracked, e.g. the ones that were too big. */
+    for challenge in &old.challenges {
+        for binary in &challenge.binaries {
+            for alternative in &binary.alternatives {
+                let exists = ctf::find_challenge(&context.ctf, &challenge.name)
+                    .and_then(|challenge| ctf::find_binary(challenge, &binary.name))
+                    .and_then(|binary| ctf::find_alternative(binary, &alternative.name))
+                    .is_ok();
+                let path = ctf::alternative_path(
+                    &context.root,
+                    &challenge.name,
+                    &binary.name,
+                    &alternative.name,
+                );
+                if !exists && path.exists() {
+                    fs::remove_file(&path)?;
+                    paths.push(path);
+                }
+            }
+        }
+    }
+    for challenge in &context.ctf.challenges {
+        for binary in &challenge.binaries {
+            if let Some(default) = &binary.default_alternative {
+                let alternative_path =
+                    ctf::alternative_path(&context.root, &challenge.name, &binary.name, default);
+                let default_path =
+                    ctf::default_alternative_path(&context.root, &challenge.name, &binary.name);
+                if alternative_path.exists()
+                    && fs::read(&alternative_path).ok() != fs::read(&default_path).ok()
+                {
+                    fs::copy(&alternative_path, &default_path)?;
+                    paths.push(default_path);
+                }
+            }
+        }
+    }
+    let message = match undo.count {
+        1 => format!("Undo \"{}\"", operations[0].summary),
+        count => {
+            let mut message = format!("Undo {} operations\n", count);
+            for operation in &operations[..count] {
+                message.push_str(&format!("\n{}", operation.summary));
+            }
+            message
+        }
+    };
+    git::commit_files(&context, &message, &paths)?;
+    for change in ctf::diff(&old, &context.ctf) {
+        ui.message(&change);
+    }
+    Ok(())
+}
@@ -0,0 +1,143 @@
+use anyhow::{anyhow, bail, Result};
+use std::io::{Read, Write};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Gzip,
+    Xz,
+    Lzma,
+    Zstd,
+    /* Legacy LZ4 framing used by the Linux kernel. */
+    Lz4,
+}
+
+impl Format {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Format::Gzip => "gzip",
+            Format::Xz => "xz",
+            Format::Lzma => "lzma",
+            Format::Zstd => "zstd",
+            Format::Lz4 => "lz4",
+        }
+    }
+
+    fn magic(&self) -> &'static [u8] {
+        match self {
+            Format::Gzip => b"\x1f\x8b\x08",
+            Format::Xz => b"\xfd7zXZ\x00",
+            Format::Lzma => b"\x5d\x00\x00",
+            Format::Zstd => b"\x28\xb5\x2f\xfd",
+            Format::Lz4 => b"\x02\x21\x4c\x18",
+        }
+    }
+}
+
+const FORMATS: [Format; 5] = [
+    Format::Gzip,
+    Format::Xz,
+    Format::Lzma,
+    Format::Zstd,
+    Format::Lz4,
+];
+
+/// Returns the compression format of the data, if it is compressed.
+pub fn detect(bytes: &[u8]) -> Option<Format> {
+    FORMATS
+        .iter()
+        .find(|format| bytes.starts_with(format.magic()))
+        .copied()
+}
+
+/// Returns offsets and formats of all potential compressed streams inside the data.
+pub fn find_streams(bytes: &[u8]) -> Vec<(usize, Format)> {
+    let mut result = Vec::new();
+    for offset in 0..bytes.len() {
+        if let Some(format) = detect(&bytes[offset..]) {
+            result.push((offset, format));
+        }
+    }
+    result
+}
+
+const LZ4_BLOCK_SIZE: usize = 8 << 20;
+
+fn lz4_decompress(bytes: &[u8], output: &mut Vec<u8>) -> Result<()> {
+    let mut pos = Format::Lz4.magic().len();
+    let mut buffer = vec![0; LZ4_BLOCK_SIZE];
+    while pos + 4 <= bytes.len() {
+        let size = u32::from_le_bytes(bytes[pos..pos + 4].try_into()?) as usize;
+        /* Another magic or a trailing uncompressed size mean the end of the stream. */
+        if size == u32::from_le_bytes(Format::Lz4.magic().try_into()?) as usize
+            || size > lz4_flex::block::get_maximum_output_size(LZ4_BLOCK_SIZE)
+        {
+            break;
+        }
+        pos += 4;
+        let block = bytes
+            .get(pos..pos + size)
+            .ok_or_else(|| anyhow!("Truncated LZ4 block"))?;
+        let n = lz4_flex::block::decompress_into(block, &mut buffer)?;
+        output.extend_from_slice(&buffer[..n]);
+        pos += size;
+    }
+    Ok(())
+}
+
+fn decompress_into(format: Format, bytes: &[u8], output: &mut Vec<u8>) -> Result<()> {
+    match format {
+        Format::Gzip => {
+            flate2::read::GzDecoder::new(bytes).read_to_end(output)?;
+        }
+        Format::Xz => lzma_rs::xz_decompress(&mut &bytes[..], output)?,
+        Format::Lzma => lzma_rs::lzma_decompress(&mut &bytes[..], output)?,
+        Format::Zstd => {
+            ruzstd::StreamingDecoder::new(bytes)
+                .map_err(|e| anyhow!("{}", e))?
+                .read_to_end(output)?;
+        }
+        Format::Lz4 => lz4_decompress(bytes, output)?,
+    }
+    Ok(())
+}
+
+/// Decompresses a single stream, ignoring the trailing data.
+pub fn decompress(format: Format, bytes: &[u8]) -> Result<Vec<u8>> {
+    let mut output = Vec::new();
+    decompress_into(format, bytes, &mut output)?;
+    Ok(output)
+}
+
+/// Decompresses as much of a single stream as possible.
+///
+/// Streams embedded into other files are usually followed by unrelated data, which some
+/// decoders treat as an error. What has been decoded up to that point is still useful.
+pub fn decompress_prefix(format: Format, bytes: &[u8]) -> Vec<u8> {
+    let mut output = Vec::new();
+    let _ = decompress_into(format, bytes, &mut output);
+    output
+}
+
+/// Decompresses the data if it is compressed, otherwise returns it as is.
+///
+/// Trailing padding after a compressed stream is tolerated.
+pub fn maybe_decompress(bytes: Vec<u8>) -> Result<(Option<Format>, Vec<u8>)> {
+    match detect(&bytes) {
+        Some(format) => {
+            let mut output = Vec::new();
+            match decompress_into(format, &bytes, &mut output) {
+                Err(e) if output.is_empty() => {
+                    bail!("Could not decompress {} data: {}", format.name(), e)
+                }
+                _ => Ok((Some(format), output)),
+            }
+        }
+        None => Ok((None, bytes)),
+    }
+}
+
+pub fn gzip(bytes: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+    encoder.write_all(bytes)?;
+    Ok(encoder.finish()?)
+}
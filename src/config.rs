@@ -0,0 +1,69 @@
+use crate::container::BackendKind;
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+/// Returns the per-user configuration directory, e.g., `~/.config/ctftool`.
+pub fn config_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|dir| dir.join("ctftool"))
+}
+
+/// Returns the per-user cache directory, e.g., `~/.cache/ctftool`.
+pub fn cache_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .map(|dir| dir.join("ctftool"))
+}
+
+/// Per-user settings from `~/.config/ctftool/config.yml`.
+#[derive(Default, Deserialize)]
+pub struct Config {
+    /// Container backend; auto-detected if not set
+    #[serde(default)]
+    pub container_backend: Option<BackendKind>,
+
+    /// Whether containers get X11 forwarding and GUI tools; auto-detected if not set
+    #[serde(default)]
+    pub gui: Option<bool>,
+
+    /// Where downloaded packages are kept; `~/.cache/ctftool/packages` if not set
+    #[serde(default)]
+    pub package_cache: Option<PathBuf>,
+}
+
+impl Config {
+    /// Returns whether GUI is enabled, which by default depends on whether there is a display.
+    pub fn gui(&self) -> bool {
+        self.gui
+            .unwrap_or_else(|| env::var_os("DISPLAY").is_some_and(|display| !display.is_empty()))
+    }
+
+    /// Returns the package cache directory.
+    pub fn package_cache(&self) -> Result<PathBuf> {
+        self.package_cache
+            .clone()
+            .or_else(|| cache_dir().map(|dir| dir.join("packages")))
+            .ok_or_else(|| anyhow!("Could not determine the package cache directory"))
+    }
+}
+
+pub fn load() -> Result<Config> {
+    let path = match config_dir() {
+        Some(config_dir) => config_dir.join("config.yml"),
+        None => return Ok(Config::default()),
+    };
+    match fs::read_to_string(path) {
+        Ok(config) => Ok(serde_yaml::from_str(&config)?),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(Config::default()),
+        Err(e) => Err(e.into()),
+    }
+}
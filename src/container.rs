@@ -0,0 +1,206 @@
+use crate::config::Config;
+use anyhow::{anyhow, bail, Result};
+use log::warn;
+use serde::Deserialize;
+use std::env;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    Docker,
+    Podman,
+}
+
+/// State of a docker-compose.yml service container.
+#[derive(Debug)]
+pub struct ContainerStatus {
+    pub service: String,
+    pub state: String,
+    pub image: String,
+    pub ports: Vec<String>,
+}
+
+fn yaml_str(value: &serde_yaml::Value, key: &str) -> String {
+    value
+        .get(key)
+        .and_then(serde_yaml::Value::as_str)
+        .unwrap_or("")
+        .to_string()
+}
+
+/// Parses `compose ps --format json` output, which is either an array or one object per line.
+pub fn parse_ps(output: &str) -> Result<Vec<ContainerStatus>> {
+    let containers: Vec<serde_yaml::Value> = if output.trim_start().starts_with('[') {
+        serde_yaml::from_str(output)?
+    } else {
+        output
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(serde_yaml::from_str)
+            .collect::<Result<_, _>>()?
+    };
+    Ok(containers
+        .iter()
+        .map(|container| ContainerStatus {
+            service: yaml_str(container, "Service"),
+            state: yaml_str(container, "State"),
+            image: yaml_str(container, "Image"),
+            ports: container
+                .get("Publishers")
+                .and_then(serde_yaml::Value::as_sequence)
+                .map(|publishers| {
+                    let mut ports: Vec<String> = publishers
+                        .iter()
+                        .filter_map(|publisher| {
+                            let published = publisher.get("PublishedPort")?.as_u64()?;
+                            let target = publisher.get("TargetPort")?.as_u64()?;
+                            (published != 0).then(|| format!("{}->{}", published, target))
+                        })
+                        .collect();
+                    ports.dedup();
+                    ports
+                })
+                .unwrap_or_default(),
+        })
+        .collect())
+}
+
+/// Parses an image creation time, which is either RFC 3339 (Docker) or Go's default time
+/// format, e.g. `2024-05-11 10:20:30.123 +0000 UTC` (Podman).
+pub fn parse_created(created: &str) -> Result<OffsetDateTime> {
+    let rfc3339 = match created.split(' ').collect::<Vec<_>>().as_slice() {
+        [date, time, offset, ..] if offset.len() == 5 => {
+            format!("{}T{}{}:{}", date, time, &offset[..3], &offset[3..])
+        }
+        _ => created.to_string(),
+    };
+    OffsetDateTime::parse(&rfc3339, &Rfc3339)
+        .map_err(|e| anyhow!("Could not parse image creation time {}: {}", created, e))
+}
+
+/// Formats a duration as a coarse age, e.g. 3d or 5h.
+pub fn format_age(seconds: i64) -> String {
+    match seconds {
+        s if s >= 86400 => format!("{}d", s / 86400),
+        s if s >= 3600 => format!("{}h", s / 3600),
+        s if s >= 60 => format!("{}m", s / 60),
+        s => format!("{}s", s.max(0)),
+    }
+}
+
+fn output(command: &mut Command) -> Result<String> {
+    let output = command.stderr(Stdio::inherit()).output()?;
+    if !output.status.success() {
+        bail!("Process exited with non-zero code: {}", output.status);
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+/// Container engine that runs docker-compose.yml files.
+pub trait Backend {
+    fn name(&self) -> &'static str;
+
+    /// Returns a `compose` command for the given file.
+    fn compose(&self, file: &str) -> Command {
+        let mut command = Command::new(self.name());
+        command.args(["compose", &format!("--file={}", file)]);
+        command
+    }
+
+    /// Returns the `xhost` argument that lets containers connect to the X server.
+    fn xhost_grant(&self) -> String;
+
+    /// Returns the containers of all services, including the stopped ones.
+    fn ps(&self, dir: &Path, file: &str) -> Result<Vec<ContainerStatus>> {
+        parse_ps(&output(
+            self.compose(file)
+                .args(["ps", "--all", "--format=json"])
+                .current_dir(dir),
+        )?)
+    }
+
+    /// Returns when an image was built, or None if it does not exist.
+    fn image_created(&self, image: &str) -> Result<Option<OffsetDateTime>> {
+        let output = Command::new(self.name())
+            .args(["image", "inspect", "--format={{.Created}}", image])
+            .stderr(Stdio::null())
+            .output()?;
+        if !output.status.success() {
+            return Ok(None);
+        }
+        parse_created(String::from_utf8(output.stdout)?.trim()).map(Some)
+    }
+}
+
+pub struct Docker;
+
+impl Backend for Docker {
+    fn name(&self) -> &'static str {
+        "docker"
+    }
+
+    fn xhost_grant(&self) -> String {
+        /* The daemon runs containers as root. */
+        "+local:root".into()
+    }
+}
+
+pub struct Podman;
+
+impl Backend for Podman {
+    fn name(&self) -> &'static str {
+        "podman"
+    }
+
+    fn xhost_grant(&self) -> String {
+        /* Rootless containers run as the current user. */
+        match env::var("USER") {
+            Ok(user) => format!("+SI:localuser:{}", user),
+            Err(_) => "+local:".into(),
+        }
+    }
+}
+
+fn in_path(program: &str) -> bool {
+    env::var_os("PATH")
+        .map(|path| env::split_paths(&path).any(|dir| dir.join(program).is_file()))
+        .unwrap_or(false)
+}
+
+fn new_backend(kind: BackendKind) -> Box<dyn Backend> {
+    match kind {
+        BackendKind::Docker => Box::new(Docker),
+        BackendKind::Podman => Box::new(Podman),
+    }
+}
+
+/// Returns the configured backend, or the first one that is installed.
+pub fn backend(config: &Config) -> Result<Box<dyn Backend>> {
+    if let Some(kind) = config.container_backend {
+        return Ok(new_backend(kind));
+    }
+    for kind in [BackendKind::Docker, BackendKind::Podman] {
+        let backend = new_backend(kind);
+        if in_path(backend.name()) {
+            return Ok(backend);
+        }
+    }
+    bail!("Neither docker nor podman is installed")
+}
+
+/// Lets containers connect to the X server; failures are not fatal, e.g., on Wayland.
+pub fn allow_x11(backend: &dyn Backend) {
+    if !in_path("xhost") {
+        return;
+    }
+    let result = Command::new("xhost").arg(backend.xhost_grant()).status();
+    match result {
+        Ok(status) if status.success() => {}
+        Ok(status) => warn!("xhost exited with non-zero code: {}", status),
+        Err(e) => warn!("Could not run xhost: {}", e),
+    }
+}
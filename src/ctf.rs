@@ -5,6 +5,7 @@ use std::path::{Path, PathBuf};
 use crate::option;
 use crate::os_str::os_str_to_str;
 use crate::path::relativize;
+use crate::qemu;
 use cookie_store::CookieStore;
 use log::warn;
 use regex::Regex;
@@ -14,25 +15,71 @@ use url::Url;
 
 use anyhow::{anyhow, bail, Error, Result};
 
+use crate::error;
 use crate::http;
 
-#[derive(Default, Serialize, Deserialize)]
+#[derive(Clone, Default, Serialize, Deserialize)]
 pub struct CTF {
     #[serde(default)]
     pub name: String,
+    /// Regex that matches the flags, see flag::DEFAULT_FORMAT
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flag_format: Option<String>,
     #[serde(default)]
     pub remotes: Vec<Remote>,
     #[serde(default)]
     pub challenges: Vec<Challenge>,
+    /// Attack-defense settings, absent for jeopardy CTFs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attack_defense: Option<AttackDefense>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct AttackDefense {
+    /// Team addresses with octet ranges, e.g. 10.60.1-40.1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub teams: Option<String>,
+    /// Game server URL with a JSON list of team addresses
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub teams_url: Option<String>,
+    /// Addresses that are not attacked, e.g. our own
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+    /// Seconds between attacks
+    #[serde(default = "default_tick")]
+    pub tick: u64,
+    /// Seconds after which the exploit is killed
+    #[serde(default = "default_timeout")]
+    pub timeout: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub submission: Option<FlagSubmission>,
+}
+
+pub fn default_tick() -> u64 {
+    60
+}
+
+pub fn default_timeout() -> u64 {
+    30
+}
+
+/// Where the game server accepts flags.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "protocol", rename_all = "lowercase")]
+pub enum FlagSubmission {
+    /// One flag per line, one response line per flag
+    Tcp { address: String },
+    /// JSON list of flags, X-Team-Token header from .ctfcredentials
+    Http { url: String },
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct RewriteRule {
     pub regex: String,
     pub rep: String,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Remote {
     pub name: String,
     pub url: String,
@@ -46,36 +93,138 @@ pub fn default_engine() -> String {
     "auto".into()
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Challenge {
     pub name: String,
     pub description: String,
     pub binaries: Vec<Binary>,
     pub services: Vec<Service>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub flags: Vec<Flag>,
+    /// Who is working on the challenge
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub claims: Vec<Signature>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<StatusChange>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notes: Vec<Note>,
 }
 
-#[derive(Serialize, Deserialize)]
+/// Who changed something and when.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Signature {
+    /// Git author, e.g. `ctf <ctf@localhost>`
+    pub author: String,
+    /// Change time in RFC 3339 format
+    pub time: String,
+}
+
+impl Signature {
+    /// Returns the author name without the email.
+    pub fn name(&self) -> &str {
+        match self.author.split_once(" <") {
+            Some((name, _)) => name,
+            None => &self.author,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Todo,
+    Wip,
+    Solved,
+    Abandoned,
+}
+
+impl Status {
+    pub const ALL: [Status; 4] = [Status::Todo, Status::Wip, Status::Solved, Status::Abandoned];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Status::Todo => "todo",
+            Status::Wip => "wip",
+            Status::Solved => "solved",
+            Status::Abandoned => "abandoned",
+        }
+    }
+}
+
+impl std::str::FromStr for Status {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Status> {
+        Status::ALL
+            .into_iter()
+            .find(|status| status.name() == s)
+            .ok_or_else(|| anyhow!("No such status: {}, use todo, wip, solved or abandoned", s))
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct StatusChange {
+    pub value: Status,
+    #[serde(flatten)]
+    pub signature: Signature,
+}
+
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Note {
+    pub text: String,
+    #[serde(flatten)]
+    pub signature: Signature,
+}
+
+/// Flag captured by an exploit.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Flag {
+    pub value: String,
+    /// Capture time in RFC 3339 format
+    pub time: String,
+    /// Exploit revision, with a `-dirty` suffix if it had uncommitted changes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revision: Option<String>,
+    /// Where the exploit was run: local, docker or remote
+    pub target: String,
+    /// Scoreboard verdict, if the flag was submitted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub submission: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Binary {
     pub name: String,
     pub alternatives: Vec<BinaryAlternative>,
     pub default_alternative: Option<String>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Checksum {
     pub algorithm: String,
     pub value: String,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
+pub struct BytePatch {
+    pub offset: u64,
+    pub bytes: String,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct BinaryAlternative {
     pub name: String,
     pub url: Option<String>,
     #[serde(default)]
     pub checksum: Option<Checksum>,
+    /// Alternative this one is produced from by applying patches
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub patches: Vec<BytePatch>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Service {
     #[serde(default)]
     pub name: Option<String>,
@@ -85,7 +234,11 @@ pub struct Service {
 
 #[derive(Default, Serialize, Deserialize)]
 pub struct Credentials {
+    #[serde(default)]
     pub remotes: Vec<RemoteCredentials>,
+    /// X-Team-Token for the attack-defense flag submission over HTTP
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub team_token: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -122,6 +275,14 @@ fn load_credentials(root: &Path) -> Result<Credentials> {
     }
 }
 
+/// Parses `.ctf` contents; empty contents, e.g. before the initial commit, mean an empty CTF.
+pub fn parse(yaml: &[u8]) -> Result<CTF> {
+    if yaml.iter().all(u8::is_ascii_whitespace) {
+        return Ok(CTF::default());
+    }
+    Ok(serde_yaml::from_slice(yaml)?)
+}
+
 pub fn load(cwd: PathBuf) -> Result<Context> {
     let mut root = cwd.clone();
     let mut path = Vec::new();
@@ -142,11 +303,7 @@ pub fn load(cwd: PathBuf) -> Result<Context> {
             Err(e) if e.kind() == ErrorKind::NotFound => {
                 match root.file_name() {
                     Some(component) => path.push(os_str_to_str(component)?.to_owned()),
-                    None => {
-                        break Err(anyhow!(
-                            "No .ctf file in the current or any of the parent directories"
-                        ));
-                    }
+                    None => break Err(Error::new(error::Error::NotAWorkspace(cwd))),
                 }
                 root.pop();
             }
@@ -161,6 +318,8 @@ fn ignore(ctf: &CTF) -> Vec<String> {
         "!**/".into(),
         "!/.ctf".into(),
         "!/.gitignore".into(),
+        "!/.gitattributes".into(),
+        "!/.templates/**".into(),
     ];
     for challenge in &ctf.challenges {
         result.push(format!("!/{}/image/Dockerfile", challenge.name));
@@ -168,6 +327,8 @@ fn ignore(ctf: &CTF) -> Vec<String> {
         result.push(format!("!/{}/pwnit.py", challenge.name));
         result.push(format!("!/{}/pwnit.c", challenge.name));
         result.push(format!("!/{}/Makefile", challenge.name));
+        result.push(format!("!/{}/{}", challenge.name, qemu::LAUNCHER));
+        result.push(format!("!/{}/{}", challenge.name, qemu::GDB_SCRIPT));
         for binary in &challenge.binaries {
             if binary.default_alternative.is_some() {
                 result.push(format!("!/{}/{}", challenge.name, binary.name));
@@ -188,6 +349,11 @@ pub fn store(context: &Context) -> Result<()> {
         context.root.join(".gitignore"),
         ignore(&context.ctf).join("\n") + "\n",
     )?;
+    /* .gitignore is generated from .ctf, so its conflicts are not real ones. */
+    fs::write(
+        context.root.join(".gitattributes"),
+        "/.ctf merge=ctf\n/.gitignore merge=union\n",
+    )?;
     fs::write(
         context.root.join(".ctf"),
         serde_yaml::to_string(&context.ctf)?,
@@ -274,6 +440,8 @@ pub fn binary_from_url(url: &str) -> Result<Binary> {
             name: "orig".into(),
             url: Some(url.into()),
             checksum: None,
+            base: None,
+            patches: Vec::new(),
         }],
         default_alternative: None,
     })
@@ -329,6 +497,8 @@ async fn resolve_google_drive_binary(client: &http::Client, id: &str) -> Result<
                     name: "orig".into(),
                     url: Some(initial_url),
                     checksum: None,
+                    base: None,
+                    patches: Vec::new(),
                 }],
                 default_alternative: None,
             });
@@ -382,6 +552,10 @@ fn merge_binary_alternatives(
     if binary_alternative2.checksum.is_some() {
         binary_alternative.checksum = binary_alternative2.checksum;
     }
+    if binary_alternative2.base.is_some() {
+        binary_alternative.base = binary_alternative2.base;
+        binary_alternative.patches = binary_alternative2.patches;
+    }
 }
 
 fn merge_binaries(binary: &mut Binary, binary2: Binary) {
@@ -429,12 +603,42 @@ fn merge_challenges(challenge: &mut Challenge, challenge2: Challenge) {
             None => challenge.services.push(service2),
         }
     }
+    for flag2 in challenge2.flags {
+        if !challenge.flags.iter().any(|flag| flag.value == flag2.value) {
+            challenge.flags.push(flag2);
+        }
+    }
+    for claim2 in challenge2.claims {
+        if !challenge
+            .claims
+            .iter()
+            .any(|claim| claim.author == claim2.author)
+        {
+            challenge.claims.push(claim2);
+        }
+    }
+    /* Remotes do not know the status, so keep the latest one. */
+    if let Some(status2) = challenge2.status {
+        match &challenge.status {
+            Some(status) if status.signature.time >= status2.signature.time => {}
+            _ => challenge.status = Some(status2),
+        }
+    }
+    for note2 in challenge2.notes {
+        if !challenge.notes.contains(&note2) {
+            challenge.notes.push(note2);
+        }
+    }
 }
 
 pub fn merge(ctf: &mut CTF, ctf2: CTF) {
     if !ctf2.name.is_empty() {
         ctf.name = ctf2.name;
     }
+    /* Do not override the format that the user has set. */
+    if ctf.flag_format.is_none() {
+        ctf.flag_format = ctf2.flag_format;
+    }
     for challenge2 in ctf2.challenges {
         let existing = ctf
             .challenges
@@ -447,18 +651,394 @@ pub fn merge(ctf: &mut CTF, ctf2: CTF) {
     }
 }
 
+/// Three-way merge of `.ctf` versions; disagreements that cannot be resolved are collected as
+/// conflicts, in which case our version wins.
+struct Merge3 {
+    conflicts: Vec<String>,
+}
+
+fn same<T: Serialize>(a: &T, b: &T) -> bool {
+    serde_yaml::to_value(a).ok() == serde_yaml::to_value(b).ok()
+}
+
+impl Merge3 {
+    fn value<T: Serialize>(&mut self, path: &str, base: Option<T>, ours: T, theirs: T) -> T {
+        if same(&ours, &theirs) || base.as_ref().is_some_and(|base| same(base, &theirs)) {
+            ours
+        } else if base.as_ref().is_some_and(|base| same(base, &ours)) {
+            theirs
+        } else {
+            self.conflicts
+                .push(format!("{}: changed on both sides", path));
+            ours
+        }
+    }
+
+    /// Merges lists of items with unique keys, e.g. challenges by name.
+    fn list<T, K, M>(
+        &mut self,
+        path: &str,
+        base: Vec<T>,
+        ours: Vec<T>,
+        theirs: Vec<T>,
+        key: K,
+        merge_item: M,
+    ) -> Vec<T>
+    where
+        T: Serialize,
+        K: Fn(&T) -> String,
+        M: Fn(&mut Merge3, &str, Option<T>, T, T) -> T,
+    {
+        let take = |items: &mut Vec<Option<T>>, k: &str| {
+            items
+                .iter_mut()
+                .find(|item| item.as_ref().is_some_and(|item| key(item) == k))
+                .and_then(Option::take)
+        };
+        let mut base: Vec<Option<T>> = base.into_iter().map(Some).collect();
+        let mut theirs: Vec<Option<T>> = theirs.into_iter().map(Some).collect();
+        let mut result = Vec::new();
+        for ours in ours {
+            let k = key(&ours);
+            let item_path = format!("{}/{}", path, k);
+            match (take(&mut base, &k), take(&mut theirs, &k)) {
+                (base, Some(theirs)) => {
+                    result.push(merge_item(self, &item_path, base, ours, theirs))
+                }
+                (Some(base), None) => {
+                    if !same(&base, &ours) {
+                        self.conflicts
+                            .push(format!("{}: changed here, removed there", item_path));
+                        result.push(ours);
+                    }
+                }
+                (None, None) => result.push(ours),
+            }
+        }
+        for theirs in theirs.into_iter().flatten() {
+            let k = key(&theirs);
+            match take(&mut base, &k) {
+                Some(base) => {
+                    if !same(&base, &theirs) {
+                        self.conflicts
+                            .push(format!("{}/{}: removed here, changed there", path, k));
+                        result.push(theirs);
+                    }
+                }
+                None => result.push(theirs),
+            }
+        }
+        result
+    }
+
+    fn binary(&mut self, path: &str, base: Option<Binary>, ours: Binary, theirs: Binary) -> Binary {
+        let (base_alternatives, base_default) = match base {
+            Some(base) => (base.alternatives, Some(base.default_alternative)),
+            None => (Vec::new(), None),
+        };
+        Binary {
+            alternatives: self.list(
+                path,
+                base_alternatives,
+                ours.alternatives,
+                theirs.alternatives,
+                |alternative| alternative.name.clone(),
+                |merge, path, base, ours, theirs| merge.value(path, base, ours, theirs),
+            ),
+            default_alternative: self.value(
+                &format!("{}/default_alternative", path),
+                base_default,
+                ours.default_alternative,
+                theirs.default_alternative,
+            ),
+            name: ours.name,
+        }
+    }
+
+    fn challenge(
+        &mut self,
+        path: &str,
+        base: Option<Challenge>,
+        ours: Challenge,
+        theirs: Challenge,
+    ) -> Challenge {
+        let base = base.unwrap_or_else(|| Challenge {
+            name: ours.name.clone(),
+            description: String::new(),
+            binaries: Vec::new(),
+            services: Vec::new(),
+            flags: Vec::new(),
+            claims: Vec::new(),
+            status: None,
+            notes: Vec::new(),
+        });
+        /* Statuses never conflict, the latest one wins. */
+        let status = match (ours.status, theirs.status) {
+            (Some(ours), Some(theirs)) => match same(&base.status, &Some(ours.clone()))
+                || theirs.signature.time > ours.signature.time
+            {
+                true => Some(theirs),
+                false => Some(ours),
+            },
+            (ours, theirs) => match same(&base.status, &ours) {
+                true => theirs,
+                false => ours,
+            },
+        };
+        Challenge {
+            description: self.value(
+                &format!("{}/description", path),
+                Some(base.description),
+                ours.description,
+                theirs.description,
+            ),
+            binaries: self.list(
+                path,
+                base.binaries,
+                ours.binaries,
+                theirs.binaries,
+                |binary| binary.name.clone(),
+                Merge3::binary,
+            ),
+            services: self.list(
+                path,
+                base.services,
+                ours.services,
+                theirs.services,
+                |service| service.name.clone().unwrap_or_else(|| service.url.clone()),
+                |merge, path, base, ours, theirs| merge.value(path, base, ours, theirs),
+            ),
+            flags: self.list(
+                path,
+                base.flags,
+                ours.flags,
+                theirs.flags,
+                |flag| flag.value.clone(),
+                |merge, path, base, ours, theirs| merge.value(path, base, ours, theirs),
+            ),
+            claims: self.list(
+                path,
+                base.claims,
+                ours.claims,
+                theirs.claims,
+                |claim| claim.author.clone(),
+                |_, _, _, ours, _| ours,
+            ),
+            status,
+            notes: self.list(
+                path,
+                base.notes,
+                ours.notes,
+                theirs.notes,
+                |note| {
+                    format!(
+                        "{} {} {}",
+                        note.signature.time, note.signature.author, note.text
+                    )
+                },
+                |_, _, _, ours, _| ours,
+            ),
+            name: ours.name,
+        }
+    }
+}
+
+/// Merges two versions of `.ctf` that were changed independently of each other since the base
+/// version, e.g. by two teammates. Returns the result and the conflicts.
+pub fn merge3(base: CTF, ours: CTF, theirs: CTF) -> (CTF, Vec<String>) {
+    let mut merge = Merge3 {
+        conflicts: Vec::new(),
+    };
+    let ctf = CTF {
+        name: merge.value("name", Some(base.name), ours.name, theirs.name),
+        flag_format: merge.value(
+            "flag_format",
+            Some(base.flag_format),
+            ours.flag_format,
+            theirs.flag_format,
+        ),
+        remotes: merge.list(
+            "remotes",
+            base.remotes,
+            ours.remotes,
+            theirs.remotes,
+            |remote| remote.name.clone(),
+            |merge, path, base, ours, theirs| merge.value(path, base, ours, theirs),
+        ),
+        challenges: merge.list(
+            "challenges",
+            base.challenges,
+            ours.challenges,
+            theirs.challenges,
+            |challenge| challenge.name.clone(),
+            Merge3::challenge,
+        ),
+        attack_defense: merge.value(
+            "attack_defense",
+            Some(base.attack_defense),
+            ours.attack_defense,
+            theirs.attack_defense,
+        ),
+    };
+    (ctf, merge.conflicts)
+}
+
+/// Compares named items, e.g. challenges, and reports the added, removed and changed ones.
+fn diff_named<'a, T, K>(
+    kind: &str,
+    old: &'a [T],
+    new: &'a [T],
+    key: K,
+    result: &mut Vec<String>,
+) -> Vec<(&'a T, &'a T)>
+where
+    T: Serialize,
+    K: Fn(&T) -> String,
+{
+    let mut common = Vec::new();
+    for item in old {
+        if !new.iter().any(|new_item| key(new_item) == key(item)) {
+            result.push(format!("-{} {}", kind, key(item)));
+        }
+    }
+    for item in new {
+        match old.iter().find(|old_item| key(old_item) == key(item)) {
+            Some(old_item) => common.push((old_item, item)),
+            None => result.push(format!("+{} {}", kind, key(item))),
+        }
+    }
+    common
+}
+
+/// Returns the alternatives of all binaries, e.g. `pwn1/exe.orig`.
+fn alternatives(challenge: &Challenge) -> Vec<(String, &BinaryAlternative)> {
+    challenge
+        .binaries
+        .iter()
+        .flat_map(|binary| {
+            binary.alternatives.iter().map(move |alternative| {
+                (
+                    format!("{}/{}.{}", challenge.name, binary.name, alternative.name),
+                    alternative,
+                )
+            })
+        })
+        .collect()
+}
+
+fn diff_challenges(old: &Challenge, new: &Challenge, result: &mut Vec<String>) {
+    let name = &new.name;
+    if old.description != new.description {
+        result.push(format!("~description {}", name));
+    }
+    for (old_alternative, new_alternative) in diff_named(
+        "binary",
+        &alternatives(old),
+        &alternatives(new),
+        |(name, _)| name.clone(),
+        result,
+    ) {
+        if !same(old_alternative, new_alternative) {
+            result.push(format!("~binary {}", new_alternative.0));
+        }
+    }
+    for binary in &new.binaries {
+        let old_default = old
+            .binaries
+            .iter()
+            .find(|old_binary| old_binary.name == binary.name)
+            .and_then(|old_binary| old_binary.default_alternative.as_ref());
+        if let Some(default) = &binary.default_alternative {
+            if old_default != Some(default) {
+                result.push(format!("=binary {}/{}.{}", name, binary.name, default));
+            }
+        }
+    }
+    for (old_service, new_service) in diff_named(
+        "service",
+        &old.services,
+        &new.services,
+        |service| format!("{}/{}", name, service.name.as_ref().unwrap_or(&service.url)),
+        result,
+    ) {
+        if !same(old_service, new_service) {
+            result.push(format!("~service {}/{}", name, new_service.url));
+        }
+    }
+    diff_named(
+        "flag",
+        &old.flags,
+        &new.flags,
+        |flag| format!("{}/{}", name, flag.value),
+        result,
+    );
+    diff_named(
+        "claim",
+        &old.claims,
+        &new.claims,
+        |claim| format!("{}/{}", name, claim.name()),
+        result,
+    );
+    if !same(&old.status, &new.status) {
+        let status = new
+            .status
+            .as_ref()
+            .map_or(Status::Todo, |status| status.value);
+        result.push(format!("~status {}/{}", name, status.name()));
+    }
+    if new.notes.len() > old.notes.len() {
+        result.push(format!("+note {}", name));
+    }
+}
+
+/// Describes how `.ctf` changed, e.g. `+challenge pwn1` or `-binary pwn1/exe.orig`, where `~`
+/// means a change and `=` a new default alternative.
+pub fn diff(old: &CTF, new: &CTF) -> Vec<String> {
+    let mut result = Vec::new();
+    if old.name != new.name {
+        result.push(format!("~name {}", new.name));
+    }
+    if old.flag_format != new.flag_format {
+        result.push("~flag_format".into());
+    }
+    for (old_remote, new_remote) in diff_named(
+        "remote",
+        &old.remotes,
+        &new.remotes,
+        |remote| remote.name.clone(),
+        &mut result,
+    ) {
+        if !same(old_remote, new_remote) {
+            result.push(format!("~remote {}", new_remote.name));
+        }
+    }
+    for (old_challenge, new_challenge) in diff_named(
+        "challenge",
+        &old.challenges,
+        &new.challenges,
+        |challenge| challenge.name.clone(),
+        &mut result,
+    ) {
+        diff_challenges(old_challenge, new_challenge, &mut result);
+    }
+    if !same(&old.attack_defense, &new.attack_defense) {
+        result.push("~attack_defense".into());
+    }
+    result
+}
+
 pub fn find_challenge<'a>(ctf: &'a CTF, name: &str) -> Result<&'a Challenge> {
     ctf.challenges
         .iter()
         .find(|challenge| challenge.name == name)
-        .ok_or_else(|| anyhow!("No such challenge: {}", name))
+        .ok_or_else(|| Error::new(error::Error::NoSuchChallenge(name.into())))
 }
 
 pub fn find_challenge_mut<'a>(ctf: &'a mut CTF, name: &str) -> Result<&'a mut Challenge> {
     ctf.challenges
         .iter_mut()
         .find(|challenge| challenge.name == name)
-        .ok_or_else(|| anyhow!("No such challenge: {}", name))
+        .ok_or_else(|| Error::new(error::Error::NoSuchChallenge(name.into())))
 }
 
 pub fn find_binary<'a>(challenge: &'a Challenge, name: &str) -> Result<&'a Binary> {
@@ -466,7 +1046,7 @@ pub fn find_binary<'a>(challenge: &'a Challenge, name: &str) -> Result<&'a Binar
         .binaries
         .iter()
         .find(|binary| binary.name == name)
-        .ok_or_else(|| anyhow!("No such binary: {}", name))
+        .ok_or_else(|| Error::new(error::Error::NoSuchBinary(name.into())))
 }
 
 pub fn try_find_binary_mut<'a>(binaries: &'a mut [Binary], name: &str) -> Option<&'a mut Binary> {
@@ -474,7 +1054,8 @@ pub fn try_find_binary_mut<'a>(binaries: &'a mut [Binary], name: &str) -> Option
 }
 
 pub fn find_binary_mut<'a>(binaries: &'a mut [Binary], name: &str) -> Result<&'a mut Binary> {
-    try_find_binary_mut(binaries, name).ok_or_else(|| anyhow!("No such binary: {}", name))
+    try_find_binary_mut(binaries, name)
+        .ok_or_else(|| Error::new(error::Error::NoSuchBinary(name.into())))
 }
 
 pub fn find_alternative<'a>(binary: &'a Binary, name: &str) -> Result<&'a BinaryAlternative> {
@@ -482,7 +1063,7 @@ pub fn find_alternative<'a>(binary: &'a Binary, name: &str) -> Result<&'a Binary
         .alternatives
         .iter()
         .find(|alternative| alternative.name == name)
-        .ok_or_else(|| anyhow!("No such alternative: {}", name))
+        .ok_or_else(|| Error::new(error::Error::NoSuchAlternative(name.into())))
 }
 
 pub fn try_find_alternative_mut<'a>(
@@ -499,21 +1080,22 @@ pub fn find_alternative_mut<'a>(
     binary: &'a mut Binary,
     name: &str,
 ) -> Result<&'a mut BinaryAlternative> {
-    try_find_alternative_mut(binary, name).ok_or_else(|| anyhow!("No such alternative: {}", name))
+    try_find_alternative_mut(binary, name)
+        .ok_or_else(|| Error::new(error::Error::NoSuchAlternative(name.into())))
 }
 
 pub fn find_remote<'a>(ctf: &'a CTF, name: &str) -> Result<&'a Remote> {
     ctf.remotes
         .iter()
         .find(|remote| remote.name == name)
-        .ok_or_else(|| anyhow!("Remote {} does not exist", name))
+        .ok_or_else(|| Error::new(error::Error::NoSuchRemote(name.into())))
 }
 
 pub fn find_remote_mut<'a>(ctf: &'a mut CTF, name: &str) -> Result<&'a mut Remote> {
     ctf.remotes
         .iter_mut()
         .find(|remote| remote.name == name)
-        .ok_or_else(|| anyhow!("Remote {} does not exist", name))
+        .ok_or_else(|| Error::new(error::Error::NoSuchRemote(name.into())))
 }
 
 pub fn set_cookies(credentials: &mut Credentials, remote_name: String, cookies: String) {
@@ -529,6 +1111,18 @@ pub fn set_cookies(credentials: &mut Credentials, remote_name: String, cookies:
     });
 }
 
+pub fn load_cookie_store(credentials: &Credentials, remote_name: &str) -> Result<CookieStore> {
+    match credentials
+        .remotes
+        .iter()
+        .find(|remote_credentials| remote_credentials.name == remote_name)
+    {
+        Some(remote_credentials) => CookieStore::load_json(remote_credentials.cookies.as_bytes())
+            .map_err(|_| anyhow!("Could not load cookies")),
+        None => Ok(CookieStore::default()),
+    }
+}
+
 pub fn default_alternative_path(root: &Path, challenge_name: &str, binary_name: &str) -> PathBuf {
     root.to_path_buf().join(challenge_name).join(binary_name)
 }
@@ -544,6 +1138,29 @@ pub fn alternative_path(
         .join(format!("{}.{}", binary_name, alternative_name))
 }
 
+/// Returns the files of all binaries of a challenge, including the default alternatives.
+pub fn binary_paths(root: &Path, challenge: &Challenge) -> Vec<PathBuf> {
+    let mut result = Vec::new();
+    for binary in &challenge.binaries {
+        if binary.default_alternative.is_some() {
+            result.push(default_alternative_path(
+                root,
+                &challenge.name,
+                &binary.name,
+            ));
+        }
+        for alternative in &binary.alternatives {
+            result.push(alternative_path(
+                root,
+                &challenge.name,
+                &binary.name,
+                &alternative.name,
+            ));
+        }
+    }
+    result
+}
+
 pub fn set_default_alternative(
     root: &Path,
     challenge_name: &str,
@@ -592,5 +1209,20 @@ pub fn try_find_service_mut<'a>(
 }
 
 pub fn find_service_mut<'a>(challenge: &'a mut Challenge, name: &str) -> Result<&'a mut Service> {
-    try_find_service_mut(challenge, name).ok_or_else(|| anyhow!("No such service: {}", name))
+    try_find_service_mut(challenge, name)
+        .ok_or_else(|| Error::new(error::Error::NoSuchService(name.into())))
+}
+
+/// Port used by the local copy of a challenge service when the remote one is unknown.
+pub static DEFAULT_SERVICE_PORT: u16 = 1337;
+
+/// Returns the port of the first `nc://` service, which the local copy of the challenge uses.
+pub fn local_service_port(challenge: &Challenge) -> u16 {
+    challenge
+        .services
+        .iter()
+        .filter_map(|service| Url::parse(&service.url).ok())
+        .filter(|url| url.scheme() == "nc")
+        .find_map(|url| url.port())
+        .unwrap_or(DEFAULT_SERVICE_PORT)
 }
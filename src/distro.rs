@@ -1,25 +1,152 @@
+use crate::config;
+use crate::ctf;
 use anyhow::{anyhow, Result};
 use elf::abi::{
     DF_1_PIE, DT_FLAGS_1, EM_386, EM_AARCH64, EM_ALPHA, EM_ARM, EM_AVR32, EM_IA_64, EM_MIPS,
-    EM_PARISC, EM_PPC, EM_PPC64, EM_RISCV, EM_S390, EM_SH, EM_SPARC, EM_X86_64,
+    EM_PARISC, EM_PPC, EM_PPC64, EM_RISCV, EM_S390, EM_SH, EM_SPARC, EM_X86_64, ET_DYN, ET_EXEC,
+    PT_INTERP,
 };
 use elf::endian::{AnyEndian, EndianParse};
 use elf::file::{Class, FileHeader};
 use elf::ElfBytes;
 use lazy_static::lazy_static;
 use regex::bytes::Regex;
+use serde::Deserialize;
 use std::default::Default;
 use std::fs;
-use std::path::PathBuf;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
 use std::str;
 
-/// Distro and package versions.
+/// Distro release and package versions.
 #[derive(Default)]
 pub struct Packages {
     pub arch: Option<&'static str>,
-    pub distro: Option<&'static str>,
-    pub distro_version: Option<&'static str>,
+    pub distro: Option<String>,
+    pub image: Option<String>,
+    pub distro_version: Option<String>,
     pub libc_version: Option<String>,
+    /// Pinned image digest, e.g. sha256:...
+    pub digest: Option<String>,
+    /// Additional packages to install
+    pub extra: Vec<String>,
+    /// How well the binary matches the release, from 0 to 1
+    pub confidence: f64,
+}
+
+/// A distro release, as described in distros.yml.
+#[derive(Deserialize)]
+pub struct Release {
+    pub version: String,
+    #[serde(default)]
+    pub gcc: Vec<String>,
+    #[serde(default)]
+    pub libc: Vec<String>,
+}
+
+/// C library implementation.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Libc {
+    #[default]
+    Glibc,
+    Musl,
+}
+
+impl Libc {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Libc::Glibc => "glibc",
+            Libc::Musl => "musl",
+        }
+    }
+}
+
+/// Language runtime that a binary is built with.
+pub enum Runtime {
+    Go(Option<String>),
+    Rust(Option<String>),
+}
+
+impl Runtime {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Runtime::Go(_) => "Go",
+            Runtime::Rust(_) => "Rust",
+        }
+    }
+
+    pub fn version(&self) -> Option<&str> {
+        match self {
+            Runtime::Go(version) | Runtime::Rust(version) => version.as_deref(),
+        }
+    }
+}
+
+/// A distro, as described in distros.yml.
+#[derive(Deserialize)]
+pub struct Distro {
+    pub name: String,
+    pub image: String,
+    #[serde(default)]
+    pub libc_family: Libc,
+    /// Regexes that extract gcc version from a binary
+    #[serde(default)]
+    pub gcc: Vec<String>,
+    /// Regexes that extract libc version from a binary
+    #[serde(default)]
+    pub libc: Vec<String>,
+    #[serde(default)]
+    pub releases: Vec<Release>,
+}
+
+fn merge_distros(distros: &mut Vec<Distro>, distros2: Vec<Distro>) {
+    for distro2 in distros2 {
+        match distros
+            .iter_mut()
+            .find(|distro| distro.name == distro2.name)
+        {
+            Some(distro) => {
+                distro.image = distro2.image;
+                distro.libc_family = distro2.libc_family;
+                if !distro2.gcc.is_empty() {
+                    distro.gcc = distro2.gcc;
+                }
+                if !distro2.libc.is_empty() {
+                    distro.libc = distro2.libc;
+                }
+                for release2 in distro2.releases {
+                    match distro
+                        .releases
+                        .iter_mut()
+                        .find(|release| release.version == release2.version)
+                    {
+                        Some(release) => *release = release2,
+                        None => distro.releases.push(release2),
+                    }
+                }
+            }
+            None => distros.push(distro2),
+        }
+    }
+}
+
+/// Loads the embedded distros.yml and applies the per-user overrides.
+pub fn load_distros() -> Result<Vec<Distro>> {
+    let mut distros: Vec<Distro> = serde_yaml::from_str(include_str!("distro/distros.yml"))?;
+    if let Some(config_dir) = config::config_dir() {
+        let path = config_dir.join("distros.yml");
+        match fs::read(&path) {
+            Ok(bytes) => {
+                let distros2: Vec<Distro> = serde_yaml::from_slice(&bytes)
+                    .map_err(|e| anyhow!("Could not parse {}: {}", path.display(), e))?;
+                merge_distros(&mut distros, distros2);
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(distros)
 }
 
 /// Try to match a regex that has exactly one capture group.
@@ -40,32 +167,85 @@ fn try_regex_1(result: &mut Option<String>, regex: &Regex, bytes: &[u8]) -> Resu
     Ok(())
 }
 
+/// Package versions built by a particular distro found in a binary.
+pub struct DistroVersions {
+    pub distro: String,
+    pub gcc_version: Option<String>,
+    pub libc_version: Option<String>,
+}
+
 /// Various information extracted from a binary.
 #[derive(Default)]
 pub struct BinaryInfo {
     pub ehdr: Option<FileHeader<AnyEndian>>,
     pub is_pie: Option<bool>,
-    pub debian_gcc_version: Option<String>,
-    pub debian_libc_version: Option<String>,
-    pub ubuntu_gcc_version: Option<String>,
-    pub ubuntu_libc_version: Option<String>,
+    pub interpreter: Option<String>,
+    pub is_static: Option<bool>,
+    pub libc: Option<Libc>,
+    pub musl_version: Option<String>,
+    pub runtime: Option<Runtime>,
+    pub distro_versions: Vec<DistroVersions>,
     pub kernel_version: Option<String>,
 }
 
 lazy_static! {
-    static ref DEBIAN_GCC_REGEX: Regex = Regex::new(r"GCC: \(Debian (.+?)\)").unwrap();
-    static ref DEBIAN_LIBC_REGEX: Regex =
-        Regex::new(r"GNU C Library \(Debian GLIBC (.+?)\)").unwrap();
-    static ref DEBIAN_LDSO_REGEX: Regex = Regex::new(r"ld\.so \(Debian GLIBC (.+?)\)").unwrap();
-    static ref UBUNTU_GCC_REGEX: Regex = Regex::new(r"GCC: \(Ubuntu (.+?)\)").unwrap();
-    static ref UBUNTU_LIBC_REGEX: Regex =
-        Regex::new(r"GNU C Library \(Ubuntu GLIBC (.+?)\)").unwrap();
-    static ref UBUNTU_LDSO_REGEX: Regex = Regex::new(r"ld\.so \(Ubuntu GLIBC (.+?)\)").unwrap();
     static ref KERNEL_REGEX: Regex = Regex::new(r"Linux version ([^ ]+)").unwrap();
+    /* Dynamic loader usage message, present in libc.so / ld-musl-*.so.1. */
+    static ref MUSL_LDSO_REGEX: Regex = Regex::new(r"musl libc \(\w+\)").unwrap();
+    /* strerror(0), present in static binaries. */
+    static ref MUSL_REGEX: Regex = Regex::new(r"\x00No error information\x00").unwrap();
+    static ref MUSL_VERSION_REGEX: Regex = Regex::new(r"\x00(1\.\d+\.\d+)\x00").unwrap();
+    static ref GLIBC_REGEX: Regex =
+        Regex::new(r"GNU C Library|GLIBC_TUNABLES|FATAL: kernel too old").unwrap();
+    static ref GO_REGEX: Regex = Regex::new(r"\xff Go buildinf:|runtime\.goexit").unwrap();
+    static ref GO_VERSION_REGEX: Regex = Regex::new(r"(go1\.\d+(?:\.\d+)?)").unwrap();
+    static ref RUST_REGEX: Regex = Regex::new(r"/rustc/[0-9a-f]{40}/|rust_panic").unwrap();
+    static ref RUST_VERSION_REGEX: Regex = Regex::new(r"rustc version (\d+\.\d+\.\d+)").unwrap();
+}
+
+/// Determines which libc a binary uses or is.
+fn get_libc(interpreter: Option<&str>, bytes: &[u8]) -> Option<Libc> {
+    match interpreter {
+        Some(interpreter) if interpreter.contains("ld-musl-") => return Some(Libc::Musl),
+        Some(_) => return Some(Libc::Glibc),
+        None => {}
+    }
+    if MUSL_LDSO_REGEX.is_match(bytes) || MUSL_REGEX.is_match(bytes) {
+        Some(Libc::Musl)
+    } else if GLIBC_REGEX.is_match(bytes) {
+        Some(Libc::Glibc)
+    } else {
+        None
+    }
+}
+
+fn get_runtime(bytes: &[u8]) -> Result<Option<Runtime>> {
+    if GO_REGEX.is_match(bytes) {
+        let mut version = None;
+        try_regex_1(&mut version, &GO_VERSION_REGEX, bytes)?;
+        return Ok(Some(Runtime::Go(version)));
+    }
+    if RUST_REGEX.is_match(bytes) {
+        let mut version = None;
+        try_regex_1(&mut version, &RUST_VERSION_REGEX, bytes)?;
+        return Ok(Some(Runtime::Rust(version)));
+    }
+    Ok(None)
+}
+
+fn try_regexes_1(result: &mut Option<String>, regexes: &[String], bytes: &[u8]) -> Result<()> {
+    for regex in regexes {
+        try_regex_1(result, &Regex::new(regex)?, bytes)?;
+    }
+    Ok(())
 }
 
 impl BinaryInfo {
     pub fn analyze(path: &PathBuf) -> Result<BinaryInfo> {
+        Self::analyze_with_distros(path, &load_distros()?)
+    }
+
+    pub fn analyze_with_distros(path: &PathBuf, distros: &[Distro]) -> Result<BinaryInfo> {
         let mut result = BinaryInfo::default();
         let bytes = fs::read(path)?;
         if let Ok(elf) = ElfBytes::<AnyEndian>::minimal_parse(&bytes) {
@@ -75,19 +255,73 @@ impl BinaryInfo {
                     _dyn.d_tag == DT_FLAGS_1 && (_dyn.clone().d_val() & (DF_1_PIE as u64)) != 0
                 }));
             }
+            if let Some(segments) = elf.segments() {
+                if let Some(phdr) = segments.iter().find(|phdr| phdr.p_type == PT_INTERP) {
+                    if let Ok(data) = elf.segment_data(&phdr) {
+                        let data = data.split(|b| *b == 0).next().unwrap_or(data);
+                        result.interpreter = Some(String::from_utf8_lossy(data).into_owned());
+                    }
+                }
+            }
+            result.is_static = Some(
+                result.interpreter.is_none()
+                    && (elf.ehdr.e_type == ET_EXEC || result.is_pie == Some(true)),
+            );
+        }
+        result.libc = get_libc(result.interpreter.as_deref(), &bytes);
+        if MUSL_LDSO_REGEX.is_match(&bytes) {
+            try_regex_1(&mut result.musl_version, &MUSL_VERSION_REGEX, &bytes)?;
+        }
+        result.runtime = get_runtime(&bytes)?;
+        for distro in distros {
+            if result.libc.is_some() && result.libc != Some(distro.libc_family) {
+                continue;
+            }
+            let mut versions = DistroVersions {
+                distro: distro.name.clone(),
+                gcc_version: None,
+                libc_version: None,
+            };
+            try_regexes_1(&mut versions.gcc_version, &distro.gcc, &bytes)?;
+            try_regexes_1(&mut versions.libc_version, &distro.libc, &bytes)?;
+            if distro.libc_family == Libc::Musl {
+                versions.libc_version = versions.libc_version.or(result.musl_version.clone());
+            }
+            if versions.gcc_version.is_some() || versions.libc_version.is_some() {
+                result.distro_versions.push(versions);
+            }
         }
-        try_regex_1(&mut result.debian_gcc_version, &DEBIAN_GCC_REGEX, &bytes)?;
-        try_regex_1(&mut result.debian_libc_version, &DEBIAN_LIBC_REGEX, &bytes)?;
-        try_regex_1(&mut result.debian_libc_version, &DEBIAN_LDSO_REGEX, &bytes)?;
-        try_regex_1(&mut result.ubuntu_gcc_version, &UBUNTU_GCC_REGEX, &bytes)?;
-        try_regex_1(&mut result.ubuntu_libc_version, &UBUNTU_LIBC_REGEX, &bytes)?;
-        try_regex_1(&mut result.ubuntu_libc_version, &UBUNTU_LDSO_REGEX, &bytes)?;
         try_regex_1(&mut result.kernel_version, &KERNEL_REGEX, &bytes)?;
         Ok(result)
     }
 }
 
-fn get_debian_arch_str(ehdr: &FileHeader<AnyEndian>) -> Option<&'static str> {
+impl BinaryInfo {
+    /// Returns whether this is an executable, as opposed to a library or an object file.
+    pub fn is_executable(&self) -> bool {
+        match &self.ehdr {
+            Some(ehdr) => {
+                ehdr.e_type == ET_EXEC || (ehdr.e_type == ET_DYN && self.is_pie == Some(true))
+            }
+            None => false,
+        }
+    }
+}
+
+/// Returns the name of the first challenge binary that is an executable.
+pub fn find_main_binary(challenge_dir: &Path, binaries: &[ctf::Binary]) -> Result<Option<String>> {
+    let distros = load_distros()?;
+    for binary in binaries {
+        let info = BinaryInfo::analyze_with_distros(&challenge_dir.join(&binary.name), &distros)?;
+        if info.is_executable() {
+            return Ok(Some(binary.name.clone()));
+        }
+    }
+    Ok(None)
+}
+
+/// Converts an ELF machine to a Debian architecture.
+pub fn get_debian_arch_str(ehdr: &FileHeader<AnyEndian>) -> Option<&'static str> {
     if ehdr.e_machine == EM_386 {
         Some("i386")
     } else if ehdr.e_machine == EM_AARCH64 {
@@ -149,144 +383,143 @@ fn get_debian_arch_str(ehdr: &FileHeader<AnyEndian>) -> Option<&'static str> {
     }
 }
 
-fn get_debian_versions_by_gcc_version(gcc_version: &str) -> &'static [&'static str] {
-    if gcc_version.starts_with("6.") {
-        &["9"]
-    } else if gcc_version.starts_with("7.") || gcc_version.starts_with("8.") {
-        &["10"]
-    } else if gcc_version.starts_with("9.") || gcc_version.starts_with("10.") {
-        &["11"]
-    } else if gcc_version.starts_with("11.") || gcc_version.starts_with("12.") {
-        &["12"]
-    } else {
-        &[]
+/// Converts a Docker platform, e.g. linux/arm64, to a Debian architecture.
+pub fn get_debian_arch_from_platform(platform: &str) -> Option<&'static str> {
+    match platform.strip_prefix("linux/").unwrap_or(platform) {
+        "386" => Some("i386"),
+        "amd64" => Some("amd64"),
+        "arm/v5" | "arm/v6" => Some("armel"),
+        "arm" | "arm/v7" => Some("armhf"),
+        "arm64" | "arm64/v8" => Some("arm64"),
+        "mips64le" => Some("mips64el"),
+        "ppc64le" => Some("ppc64el"),
+        "riscv64" => Some("riscv64"),
+        "s390x" => Some("s390x"),
+        _ => None,
     }
 }
 
-fn get_ubuntu_versions_by_gcc_version(gcc_version: &str) -> &'static [&'static str] {
-    if gcc_version.starts_with("7.") {
-        &["18.04"]
-    } else if gcc_version.starts_with("9.") {
-        &["20.04"]
-    } else if gcc_version.starts_with("11.") {
-        &["22.04"]
-    } else if gcc_version.starts_with("12.") {
-        &["22.10", "23.04"]
-    } else {
-        &[]
-    }
+fn matches(version: &Option<String>, prefixes: &[String]) -> Option<bool> {
+    version
+        .as_ref()
+        .map(|version| prefixes.iter().any(|prefix| version.starts_with(prefix)))
 }
 
-fn get_debian_versions_by_libc_version(libc_version: &str) -> &'static [&'static str] {
-    if libc_version.starts_with("2.24-") {
-        &["9"]
-    } else if libc_version.starts_with("2.28-") {
-        &["10"]
-    } else if libc_version.starts_with("2.31-") {
-        &["11"]
-    } else if libc_version.starts_with("2.36-") {
-        &["12"]
-    } else {
-        &[]
-    }
-}
+/* libc is more important than gcc, since it is what a binary runs against. */
+static GCC_WEIGHT: f64 = 1.0;
+static LIBC_WEIGHT: f64 = 2.0;
 
-fn get_ubuntu_versions_by_libc_version(libc_version: &str) -> &'static [&'static str] {
-    if libc_version.starts_with("2.27-") {
-        &["18.04"]
-    } else if libc_version.starts_with("2.31-") {
-        &["20.04"]
-    } else if libc_version.starts_with("2.35-") {
-        &["22.04"]
-    } else if libc_version.starts_with("2.36-") {
-        &["22.10"]
-    } else if libc_version.starts_with("2.37-") {
-        &["23.04"]
-    } else {
-        &[]
+/// Scores how well package versions match a release.
+fn get_confidence(versions: &DistroVersions, release: &Release) -> f64 {
+    let mut total = 0.0;
+    let mut matched = 0.0;
+    for (matches, weight) in [
+        (matches(&versions.gcc_version, &release.gcc), GCC_WEIGHT),
+        (matches(&versions.libc_version, &release.libc), LIBC_WEIGHT),
+    ] {
+        if let Some(matches) = matches {
+            total += weight;
+            if matches {
+                matched += weight;
+            }
+        }
     }
-}
-
-/// Returns an intersection of multiple slices.
-fn get_intersection<'a>(slices: &[&[&'a str]]) -> Vec<&'a str> {
-    if let Some(first) = slices.first() {
-        first
-            .iter()
-            .copied()
-            .filter(|x| {
-                let rest = &slices[1..];
-                rest.iter().all(|y| y.contains(x))
-            })
-            .collect()
+    if total == 0.0 {
+        0.0
     } else {
-        Vec::new()
+        matched / total
     }
 }
 
-fn get_distro_version<'a>(candidates: &[Option<&[&'a str]>]) -> Option<&'a str> {
-    let known: Vec<&[&'a str]> = candidates.iter().filter_map(|x| *x).collect();
-    get_intersection(&known).first().copied()
-}
-
-fn get_debian_version(info: &BinaryInfo) -> Option<&'static str> {
-    get_distro_version(&[
-        info.debian_gcc_version
-            .as_ref()
-            .map(|x| get_debian_versions_by_gcc_version(x)),
-        info.debian_libc_version
-            .as_ref()
-            .map(|x| get_debian_versions_by_libc_version(x)),
-    ])
-}
-
-fn get_ubuntu_version(info: &BinaryInfo) -> Option<&'static str> {
-    get_distro_version(&[
-        info.ubuntu_gcc_version
-            .as_ref()
-            .map(|x| get_ubuntu_versions_by_gcc_version(x)),
-        info.ubuntu_libc_version
-            .as_ref()
-            .map(|x| get_ubuntu_versions_by_libc_version(x)),
-    ])
-}
-
 pub static DEFAULT_ARCH: &str = "amd64";
 pub static DEFAULT_DISTRO: &str = "ubuntu";
 pub static DEFAULT_DISTRO_VERSION: &str = "latest";
 pub static DEFAULT_LIBC_VERSION: &str = "*";
+pub static DEFAULT_MUSL_DISTRO: &str = "alpine";
+static MUSL_CONFIDENCE: f64 = 0.5;
+
+/// Returns candidate distro releases for a binary, best matches first.
+pub fn get_packages(path: &PathBuf) -> Result<Vec<Packages>> {
+    let distros = load_distros()?;
+    let info = BinaryInfo::analyze_with_distros(path, &distros)?;
+    Ok(get_packages_for(&info, &distros))
+}
 
-pub fn get_packages(path: &PathBuf) -> Result<Option<Packages>> {
-    let info = BinaryInfo::analyze(path)?;
+/// Returns candidate distro releases for an analyzed binary, best matches first.
+pub fn get_packages_for(info: &BinaryInfo, distros: &[Distro]) -> Vec<Packages> {
     let arch = info
         .ehdr
-        .and_then(|ehdr| get_debian_arch_str(&ehdr))
+        .as_ref()
+        .and_then(get_debian_arch_str)
         .unwrap_or(DEFAULT_ARCH);
-    if let Some(debian_version) = get_debian_version(&info) {
-        return Ok(Some(Packages {
-            arch: Some(arch),
-            distro: Some("debian"),
-            distro_version: Some(debian_version),
-            libc_version: info.debian_libc_version,
-        }));
+    let mut result = Vec::new();
+    for versions in &info.distro_versions {
+        let distro = match distros.iter().find(|distro| distro.name == versions.distro) {
+            Some(distro) => distro,
+            None => continue,
+        };
+        for release in &distro.releases {
+            let confidence = get_confidence(versions, release);
+            if confidence > 0.0 {
+                result.push(Packages {
+                    arch: Some(arch),
+                    distro: Some(distro.name.clone()),
+                    image: Some(distro.image.clone()),
+                    distro_version: Some(release.version.clone()),
+                    libc_version: versions.libc_version.clone(),
+                    digest: None,
+                    extra: Vec::new(),
+                    confidence,
+                });
+            }
+        }
     }
-    if let Some(ubuntu_version) = get_ubuntu_version(&info) {
-        return Ok(Some(Packages {
+    if result.is_empty() && info.libc == Some(Libc::Musl) {
+        /* Nothing is known except that this is a musl binary. */
+        result.push(Packages {
             arch: Some(arch),
-            distro: Some("ubuntu"),
-            distro_version: Some(ubuntu_version),
-            libc_version: info.ubuntu_libc_version,
-        }));
+            distro: Some(DEFAULT_MUSL_DISTRO.into()),
+            image: Some(DEFAULT_MUSL_DISTRO.into()),
+            distro_version: Some(DEFAULT_DISTRO_VERSION.into()),
+            libc_version: None,
+            digest: None,
+            extra: Vec::new(),
+            confidence: MUSL_CONFIDENCE,
+        });
     }
-    Ok(None)
+    /* Stable sort keeps the distros.yml order for equally good matches. */
+    result.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+    result
 }
 
+/// Chooses the release that matches all binaries best.
 pub fn merge_packages_variants(packages_variants: Vec<Packages>) -> Packages {
-    let mut packages = Packages::default();
+    let mut scores: Vec<Packages> = Vec::new();
     for packages_variant in packages_variants {
-        packages.arch = packages.arch.or(packages_variant.arch);
-        packages.distro = packages.distro.or(packages_variant.distro);
-        packages.distro_version = packages.distro_version.or(packages_variant.distro_version);
-        packages.libc_version = packages.libc_version.or(packages_variant.libc_version);
+        let existing = scores.iter_mut().find(|packages| {
+            packages.distro == packages_variant.distro
+                && packages.distro_version == packages_variant.distro_version
+        });
+        match existing {
+            Some(packages) => {
+                packages.arch = packages.arch.or(packages_variant.arch);
+                packages.libc_version = packages
+                    .libc_version
+                    .take()
+                    .or(packages_variant.libc_version);
+                packages.confidence += packages_variant.confidence;
+            }
+            None => scores.push(packages_variant),
+        }
     }
-    packages
+    scores
+        .into_iter()
+        .reduce(|best, packages| {
+            if packages.confidence > best.confidence {
+                packages
+            } else {
+                best
+            }
+        })
+        .unwrap_or_default()
 }
@@ -0,0 +1,340 @@
+use crate::ui::Ui;
+use crate::{distro, path, shell};
+use anyhow::{anyhow, Result};
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+lazy_static! {
+    static ref DOCKERFILE_NAME_REGEX: Regex =
+        Regex::new(r"^(Dockerfile(\..+)?|.+\.[Dd]ockerfile)$").unwrap();
+    static ref COMPOSE_NAME_REGEX: Regex =
+        Regex::new(r"^(docker-)?compose(\..+)?\.ya?ml$").unwrap();
+    static ref HEREDOC_REGEX: Regex = Regex::new(r#"<<-?["']?(\w+)["']?"#).unwrap();
+    static ref VARIABLE_REGEX: Regex = Regex::new(r"\$\{?(\w+)\}?").unwrap();
+}
+
+/// Docker image reference, e.g. ubuntu:22.04@sha256:...
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Image {
+    pub name: String,
+    pub tag: Option<String>,
+    pub digest: Option<String>,
+}
+
+impl Image {
+    pub fn parse(reference: &str) -> Image {
+        let (reference, digest) = match reference.split_once('@') {
+            Some((reference, digest)) => (reference, Some(digest.to_string())),
+            None => (reference, None),
+        };
+        let slash = reference.rfind('/').map(|i| i + 1).unwrap_or(0);
+        let (name, tag) = match reference[slash..].split_once(':') {
+            Some((_, tag)) => (
+                &reference[..reference.len() - tag.len() - 1],
+                Some(tag.to_string()),
+            ),
+            None => (reference, None),
+        };
+        Image {
+            name: name.to_string(),
+            tag,
+            digest,
+        }
+    }
+
+    /// Returns the name of an official image, e.g. ubuntu for docker.io/library/ubuntu.
+    pub fn official_name(&self) -> Option<&str> {
+        let name = self.name.strip_prefix("docker.io/").unwrap_or(&self.name);
+        let name = name.strip_prefix("library/").unwrap_or(name);
+        if name.contains('/') {
+            None
+        } else {
+            Some(name)
+        }
+    }
+}
+
+impl fmt::Display for Image {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if let Some(tag) = &self.tag {
+            write!(f, ":{}", tag)?;
+        }
+        if let Some(digest) = &self.digest {
+            write!(f, "@{}", digest)?;
+        }
+        Ok(())
+    }
+}
+
+/// How a challenge is deployed, according to its Dockerfile or docker-compose.yml.
+#[derive(Clone, Debug)]
+pub struct Deployment {
+    pub path: PathBuf,
+    pub image: Image,
+    pub arch: Option<&'static str>,
+    pub packages: Vec<String>,
+}
+
+struct Stage {
+    name: Option<String>,
+    deployment: Deployment,
+    copy_from: Vec<String>,
+}
+
+/// Joins continuation lines and heredocs, and drops comments.
+fn instructions(dockerfile: &str) -> Vec<String> {
+    let mut result = Vec::new();
+    let mut lines = dockerfile.lines();
+    let mut current = String::new();
+    while let Some(line) = lines.next() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with('#') || (current.is_empty() && trimmed.is_empty()) {
+            continue;
+        }
+        if let Some(line) = line.strip_suffix('\\') {
+            current.push_str(line);
+            current.push(' ');
+            continue;
+        }
+        current.push_str(line);
+        if let Some(captures) = HEREDOC_REGEX.captures(&current) {
+            let delimiter = captures[1].to_string();
+            for line in lines.by_ref() {
+                if line.trim() == delimiter {
+                    break;
+                }
+                current.push('\n');
+                current.push_str(line);
+            }
+        }
+        result.push(std::mem::take(&mut current));
+    }
+    if !current.is_empty() {
+        result.push(current);
+    }
+    result
+}
+
+fn expand(s: &str, args: &HashMap<String, String>) -> String {
+    VARIABLE_REGEX
+        .replace_all(s, |captures: &regex::Captures| {
+            args.get(&captures[1]).cloned().unwrap_or_default()
+        })
+        .into_owned()
+}
+
+/// Options of the package managers' install commands that take a value.
+static OPTIONS_WITH_VALUES: &[&str] = &["-o", "-t", "--target-release", "-X", "--repository"];
+
+/// Returns the packages installed by a shell script.
+pub fn installed_packages(script: &str) -> Vec<String> {
+    let mut result = Vec::new();
+    for words in shell::split(script) {
+        let mut words = words.iter().map(String::as_str);
+        while let Some(word) = words.next() {
+            let verbs: &[&str] = match word {
+                "apt-get" | "apt" | "aptitude" | "dnf" | "yum" | "microdnf" => &["install"],
+                "apk" => &["add"],
+                _ => continue,
+            };
+            let mut installing = false;
+            while let Some(word) = words.next() {
+                if OPTIONS_WITH_VALUES.contains(&word) {
+                    words.next();
+                    continue;
+                }
+                if word.starts_with('-') {
+                    continue;
+                }
+                if installing {
+                    if !word.contains('$') {
+                        result.push(word.to_string());
+                    }
+                } else if verbs.contains(&word) {
+                    installing = true;
+                } else {
+                    break;
+                }
+            }
+        }
+    }
+    result
+}
+
+/// Parses a Dockerfile and determines the image in which the challenge runs.
+///
+/// Multi-stage builds are often used for sandboxing, e.g. with pwn.red/jail, in which case the
+/// challenge runs in a stage or an image that the final stage copies from.
+pub fn parse_dockerfile(path: &Path, dockerfile: &str) -> Option<Deployment> {
+    let mut args = HashMap::new();
+    let mut stages: Vec<Stage> = Vec::new();
+    for instruction in instructions(dockerfile) {
+        let (keyword, rest) = instruction
+            .trim()
+            .split_once(char::is_whitespace)
+            .unwrap_or((instruction.trim(), ""));
+        match keyword.to_uppercase().as_str() {
+            "ARG" if stages.is_empty() => {
+                for word in shell::split(rest).into_iter().flatten() {
+                    let (name, value) = word.split_once('=').unwrap_or((&word, ""));
+                    args.insert(name.to_string(), value.to_string());
+                }
+            }
+            "FROM" => {
+                let mut platform = None;
+                let mut words = Vec::new();
+                for word in rest.split_whitespace() {
+                    match word.strip_prefix("--platform=") {
+                        Some(value) => platform = Some(expand(value, &args)),
+                        None => words.push(expand(word, &args)),
+                    }
+                }
+                let reference = words.first().filter(|reference| !reference.is_empty())?;
+                let name = match words.as_slice() {
+                    [_, as_, name] if as_.eq_ignore_ascii_case("as") => Some(name.clone()),
+                    _ => None,
+                };
+                let mut deployment = match stages
+                    .iter()
+                    .find(|stage| stage.name.as_ref() == Some(reference))
+                {
+                    Some(stage) => stage.deployment.clone(),
+                    None => Deployment {
+                        path: path.to_path_buf(),
+                        image: Image::parse(reference),
+                        arch: None,
+                        packages: Vec::new(),
+                    },
+                };
+                if let Some(platform) = platform {
+                    deployment.arch = distro::get_debian_arch_from_platform(&platform);
+                }
+                stages.push(Stage {
+                    name,
+                    deployment,
+                    copy_from: Vec::new(),
+                });
+            }
+            "RUN" => {
+                if let Some(stage) = stages.last_mut() {
+                    stage.deployment.packages.extend(installed_packages(rest));
+                }
+            }
+            "COPY" => {
+                if let Some(stage) = stages.last_mut() {
+                    for word in rest.split_whitespace() {
+                        if let Some(from) = word.strip_prefix("--from=") {
+                            stage.copy_from.push(expand(from, &args));
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    let last = stages.last()?;
+    if last.deployment.image.official_name().is_some() {
+        return Some(last.deployment.clone());
+    }
+    for from in &last.copy_from {
+        if let Some(stage) = stages
+            .iter()
+            .find(|stage| stage.name.as_ref() == Some(from))
+        {
+            return Some(stage.deployment.clone());
+        }
+        if from.parse::<usize>().is_err() {
+            return Some(Deployment {
+                path: path.to_path_buf(),
+                image: Image::parse(from),
+                arch: last.deployment.arch,
+                packages: Vec::new(),
+            });
+        }
+    }
+    Some(last.deployment.clone())
+}
+
+fn get_str<'a>(mapping: &'a serde_yaml::Mapping, key: &str) -> Option<&'a str> {
+    mapping.get(key).and_then(serde_yaml::Value::as_str)
+}
+
+/// Parses a docker-compose.yml and determines the images in which services run.
+pub fn parse_compose(path: &Path, compose: &str) -> Result<Vec<Deployment>> {
+    let compose: serde_yaml::Value = serde_yaml::from_str(compose)?;
+    let dir = path.parent().unwrap();
+    let mut result = Vec::new();
+    let services = match compose
+        .get("services")
+        .and_then(|services| services.as_mapping())
+    {
+        Some(services) => services,
+        None => return Ok(result),
+    };
+    for (_, service) in services {
+        let service = service
+            .as_mapping()
+            .ok_or_else(|| anyhow!("Service is not a mapping"))?;
+        let arch = get_str(service, "platform").and_then(distro::get_debian_arch_from_platform);
+        let build = service.get("build");
+        let dockerfile = match build {
+            Some(serde_yaml::Value::String(context)) => Some(dir.join(context).join("Dockerfile")),
+            Some(serde_yaml::Value::Mapping(build)) => Some(
+                dir.join(get_str(build, "context").unwrap_or("."))
+                    .join(get_str(build, "dockerfile").unwrap_or("Dockerfile")),
+            ),
+            _ => None,
+        };
+        let deployment = match (dockerfile, get_str(service, "image")) {
+            (Some(dockerfile), _) => match fs::read_to_string(&dockerfile) {
+                Ok(contents) => parse_dockerfile(&dockerfile, &contents),
+                Err(_) => None,
+            },
+            (None, Some(image)) => Some(Deployment {
+                path: path.to_path_buf(),
+                image: Image::parse(image),
+                arch: None,
+                packages: Vec::new(),
+            }),
+            (None, None) => None,
+        };
+        if let Some(mut deployment) = deployment {
+            deployment.arch = deployment.arch.or(arch);
+            result.push(deployment);
+        }
+    }
+    Ok(result)
+}
+
+/// Finds Dockerfiles and docker-compose.yml files in a challenge directory, including
+/// extracted archives, and parses them. Files that cannot be read or parsed are skipped with a
+/// warning, since challenges often ship unrelated files with matching names.
+pub fn find_deployments(dir: &Path, exclude: &[PathBuf], ui: &dyn Ui) -> Result<Vec<Deployment>> {
+    let mut result = Vec::new();
+    for path in path::walk(dir)? {
+        if exclude.contains(&path) {
+            continue;
+        }
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        let is_dockerfile = DOCKERFILE_NAME_REGEX.is_match(&name);
+        if !is_dockerfile && !COMPOSE_NAME_REGEX.is_match(&name) {
+            continue;
+        }
+        let deployments = fs::read_to_string(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|contents| match is_dockerfile {
+                true => Ok(parse_dockerfile(&path, &contents).into_iter().collect()),
+                false => parse_compose(&path, &contents),
+            });
+        match deployments {
+            Ok(deployments) => result.extend(deployments),
+            Err(e) => ui.warn(&format!("Skipping {}: {:#}", path.display(), e)),
+        }
+    }
+    Ok(result)
+}
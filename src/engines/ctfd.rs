@@ -1,10 +1,10 @@
 use cookie_store::CookieStore;
 use futures::future::FutureExt;
 use regex::Regex;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use url::Url;
 
-use anyhow::{bail, Result};
+use anyhow::{anyhow, bail, Result};
 
 use crate::ctf;
 use crate::ctf::Remote;
@@ -24,6 +24,23 @@ struct Challenge {
     category: String,
 }
 
+#[derive(Serialize)]
+struct Submission<'a> {
+    challenge_id: i32,
+    submission: &'a str,
+}
+
+#[derive(Deserialize)]
+struct Attempt {
+    success: bool,
+    data: AttemptData,
+}
+
+#[derive(Deserialize)]
+struct AttemptData {
+    status: String,
+}
+
 #[derive(Deserialize)]
 struct ChallengeDetails {
     success: bool,
@@ -115,6 +132,12 @@ async fn login(
     Ok(cookie_store)
 }
 
+/// Returns the local name of a challenge, which submit uses to find it again.
+fn local_name(challenge: &Challenge) -> String {
+    let category = ctf::best_category(std::slice::from_ref(&challenge.category));
+    format!("{}-{}", category, ctf::sanitize_title(&challenge.name))
+}
+
 async fn fetch_challenge(
     client: &http::Client,
     cookie_store: &CookieStore,
@@ -134,26 +157,27 @@ async fn fetch_challenge(
     if !challenge_details.success {
         bail!("Could not retrieve challenge {}", challenge.id);
     }
-    let category = ctf::best_category(&[challenge.category]);
-    let title = ctf::sanitize_title(&challenge.name);
     let binaries =
         ctf::binaries_from_description(client, cookie_store, &challenge_details.data.description)
             .await?;
     let services = ctf::services_from_description(&challenge_details.data.description)?;
     Ok(ctf::Challenge {
-        name: format!("{}-{}", category, title),
+        name: local_name(&challenge),
         description: challenge_details.data.description,
         binaries,
         services,
+        flags: Vec::new(),
+        claims: Vec::new(),
+        status: None,
+        notes: Vec::new(),
     })
 }
 
-pub async fn fetch(
+async fn fetch_challenges(
     client: &http::Client,
     cookie_store: &CookieStore,
     remote: &ctf::Remote,
-) -> Result<ctf::CTF> {
-    let mut ctf = ctf::CTF::default();
+) -> Result<Vec<Challenge>> {
     let challenges_url = http::build_url(&remote.url, &["api", "v1", "challenges"])?;
     let challenges_request = client
         .get(challenges_url.as_str())
@@ -164,13 +188,73 @@ pub async fn fetch(
     if !challenges.success {
         bail!("Could not retrieve challenges");
     }
-    for challenge in challenges.data {
+    Ok(challenges.data)
+}
+
+pub async fn fetch(
+    client: &http::Client,
+    cookie_store: &CookieStore,
+    remote: &ctf::Remote,
+) -> Result<ctf::CTF> {
+    let mut ctf = ctf::CTF::default();
+    for challenge in fetch_challenges(client, cookie_store, remote).await? {
         ctf.challenges
             .push(fetch_challenge(client, cookie_store, remote, challenge).await?);
     }
     Ok(ctf)
 }
 
+/// Extracts the nonce that CTFd expects in the CSRF-Token header of API requests.
+async fn get_csrf_nonce(
+    client: &http::Client,
+    cookie_store: &CookieStore,
+    remote: &ctf::Remote,
+) -> Result<String> {
+    let url = Url::parse(&remote.url)?;
+    let request = client
+        .get(url.as_str())
+        .add_cookie_header(&url, cookie_store);
+    let response = client.execute(request.build()?).await?;
+    response.error_for_status_ref()?;
+    let page = response.text().await?;
+    let nonce_regex = Regex::new(r#"(?:csrf_nonce = |'csrfNonce': )"([0-9a-f]+)""#)?;
+    match nonce_regex.captures(&page) {
+        Some(capture) => Ok(capture[1].to_owned()),
+        None => bail!("Could not find CSRF nonce"),
+    }
+}
+
+pub async fn submit(
+    client: &http::Client,
+    cookie_store: &CookieStore,
+    remote: &ctf::Remote,
+    challenge_name: &str,
+    flag: &str,
+) -> Result<String> {
+    let challenge = fetch_challenges(client, cookie_store, remote)
+        .await?
+        .into_iter()
+        .find(|challenge| local_name(challenge) == challenge_name)
+        .ok_or_else(|| anyhow!("No such challenge on {}: {}", remote.name, challenge_name))?;
+    let nonce = get_csrf_nonce(client, cookie_store, remote).await?;
+    let attempt_url = http::build_url(&remote.url, &["api", "v1", "challenges", "attempt"])?;
+    let attempt_request = client
+        .post(attempt_url.as_str())
+        .header("CSRF-Token", nonce)
+        .json(&Submission {
+            challenge_id: challenge.id,
+            submission: flag,
+        })
+        .add_cookie_header(&attempt_url, cookie_store);
+    let attempt_response = client.execute(attempt_request.build()?).await?;
+    attempt_response.error_for_status_ref()?;
+    let attempt: Attempt = attempt_response.json().await?;
+    if !attempt.success {
+        bail!("Could not submit a flag for {}", challenge_name);
+    }
+    Ok(attempt.data.status)
+}
+
 pub struct CtfdEngine {}
 
 impl engines::Engine for CtfdEngine {
@@ -201,4 +285,15 @@ impl engines::Engine for CtfdEngine {
     ) -> engines::FetchResult<'a> {
         fetch(client, cookie_store, remote).boxed()
     }
+
+    fn submit<'a>(
+        &self,
+        client: &'a http::Client,
+        cookie_store: &'a CookieStore,
+        remote: &'a ctf::Remote,
+        challenge: &'a str,
+        flag: &'a str,
+    ) -> engines::SubmitResult<'a> {
+        submit(client, cookie_store, remote, challenge, flag).boxed()
+    }
 }
@@ -168,6 +168,10 @@ Authors: {}
         ),
         binaries,
         services,
+        flags: Vec::new(),
+        claims: Vec::new(),
+        status: None,
+        notes: Vec::new(),
     })
 }
 
@@ -224,4 +228,15 @@ impl engines::Engine for InsomniHackEngine {
     ) -> engines::FetchResult<'a> {
         fetch(client, cookie_store, remote).boxed()
     }
+
+    fn submit<'a>(
+        &self,
+        _client: &'a http::Client,
+        _cookie_store: &'a CookieStore,
+        _remote: &'a ctf::Remote,
+        _challenge: &'a str,
+        _flag: &'a str,
+    ) -> engines::SubmitResult<'a> {
+        engines::submit_unsupported("insomnihack")
+    }
 }
@@ -5,6 +5,7 @@ use std::pin::Pin;
 use cookie_store::CookieStore;
 
 use anyhow::{anyhow, Result};
+use futures::future::{self, FutureExt};
 use lazy_static::lazy_static;
 
 use crate::ctf;
@@ -17,6 +18,7 @@ pub mod watevr;
 type DetectResult<'a> = Pin<Box<dyn Future<Output = Result<()>> + 'a>>;
 type LoginResult<'a> = Pin<Box<dyn Future<Output = Result<CookieStore>> + 'a>>;
 type FetchResult<'a> = Pin<Box<dyn Future<Output = Result<ctf::CTF>> + 'a>>;
+type SubmitResult<'a> = Pin<Box<dyn Future<Output = Result<String>> + 'a>>;
 
 pub trait Engine {
     fn detect<'a>(
@@ -38,6 +40,15 @@ pub trait Engine {
         cookie_store: &'a CookieStore,
         remote: &'a ctf::Remote,
     ) -> FetchResult<'a>;
+    /// Submits a flag and returns the scoreboard verdict, e.g. "correct".
+    fn submit<'a>(
+        &self,
+        client: &'a http::Client,
+        cookie_store: &'a CookieStore,
+        remote: &'a ctf::Remote,
+        challenge: &'a str,
+        flag: &'a str,
+    ) -> SubmitResult<'a>;
 }
 
 lazy_static! {
@@ -78,10 +89,15 @@ pub async fn detect(client: &http::Client, remote: &ctf::Remote) -> Result<Strin
             return Ok((*name).to_string());
         }
     }
-    for (name, e) in errors {
-        eprintln!("{}: {}", name, e);
-    }
-    Err(anyhow!("Could not detect engine used by {}", remote.name))
+    let reasons: Vec<String> = errors
+        .iter()
+        .map(|(name, e)| format!("{}: {}", name, e))
+        .collect();
+    Err(anyhow!(
+        "Could not detect engine used by {} ({})",
+        remote.name,
+        reasons.join("; ")
+    ))
 }
 
 pub async fn detect_needle(main_page: &str, needle: &str) -> Result<()> {
@@ -91,3 +107,7 @@ pub async fn detect_needle(main_page: &str, needle: &str) -> Result<()> {
         Err(anyhow!("Main page does not contain \"{}\"", needle))
     }
 }
+
+pub fn submit_unsupported<'a>(engine: &str) -> SubmitResult<'a> {
+    future::err(anyhow!("{} does not support flag submission", engine)).boxed()
+}
@@ -1,5 +1,6 @@
 use cookie_store::CookieStore;
 use futures::future::{self, FutureExt};
+use log::warn;
 use serde::Deserialize;
 
 use anyhow::Result;
@@ -62,7 +63,7 @@ Description: {}",
                 .filter_map(|url| match ctf::binary_from_url(url.as_str()) {
                     Ok(binary) => Some(binary),
                     Err(e) => {
-                        println!("{}", e);
+                        warn!("{}", e);
                         None
                     }
                 })
@@ -79,6 +80,10 @@ Description: {}",
                     url: format!("nc://{}", url),
                 }))
                 .collect(),
+            flags: Vec::new(),
+            claims: Vec::new(),
+            status: None,
+            notes: Vec::new(),
         })
     }
     Ok(ctf)
@@ -114,4 +119,15 @@ impl engines::Engine for WatevrEngine {
     ) -> engines::FetchResult<'a> {
         fetch(client, cookie_store, remote).boxed()
     }
+
+    fn submit<'a>(
+        &self,
+        _client: &'a http::Client,
+        _cookie_store: &'a CookieStore,
+        _remote: &'a ctf::Remote,
+        _challenge: &'a str,
+        _flag: &'a str,
+    ) -> engines::SubmitResult<'a> {
+        engines::submit_unsupported("watevr")
+    }
 }
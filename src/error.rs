@@ -0,0 +1,100 @@
+//! Errors that the operations on CTF repositories report, e.g., a missing challenge.
+//!
+//! `ctf` returns them wrapped into `anyhow::Error`, and `workspace` returns them as is.
+
+use std::fmt::{self, Display, Formatter};
+use std::path::PathBuf;
+
+/// What a name refers to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Challenge,
+    Binary,
+    Alternative,
+    Service,
+    Remote,
+}
+
+impl Kind {
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::Challenge => "Challenge",
+            Kind::Binary => "Binary",
+            Kind::Alternative => "Alternative",
+            Kind::Service => "Service",
+            Kind::Remote => "Remote",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// No `.ctf` in the directory or any of its parents
+    NotAWorkspace(PathBuf),
+    NoSuchChallenge(String),
+    NoSuchBinary(String),
+    NoSuchAlternative(String),
+    NoSuchService(String),
+    NoSuchRemote(String),
+    AlreadyExists(Kind, String),
+    /// Names must be usable as file names
+    InvalidName(Kind, String),
+    /// A binary must be put into the challenge directory before it is added
+    MissingFile(PathBuf),
+    /// An alternative cannot be removed while another one is patched on top of it
+    BaseOfPatched(String, String),
+    /// File system, git or `.ctf` format errors
+    Other(anyhow::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NotAWorkspace(dir) => write!(
+                f,
+                "No .ctf file in {} or any of the parent directories",
+                dir.display()
+            ),
+            Error::NoSuchChallenge(name) => write!(f, "No such challenge: {}", name),
+            Error::NoSuchBinary(name) => write!(f, "No such binary: {}", name),
+            Error::NoSuchAlternative(name) => write!(f, "No such alternative: {}", name),
+            Error::NoSuchService(name) => write!(f, "No such service: {}", name),
+            Error::NoSuchRemote(name) => write!(f, "Remote {} does not exist", name),
+            Error::AlreadyExists(kind, name) => {
+                write!(f, "{} {} already exists", kind.name(), name)
+            }
+            Error::InvalidName(kind, name) => {
+                write!(f, "{} name {:?} is not valid", kind.name(), name)
+            }
+            Error::MissingFile(path) => write!(f, "{} does not exist", path.display()),
+            Error::BaseOfPatched(name, patched) => {
+                write!(
+                    f,
+                    "Cannot remove {}, {} is patched on top of it",
+                    name, patched
+                )
+            }
+            Error::Other(e) => write!(f, "{:#}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<anyhow::Error> for Error {
+    fn from(e: anyhow::Error) -> Self {
+        /* Keep the typed errors of the lower-level helpers. */
+        match e.downcast::<Error>() {
+            Ok(e) => e,
+            Err(e) => Error::Other(e),
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Other(e.into())
+    }
+}
@@ -0,0 +1,45 @@
+use anyhow::{bail, Result};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Returns the command that runs the generated exploit and the files that it consists of.
+pub fn exploit_command(challenge_dir: &Path) -> Result<(Command, Vec<PathBuf>)> {
+    if challenge_dir.join("pwnit.py").exists() {
+        let mut command = Command::new("./pwnit.py");
+        command.current_dir(challenge_dir);
+        Ok((command, vec![challenge_dir.join("pwnit.py")]))
+    } else if challenge_dir.join("pwnit").join("Cargo.toml").exists() {
+        let mut command = Command::new("cargo");
+        command
+            .args([
+                "run",
+                "--quiet",
+                "--manifest-path",
+                "pwnit/Cargo.toml",
+                "--",
+            ])
+            .current_dir(challenge_dir);
+        Ok((command, vec![challenge_dir.join("pwnit")]))
+    } else {
+        bail!("No pwnit.py or pwnit/Cargo.toml, do ctf exploit init first")
+    }
+}
+
+/// Formats data as a Python bytes literal.
+pub fn python_bytes(bytes: &[u8]) -> String {
+    let mut result = String::from("b\"");
+    for b in bytes {
+        match *b {
+            b'\\' | b'"' => {
+                result.push('\\');
+                result.push(*b as char);
+            }
+            b'\n' => result.push_str("\\n"),
+            b'\t' => result.push_str("\\t"),
+            b' '..=b'~' => result.push(*b as char),
+            _ => result.push_str(&format!("\\x{:02x}", b)),
+        }
+    }
+    result.push('"');
+    result
+}
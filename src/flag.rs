@@ -0,0 +1,131 @@
+use anyhow::Result;
+use console::style;
+use regex::Regex;
+use serde::Serialize;
+
+use crate::{archive, compression, ctf};
+
+/// Flag format used when the CTF does not define one, e.g. `CTF{s0me_fl4g}`.
+pub static DEFAULT_FORMAT: &str = r"\w+\{[^}\s]+\}";
+
+/// How many archives and compressed streams can be nested into each other.
+const MAX_DEPTH: usize = 8;
+
+/// Flag found in a file, e.g. in `dist.tar.gz:flag.txt`.
+#[derive(Serialize)]
+pub struct Match {
+    pub location: String,
+    pub flag: String,
+}
+
+/// Finds flags of a specific format.
+pub struct Detector {
+    regex: Regex,
+}
+
+impl Detector {
+    pub fn new(format: &str) -> Result<Detector> {
+        Ok(Detector {
+            regex: Regex::new(format)?,
+        })
+    }
+
+    /// Uses the CTF flag format, or the default one if it is not set.
+    pub fn for_ctf(ctf: &ctf::CTF) -> Result<Detector> {
+        Detector::new(ctf.flag_format.as_deref().unwrap_or(DEFAULT_FORMAT))
+    }
+
+    /// Returns the distinct flags found in a text, in the order of their first occurrence.
+    pub fn find(&self, text: &str) -> Vec<String> {
+        let mut result: Vec<String> = Vec::new();
+        for m in self.regex.find_iter(text) {
+            if !result.iter().any(|flag| flag == m.as_str()) {
+                result.push(m.as_str().to_string());
+            }
+        }
+        result
+    }
+
+    /// Makes the flags in a text stand out on a terminal.
+    pub fn highlight(&self, text: &str) -> String {
+        self.regex
+            .replace_all(text, |captures: &regex::Captures| {
+                style(&captures[0]).bold().red().to_string()
+            })
+            .into_owned()
+    }
+
+    /// Reports each flag once, in the innermost file that contains it.
+    fn scan_into(&self, location: &str, bytes: &[u8], depth: usize, result: &mut Vec<Match>) {
+        let start = result.len();
+        if depth < MAX_DEPTH {
+            if let Some(format) = compression::detect(bytes) {
+                let decompressed = compression::decompress_prefix(format, bytes);
+                self.scan_into(
+                    &format!("{}:<{}>", location, format.name()),
+                    &decompressed,
+                    depth + 1,
+                    result,
+                );
+            } else if let Ok(Some(entries)) = archive::extract(bytes) {
+                for entry in entries {
+                    self.scan_into(
+                        &format!("{}:{}", location, entry.name),
+                        &entry.data,
+                        depth + 1,
+                        result,
+                    );
+                }
+            }
+        }
+        for flag in self.find(&String::from_utf8_lossy(bytes)) {
+            /* Archive headers may stick to the flags, e.g. "flag.txthxp{...}" in a zip. */
+            if !result[start..].iter().any(|m| flag.contains(&m.flag)) {
+                result.push(Match {
+                    location: location.to_string(),
+                    flag,
+                });
+            }
+        }
+    }
+
+    /// Finds flags in a file, looking inside compressed streams and archives.
+    pub fn scan(&self, location: &str, bytes: &[u8]) -> Vec<Match> {
+        let mut result = Vec::new();
+        self.scan_into(location, bytes, 0, &mut result);
+        result
+    }
+}
+
+/// Guesses the flag format from the example flags in the challenge descriptions, e.g.
+/// `Flag format: hxp{...}`, by taking their most common prefix. Only the lines that talk about
+/// the flag format or an example flag are considered, since the guess is never overridden and
+/// code or formulas, e.g. `\frac{1}{2}`, must not become the flag format.
+pub fn guess_format<'a, I>(descriptions: I) -> Result<Option<String>>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let example_regex = Regex::new(r"\b(\w+)\{[^}\n]*\}")?;
+    let flag_regex = Regex::new(r"(?i)\bflags?\b")?;
+    let phrase_regex = Regex::new(r"(?i)\b(?:format|example|looks?\s+like)\b")?;
+    let mut prefixes: Vec<(String, usize)> = Vec::new();
+    let lines = descriptions
+        .into_iter()
+        .flat_map(str::lines)
+        .filter(|line| flag_regex.is_match(line) && phrase_regex.is_match(line));
+    for line in lines {
+        for captures in example_regex.captures_iter(line) {
+            let prefix = &captures[1];
+            match prefixes.iter_mut().find(|(existing, _)| existing == prefix) {
+                Some((_, count)) => *count += 1,
+                None => prefixes.push((prefix.to_string(), 1)),
+            }
+        }
+    }
+    /* max_by_key() returns the last maximum, prefer the first one. */
+    Ok(prefixes
+        .iter()
+        .rev()
+        .max_by_key(|(_, count)| *count)
+        .map(|(prefix, _)| format!(r"{}\{{[^}}\s]+\}}", regex::escape(prefix))))
+}
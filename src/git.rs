@@ -1,55 +1,376 @@
-use std::process::{Command, Stdio};
+use std::process::Command;
 
-use anyhow::Result;
+use anyhow::{anyhow, bail, Result};
+use git2::{
+    DiffOptions, ErrorCode, Oid, Repository, RepositoryInitOptions, Signature, Status,
+    StatusOptions,
+};
 
 use crate::ctf;
 use crate::subprocess::check_call;
-use std::path::Path;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+/// Files that `ctf::store` writes.
+static METADATA: [&str; 3] = [".ctf", ".gitignore", ".gitattributes"];
+
+/// Identity used when git does not have one, e.g. on CI.
+static DEFAULT_NAME: &str = "ctf";
+static DEFAULT_EMAIL: &str = "ctf@localhost";
 
 pub fn init(repo: &Path) -> Result<()> {
-    check_call(
-        Command::new("git")
-            .args(["init", "--initial-branch=master"])
-            .current_dir(repo),
-    )
+    let mut options = RepositoryInitOptions::new();
+    options.initial_head("master");
+    Repository::init_opts(repo, &options)?;
+    Ok(())
+}
+
+fn signature(repo: &Repository) -> Result<Signature<'static>> {
+    match repo.signature() {
+        Ok(signature) => Ok(signature),
+        Err(e) if e.code() == ErrorCode::NotFound => {
+            Ok(Signature::now(DEFAULT_NAME, DEFAULT_EMAIL)?)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Returns a path relative to the repository, which is what the index uses.
+fn relative(repo: &Path, path: &Path) -> Result<PathBuf> {
+    match path.is_absolute() {
+        true => path
+            .strip_prefix(repo)
+            .map(Path::to_path_buf)
+            .map_err(|_| anyhow!("{} is not in {}", path.display(), repo.display())),
+        false => Ok(path.to_path_buf()),
+    }
 }
 
-pub fn commit(context: &ctf::Context, message: &str) -> Result<()> {
+/// Stores `.ctf` and commits it together with the files that the command created, changed or
+/// removed; these are staged even if `.gitignore` excludes them. Other changes in the working tree
+/// are left alone. Returns the new commit, or None if nothing changed.
+pub fn commit_files(
+    context: &ctf::Context,
+    message: &str,
+    paths: &[PathBuf],
+) -> Result<Option<Oid>> {
     ctf::store(context)?;
-    check_call(
-        Command::new("git")
-            .args(["add", "."])
-            .current_dir(&context.root),
-    )?;
-    check_call(
-        Command::new("git")
-            .args(["commit", "-m", message])
-            .current_dir(&context.root),
+    let repo = Repository::open(&context.root)?;
+    let mut index = repo.index()?;
+    let metadata = METADATA.iter().map(PathBuf::from);
+    for path in metadata.chain(paths.iter().map(|path| path.to_path_buf())) {
+        let path = relative(&context.root, &path)?;
+        if context.root.join(&path).exists() {
+            index.add_path(&path)?;
+        } else if index.get_path(&path, 0).is_some() {
+            index.remove_path(&path)?;
+        }
+    }
+    index.write()?;
+    let tree = repo.find_tree(index.write_tree()?)?;
+    let parent = match repo.head() {
+        Ok(head) => Some(head.peel_to_commit()?),
+        Err(e) if e.code() == ErrorCode::UnbornBranch => None,
+        Err(e) => return Err(e.into()),
+    };
+    if let Some(parent) = &parent {
+        if parent.tree_id() == tree.id() {
+            /* Nothing to commit, e.g., checkout did not change anything. */
+            return Ok(None);
+        }
+    }
+    let signature = signature(&repo)?;
+    let parents: Vec<_> = parent.iter().collect();
+    let oid = repo.commit(
+        Some("HEAD"),
+        &signature,
+        &signature,
+        message,
+        &tree,
+        &parents,
     )?;
-    Ok(())
+    Ok(Some(oid))
+}
+
+/// Stores and commits `.ctf`.
+pub fn commit(context: &ctf::Context, message: &str) -> Result<Option<Oid>> {
+    commit_files(context, message, &[])
 }
 
 pub fn get_option(root: &Path, name: &str) -> Result<Option<String>> {
-    let child = Command::new("git")
-        .args(["config", name])
-        .current_dir(root)
-        .stdin(Stdio::null())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::null())
-        .spawn()?;
-    let output = child.wait_with_output()?;
-    if output.status.success() {
-        Ok(Some(String::from_utf8(output.stdout)?))
-    } else {
-        Ok(None)
+    let config = Repository::open(root)?.config()?;
+    match config.get_string(name) {
+        Ok(value) => Ok(Some(value)),
+        Err(e) if e.code() == ErrorCode::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
     }
 }
 
 pub fn set_option(root: &Path, name: &str, value: &str) -> Result<()> {
-    check_call(
-        Command::new("git")
-            .args(["config", name, value])
-            .current_dir(root),
-    )?;
+    let mut config = Repository::open(root)?.config()?;
+    config.set_str(name, value)?;
     Ok(())
 }
+
+/// Makes git merge `.ctf` with `ctf merge-driver`, see `.gitattributes`.
+pub fn setup_merge_driver(repo: &Path) -> Result<()> {
+    set_option(repo, "merge.ctf.name", "ctftool .ctf merge driver")?;
+    set_option(repo, "merge.ctf.driver", "ctf merge-driver %O %A %B")
+}
+
+pub fn current_branch(repo: &Path) -> Result<String> {
+    let repo = Repository::open(repo)?;
+    let head = repo.head()?;
+    if !head.is_branch() {
+        bail!("Not on a branch");
+    }
+    Ok(head
+        .shorthand()
+        .ok_or_else(|| anyhow!("Branch name is not UTF-8"))?
+        .to_string())
+}
+
+/* Fetching, rebasing and pushing use git itself: libgit2 supports neither merge drivers nor the
+ * user's credential helpers and ssh config. */
+
+/// Creates a git command that uses the same identity as `commit`.
+fn git_command(repo: &Path) -> Result<Command> {
+    let mut command = Command::new("git");
+    command.current_dir(repo);
+    if let Err(e) = Repository::open(repo)?.signature() {
+        if e.code() != ErrorCode::NotFound {
+            return Err(e.into());
+        }
+        for (name, value) in [
+            ("GIT_AUTHOR_NAME", DEFAULT_NAME),
+            ("GIT_AUTHOR_EMAIL", DEFAULT_EMAIL),
+            ("GIT_COMMITTER_NAME", DEFAULT_NAME),
+            ("GIT_COMMITTER_EMAIL", DEFAULT_EMAIL),
+        ] {
+            command.env(name, value);
+        }
+    }
+    Ok(command)
+}
+
+pub fn fetch(repo: &Path, remote: &str) -> Result<()> {
+    check_call(git_command(repo)?.args(["fetch", remote]))
+}
+
+pub fn rev_exists(repo: &Path, rev: &str) -> Result<bool> {
+    Ok(Repository::open(repo)?.revparse_single(rev).is_ok())
+}
+
+/// Rebases the current branch; returns false if there are conflicts, leaving the rebase in
+/// progress.
+pub fn rebase(repo: &Path, upstream: &str) -> Result<bool> {
+    Ok(git_command(repo)?
+        .args(["rebase", upstream])
+        .status()?
+        .success())
+}
+
+/// Returns the files with unresolved conflicts.
+pub fn conflicts(repo: &Path) -> Result<Vec<String>> {
+    let index = Repository::open(repo)?.index()?;
+    let mut result = Vec::new();
+    for conflict in index.conflicts()? {
+        let conflict = conflict?;
+        if let Some(entry) = conflict.our.or(conflict.their).or(conflict.ancestor) {
+            result.push(String::from_utf8_lossy(&entry.path).into_owned());
+        }
+    }
+    Ok(result)
+}
+
+pub fn push(repo: &Path, remote: &str, branch: &str) -> Result<()> {
+    check_call(git_command(repo)?.args(["push", remote, &format!("HEAD:{}", branch)]))
+}
+
+/// Returns the author of the next commit, e.g. `ctf <ctf@localhost>`.
+pub fn author(repo: &Path) -> Result<String> {
+    let signature = signature(&Repository::open(repo)?)?;
+    Ok(format!(
+        "{} <{}>",
+        String::from_utf8_lossy(signature.name_bytes()),
+        String::from_utf8_lossy(signature.email_bytes())
+    ))
+}
+
+/// Commit made by a command.
+pub struct Operation {
+    pub id: Oid,
+    pub short_id: String,
+    pub summary: String,
+    pub author: String,
+    /// Commit time in seconds since the epoch
+    pub time: i64,
+    /// `.ctf` contents after the operation, empty if there was none
+    pub ctf: Vec<u8>,
+}
+
+/// Returns up to `count` last operations on the current branch, most recent first.
+pub fn history(repo: &Path, count: usize) -> Result<Vec<Operation>> {
+    let repo = Repository::open(repo)?;
+    let mut walk = repo.revwalk()?;
+    walk.push_head()?;
+    walk.simplify_first_parent()?;
+    let mut result = Vec::new();
+    for oid in walk.take(count) {
+        let commit = repo.find_commit(oid?)?;
+        let ctf = match commit.tree()?.get_path(Path::new(".ctf")) {
+            Ok(entry) => entry.to_object(&repo)?.peel_to_blob()?.content().to_vec(),
+            Err(e) if e.code() == ErrorCode::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        result.push(Operation {
+            id: commit.id(),
+            short_id: commit
+                .as_object()
+                .short_id()?
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            summary: String::from_utf8_lossy(commit.summary_bytes().unwrap_or_default())
+                .into_owned(),
+            author: String::from_utf8_lossy(commit.author().name_bytes()).into_owned(),
+            time: commit.time().seconds(),
+            ctf,
+        });
+    }
+    Ok(result)
+}
+
+/// Restores the tracked files that changed since a commit to their versions in that commit and
+/// stages them; files that the commit did not have are deleted, unless `keep` says otherwise.
+/// Fails without changing anything if some of these files have uncommitted changes. Returns the
+/// restored files.
+pub fn restore<F>(root: &Path, id: Oid, keep: F) -> Result<Vec<PathBuf>>
+where
+    F: Fn(&Path) -> bool,
+{
+    let repo = Repository::open(root)?;
+    let head_tree = repo.head()?.peel_to_tree()?;
+    let tree = repo.find_commit(id)?.tree()?;
+    let diff = repo.diff_tree_to_tree(Some(&head_tree), Some(&tree), None)?;
+    let mut changes = Vec::new();
+    for delta in diff.deltas() {
+        let file = match delta.new_file().exists() {
+            true => delta.new_file(),
+            false => delta.old_file(),
+        };
+        let path = file
+            .path()
+            .ok_or_else(|| anyhow!("Path is not UTF-8"))?
+            .to_path_buf();
+        let status = match repo.status_file(&path) {
+            Ok(status) => status,
+            /* Neither in the index nor in the working tree. */
+            Err(e) if e.code() == ErrorCode::NotFound => Status::CURRENT,
+            Err(e) => return Err(e.into()),
+        };
+        if status != Status::CURRENT && status != Status::IGNORED {
+            bail!("{} has uncommitted changes", path.display());
+        }
+        let blob = match delta.new_file().exists() {
+            true => Some((
+                repo.find_blob(file.id())?.content().to_vec(),
+                file.mode() == git2::FileMode::BlobExecutable,
+            )),
+            false => None,
+        };
+        changes.push((path, blob));
+    }
+    let mut index = repo.index()?;
+    let mut result = Vec::new();
+    for (path, blob) in changes {
+        let full_path = root.join(&path);
+        match blob {
+            Some((content, executable)) => {
+                if let Some(parent) = full_path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::write(&full_path, content)?;
+                let mut permissions = fs::metadata(&full_path)?.permissions();
+                permissions.set_mode(match executable {
+                    true => 0o755,
+                    false => 0o644,
+                });
+                fs::set_permissions(&full_path, permissions)?;
+                index.add_path(&path)?;
+            }
+            None => {
+                if !keep(&path) && full_path.exists() {
+                    fs::remove_file(&full_path)?;
+                }
+                index.remove_path(&path)?;
+            }
+        }
+        result.push(full_path);
+    }
+    index.write()?;
+    Ok(result)
+}
+
+/// Returns the abbreviated hash of the last commit that touched the paths, with a `-dirty` suffix
+/// if they have uncommitted changes.
+pub fn revision(repo: &Path, paths: &[PathBuf]) -> Result<Option<String>> {
+    let root = repo;
+    let repo = Repository::open(root)?;
+    let paths = paths
+        .iter()
+        .map(|path| relative(root, path))
+        .collect::<Result<Vec<_>>>()?;
+    let mut walk = repo.revwalk()?;
+    if let Err(e) = walk.push_head() {
+        return match e.code() {
+            ErrorCode::UnbornBranch | ErrorCode::NotFound => Ok(None),
+            _ => Err(e.into()),
+        };
+    }
+    let mut last = None;
+    for oid in walk {
+        let commit = repo.find_commit(oid?)?;
+        let parent_tree = match commit.parents().next() {
+            Some(parent) => Some(parent.tree()?),
+            None => None,
+        };
+        let mut options = DiffOptions::new();
+        for path in &paths {
+            options.pathspec(path);
+        }
+        let diff = repo.diff_tree_to_tree(
+            parent_tree.as_ref(),
+            Some(&commit.tree()?),
+            Some(&mut options),
+        )?;
+        if diff.deltas().len() > 0 {
+            last = Some(commit);
+            break;
+        }
+    }
+    let hash = match last {
+        Some(commit) => commit
+            .as_object()
+            .short_id()?
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+        None => return Ok(None),
+    };
+    let mut options = StatusOptions::new();
+    options.include_untracked(false);
+    for path in &paths {
+        options.pathspec(path);
+    }
+    let clean = repo
+        .statuses(Some(&mut options))?
+        .iter()
+        .all(|entry| entry.status() == Status::CURRENT);
+    Ok(Some(if clean {
+        hash
+    } else {
+        format!("{}-dirty", hash)
+    }))
+}
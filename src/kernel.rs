@@ -0,0 +1,462 @@
+use crate::compression::{self, Format};
+use crate::{path, shell};
+use anyhow::{anyhow, bail, Result};
+use elf::endian::AnyEndian;
+use elf::ElfBytes;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+lazy_static! {
+    static ref KERNEL_NAME_REGEX: Regex =
+        Regex::new(r"^(bzImage|zImage|Image|vmlinuz.*|vmlinux)$").unwrap();
+    static ref INITRAMFS_NAME_REGEX: Regex =
+        Regex::new(r"^(initramfs|initrd|rootfs|ramdisk).*$|\.cpio(\.\w+)?$").unwrap();
+}
+
+const ELF_MAGIC: &[u8] = b"\x7fELF";
+
+/// Initramfs with the exploit inserted, which is not a challenge file.
+pub const PWNIT_INITRAMFS: &str = "pwnit.cpio";
+
+/// Finds kernel images and initramfs archives in a challenge directory.
+pub fn find_files(dir: &Path) -> Result<(Vec<PathBuf>, Vec<PathBuf>)> {
+    let mut kernels = Vec::new();
+    let mut initramfses = Vec::new();
+    for path in path::walk(dir)? {
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        if KERNEL_NAME_REGEX.is_match(&name) {
+            kernels.push(path);
+        } else if INITRAMFS_NAME_REGEX.is_match(&name) && !name.starts_with(PWNIT_INITRAMFS) {
+            initramfses.push(path);
+        }
+    }
+    /* vmlinux next to a compressed image was most likely extracted from it. */
+    let compressed: Vec<PathBuf> = kernels
+        .iter()
+        .filter(|path| path.file_name() != Some("vmlinux".as_ref()))
+        .cloned()
+        .collect();
+    kernels.retain(|path| {
+        path.file_name() != Some("vmlinux".as_ref())
+            || !compressed
+                .iter()
+                .any(|other| other.parent() == path.parent())
+    });
+    Ok((kernels, initramfses))
+}
+
+/// Where vmlinux was found.
+pub struct Vmlinux {
+    pub format: Option<Format>,
+    pub offset: usize,
+    pub bytes: Vec<u8>,
+}
+
+/// Extracts vmlinux from a compressed kernel image, e.g. bzImage.
+///
+/// Like the kernel's own `scripts/extract-vmlinux`, tries to decompress the data at every
+/// compression magic and picks the first result that is an ELF file.
+pub fn extract_vmlinux(bytes: &[u8]) -> Option<Vmlinux> {
+    if bytes.starts_with(ELF_MAGIC) {
+        return Some(Vmlinux {
+            format: None,
+            offset: 0,
+            bytes: bytes.to_vec(),
+        });
+    }
+    let streams = compression::find_streams(bytes);
+    /* LZMA has a short magic, which produces a lot of false positives, so try it last. */
+    let (lzma, others): (Vec<_>, Vec<_>) = streams
+        .into_iter()
+        .partition(|(_, format)| *format == Format::Lzma);
+    for (offset, format) in others.into_iter().chain(lzma) {
+        let output = compression::decompress_prefix(format, &bytes[offset..]);
+        if output.starts_with(ELF_MAGIC) {
+            return Some(Vmlinux {
+                format: Some(format),
+                offset,
+                bytes: output,
+            });
+        }
+    }
+    None
+}
+
+/// Returns the version banner of a kernel.
+pub fn version(vmlinux: &[u8]) -> Option<String> {
+    let start = find(vmlinux, b"Linux version ")?;
+    let end = vmlinux[start..]
+        .iter()
+        .position(|b| *b == 0 || *b == b'\n')
+        .map(|n| start + n)
+        .unwrap_or(vmlinux.len());
+    Some(String::from_utf8_lossy(&vmlinux[start..end]).into_owned())
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Extracts the config embedded by CONFIG_IKCONFIG.
+pub fn extract_ikconfig(vmlinux: &[u8]) -> Result<Option<String>> {
+    let start = match find(vmlinux, b"IKCFG_ST") {
+        Some(start) => start + 8,
+        None => return Ok(None),
+    };
+    let config = compression::decompress(Format::Gzip, &vmlinux[start..])?;
+    Ok(Some(String::from_utf8(config)?))
+}
+
+/// Parses `CONFIG_FOO=value` lines; options that are not set are omitted.
+pub fn parse_config(config: &str) -> BTreeMap<String, String> {
+    config
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+}
+
+pub struct Mitigation {
+    pub name: &'static str,
+    /* Enabling any of these options enables the mitigation. */
+    pub options: &'static [&'static str],
+    /* CPU feature that the mitigation needs as well. */
+    pub cpu_feature: Option<&'static str>,
+}
+
+pub const MITIGATIONS: &[Mitigation] = &[
+    Mitigation {
+        name: "SMEP",
+        options: &[],
+        cpu_feature: Some("smep"),
+    },
+    Mitigation {
+        name: "SMAP",
+        options: &["CONFIG_X86_SMAP"],
+        cpu_feature: Some("smap"),
+    },
+    Mitigation {
+        name: "KPTI",
+        options: &["CONFIG_PAGE_TABLE_ISOLATION", "CONFIG_UNMAP_KERNEL_AT_EL0"],
+        cpu_feature: None,
+    },
+    Mitigation {
+        name: "KASLR",
+        options: &["CONFIG_RANDOMIZE_BASE"],
+        cpu_feature: None,
+    },
+    Mitigation {
+        name: "Hardened usercopy",
+        options: &["CONFIG_HARDENED_USERCOPY"],
+        cpu_feature: None,
+    },
+    Mitigation {
+        name: "Freelist hardening",
+        options: &["CONFIG_SLAB_FREELIST_HARDENED"],
+        cpu_feature: None,
+    },
+    Mitigation {
+        name: "Freelist randomization",
+        options: &["CONFIG_SLAB_FREELIST_RANDOM"],
+        cpu_feature: None,
+    },
+    Mitigation {
+        name: "Static usermode helper",
+        options: &["CONFIG_STATIC_USERMODEHELPER"],
+        cpu_feature: None,
+    },
+];
+
+impl Mitigation {
+    /// Returns whether the config and the QEMU arguments from the run script, if any, enable the
+    /// mitigation, or None if this depends on the CPU.
+    pub fn is_enabled(
+        &self,
+        config: &BTreeMap<String, String>,
+        qemu_args: Option<&[String]>,
+    ) -> Option<bool> {
+        let configured = self.options.is_empty()
+            || self
+                .options
+                .iter()
+                .any(|option| config.get(*option).map(String::as_str) == Some("y"));
+        let feature = match (configured, self.cpu_feature) {
+            (false, _) => return Some(false),
+            (true, None) => return Some(true),
+            (true, Some(feature)) => feature,
+        };
+        let args = qemu_args?;
+        /* E.g., nosmep on the kernel command line turns the feature off. */
+        if kernel_params(args).any(|param| param == format!("no{}", feature)) {
+            return Some(false);
+        }
+        cpu_feature(args, feature)
+    }
+}
+
+/// QEMU CPU models that lack SMEP and SMAP, the first one being the default.
+const PLAIN_CPU_MODELS: &[&str] = &["qemu64", "qemu32", "kvm64", "kvm32"];
+
+/// Returns the value of the last occurrence of a QEMU option, e.g., `-cpu`.
+fn qemu_option<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    args.windows(2)
+        .filter(|pair| pair[0] == format!("-{}", name) || pair[0] == format!("--{}", name))
+        .map(|pair| pair[1].as_str())
+        .last()
+}
+
+fn kernel_params(args: &[String]) -> impl Iterator<Item = &str> {
+    qemu_option(args, "append").unwrap_or("").split_whitespace()
+}
+
+/// Returns whether `-cpu MODEL,+FEATURE,...` enables a CPU feature, or None if this depends on the
+/// model, e.g., `host`.
+pub fn cpu_feature(args: &[String], feature: &str) -> Option<bool> {
+    let mut parts = qemu_option(args, "cpu")
+        .unwrap_or(PLAIN_CPU_MODELS[0])
+        .split(',');
+    let model = parts.next().unwrap();
+    let mut enabled = match PLAIN_CPU_MODELS.contains(&model) {
+        true => Some(false),
+        false => None,
+    };
+    for part in parts {
+        let value = match part.split_once('=') {
+            Some((name, value)) if name == feature => Some(matches!(value, "on" | "true" | "yes")),
+            Some(_) => None,
+            None if part == format!("+{}", feature) || part == feature => Some(true),
+            None if part == format!("-{}", feature) => Some(false),
+            None => None,
+        };
+        enabled = value.or(enabled);
+    }
+    enabled
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CpioEntry {
+    pub ino: u32,
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub nlink: u32,
+    pub mtime: u32,
+    pub devmajor: u32,
+    pub devminor: u32,
+    pub rdevmajor: u32,
+    pub rdevminor: u32,
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+pub const S_IFMT: u32 = 0o170000;
+pub const S_IFREG: u32 = 0o100000;
+
+impl CpioEntry {
+    pub fn is_file(&self) -> bool {
+        self.mode & S_IFMT == S_IFREG
+    }
+}
+
+const CPIO_HEADER_SIZE: usize = 110;
+const CPIO_TRAILER: &str = "TRAILER!!!";
+
+fn align4(value: usize) -> usize {
+    (value + 3) & !3
+}
+
+fn parse_hex(bytes: &[u8]) -> Result<u32> {
+    Ok(u32::from_str_radix(std::str::from_utf8(bytes)?, 16)?)
+}
+
+/// Parses an initramfs, which is a sequence of possibly compressed "newc" cpio archives.
+pub fn parse_cpio(bytes: &[u8]) -> Result<Vec<CpioEntry>> {
+    let mut entries = Vec::new();
+    let mut pos = 0;
+    loop {
+        while pos < bytes.len() && bytes[pos] == 0 {
+            pos += 1;
+        }
+        /* The last entry may lack padding. */
+        if pos >= bytes.len() {
+            break;
+        }
+        if let Some(format) = compression::detect(&bytes[pos..]) {
+            let (_, decompressed) = compression::maybe_decompress(bytes[pos..].to_vec())?;
+            entries.extend(parse_cpio(&decompressed).map_err(|e| {
+                anyhow!(
+                    "Could not parse {} compressed archive: {}",
+                    format.name(),
+                    e
+                )
+            })?);
+            break;
+        }
+        let header = bytes
+            .get(pos..pos + CPIO_HEADER_SIZE)
+            .ok_or_else(|| anyhow!("Truncated cpio header at 0x{:x}", pos))?;
+        if &header[..6] != b"070701" && &header[..6] != b"070702" {
+            bail!("Unsupported cpio format at 0x{:x}", pos);
+        }
+        let field = |i: usize| parse_hex(&header[6 + i * 8..6 + (i + 1) * 8]);
+        let filesize = field(6)? as usize;
+        let namesize = field(11)? as usize;
+        let name_start = pos + CPIO_HEADER_SIZE;
+        let name = bytes
+            .get(name_start..name_start + namesize)
+            .ok_or_else(|| anyhow!("Truncated cpio file name at 0x{:x}", pos))?;
+        let name = String::from_utf8_lossy(name.split(|b| *b == 0).next().unwrap()).into_owned();
+        let data_start = align4(name_start + namesize);
+        let data = bytes
+            .get(data_start..data_start + filesize)
+            .ok_or_else(|| anyhow!("Truncated cpio file data for {}", name))?;
+        pos = align4(data_start + filesize);
+        if name == CPIO_TRAILER {
+            continue;
+        }
+        entries.push(CpioEntry {
+            ino: field(0)?,
+            mode: field(1)?,
+            uid: field(2)?,
+            gid: field(3)?,
+            nlink: field(4)?,
+            mtime: field(5)?,
+            devmajor: field(7)?,
+            devminor: field(8)?,
+            rdevmajor: field(9)?,
+            rdevminor: field(10)?,
+            name,
+            data: data.to_vec(),
+        });
+    }
+    Ok(entries)
+}
+
+/// Serializes entries as a single "newc" cpio archive.
+pub fn write_cpio(entries: &[CpioEntry]) -> Vec<u8> {
+    let mut result = Vec::new();
+    let trailer = CpioEntry {
+        nlink: 1,
+        name: CPIO_TRAILER.to_string(),
+        ..Default::default()
+    };
+    for entry in entries.iter().chain([&trailer]) {
+        let fields = [
+            entry.ino,
+            entry.mode,
+            entry.uid,
+            entry.gid,
+            entry.nlink,
+            entry.mtime,
+            entry.data.len() as u32,
+            entry.devmajor,
+            entry.devminor,
+            entry.rdevmajor,
+            entry.rdevminor,
+            entry.name.len() as u32 + 1,
+            0,
+        ];
+        result.extend_from_slice(b"070701");
+        for field in fields {
+            result.extend_from_slice(format!("{:08x}", field).as_bytes());
+        }
+        result.extend_from_slice(entry.name.as_bytes());
+        result.push(0);
+        result.resize(align4(result.len()), 0);
+        result.extend_from_slice(&entry.data);
+        result.resize(align4(result.len()), 0);
+    }
+    result.resize(result.len().div_ceil(512) * 512, 0);
+    result
+}
+
+/// Adds a regular file to an archive or replaces an existing one.
+pub fn insert_file(entries: &mut Vec<CpioEntry>, name: &str, mode: u32, data: Vec<u8>) {
+    let name = name.trim_start_matches('/').to_string();
+    let ino = entries.iter().map(|entry| entry.ino).max().unwrap_or(0) + 1;
+    entries.retain(|entry| entry.name != name);
+    entries.push(CpioEntry {
+        ino,
+        mode: S_IFREG | mode,
+        nlink: 1,
+        name,
+        data,
+        ..Default::default()
+    });
+}
+
+/// Returns the QEMU command line from a script, starting with the `qemu-system-*` word.
+pub fn parse_run_script(script: &str) -> Option<Vec<String>> {
+    shell::split(script).into_iter().find_map(|words| {
+        let start = words
+            .iter()
+            .position(|word| word.starts_with("qemu-system-"))?;
+        Some(words[start..].to_vec())
+    })
+}
+
+/// Finds a script that runs QEMU in a challenge directory.
+pub fn find_run_script(dir: &Path) -> Result<Option<(PathBuf, Vec<String>)>> {
+    for path in path::walk(dir)? {
+        if path.extension() != Some("sh".as_ref()) {
+            continue;
+        }
+        if let Some(args) = parse_run_script(&String::from_utf8_lossy(&fs::read(&path)?)) {
+            return Ok(Some((path, args)));
+        }
+    }
+    Ok(None)
+}
+
+#[derive(Default)]
+pub struct ModuleInfo {
+    pub name: Option<String>,
+    pub vermagic: Option<String>,
+    pub exports: Vec<String>,
+}
+
+/// Reads `.modinfo` and the exported symbols of a kernel module.
+pub fn analyze_module(bytes: &[u8]) -> Result<ModuleInfo> {
+    let (_, bytes) = compression::maybe_decompress(bytes.to_vec())?;
+    let elf = ElfBytes::<AnyEndian>::minimal_parse(&bytes)?;
+    let mut result = ModuleInfo::default();
+    if let Some(shdr) = elf.section_header_by_name(".modinfo")? {
+        let (data, _) = elf.section_data(&shdr)?;
+        for entry in data.split(|b| *b == 0) {
+            let entry = String::from_utf8_lossy(entry);
+            match entry.split_once('=') {
+                Some(("name", value)) => result.name = Some(value.to_string()),
+                Some(("vermagic", value)) => result.vermagic = Some(value.to_string()),
+                _ => {}
+            }
+        }
+    }
+    if let Some(shdr) = elf.section_header_by_name("__ksymtab_strings")? {
+        let (data, _) = elf.section_data(&shdr)?;
+        for name in data.split(|b| *b == 0).filter(|name| !name.is_empty()) {
+            result
+                .exports
+                .push(String::from_utf8_lossy(name).into_owned());
+        }
+    }
+    if let Some((symtab, strtab)) = elf.symbol_table()? {
+        for symbol in symtab.iter() {
+            if let Some(name) = strtab
+                .get(symbol.st_name as usize)?
+                .strip_prefix("__ksymtab_")
+            {
+                if !result.exports.iter().any(|export| export == name) {
+                    result.exports.push(name.to_string());
+                }
+            }
+        }
+    }
+    result.exports.sort();
+    Ok(result)
+}
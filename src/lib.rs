@@ -2,23 +2,55 @@ use std::ffi::OsString;
 use std::path::PathBuf;
 use std::sync::Once;
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use clap::Parser;
 
+pub mod ad;
+pub mod archive;
+pub mod asm;
 pub mod commands;
+pub mod compression;
+pub mod config;
+pub mod container;
 pub mod ctf;
 pub mod distro;
+pub mod dockerfile;
 pub mod engines;
+pub mod error;
+pub mod exploit;
+pub mod flag;
 pub mod git;
 pub mod http;
+pub mod kernel;
+pub mod menu;
 pub mod option;
 pub mod os_str;
+pub mod package_cache;
+pub mod patch;
+pub mod patchelf;
 pub mod path;
+pub mod pcap;
+pub mod qemu;
+pub mod shell;
 pub mod subprocess;
+pub mod ui;
+pub mod workspace;
+
+pub use ui::Ui;
+pub use workspace::Workspace;
 
 /// Automates all the boring CTF stuff
 #[derive(Parser)]
 struct Opts {
+    /// How read commands, e.g. show, print records: table, json or yaml
+    #[clap(
+        long = "format",
+        value_name = "FORMAT",
+        global = true,
+        default_value = "table"
+    )]
+    output_format: ui::Format,
+
     #[clap(subcommand)]
     subcmd: SubCommand,
 }
@@ -64,35 +96,105 @@ enum SubCommand {
     /// Manages exploits
     #[clap(name = "exploit")]
     Exploit(commands::exploit::Exploit),
+
+    /// Analyzes Linux kernel challenges
+    #[clap(name = "kernel")]
+    Kernel(commands::kernel::Kernel),
+
+    /// Configures and finds flags
+    #[clap(name = "flag")]
+    Flag(commands::flag::Flag),
+
+    /// Attacks other teams in attack-defense CTFs
+    #[clap(name = "ad")]
+    Ad(commands::ad::Ad),
+
+    /// Pulls the teammates' changes and pushes ours
+    #[clap(name = "sync")]
+    Sync(commands::sync::Sync),
+
+    /// Lists the operations and their effects on .ctf
+    #[clap(name = "log")]
+    Log(commands::log::Log),
+
+    /// Reverts the last operations
+    #[clap(name = "undo")]
+    Undo(commands::undo::Undo),
+
+    /// Merges .ctf versions, used by git
+    #[clap(name = "merge-driver")]
+    MergeDriver(commands::merge_driver::MergeDriver),
 }
 
-pub async fn main<I, T>(args: I, current_dir: PathBuf) -> Result<()>
+pub async fn main<I, T>(args: I, current_dir: PathBuf, ui: &dyn Ui) -> Result<()>
 where
     I: IntoIterator<Item = T>,
     T: Into<OsString> + Clone,
 {
     let opts: Opts = Opts::try_parse_from(args)?;
+    let prints_records = match &opts.subcmd {
+        SubCommand::Remote(remote) => remote.prints_records(),
+        SubCommand::Binary(binary) => binary.prints_records(),
+        SubCommand::Challenge(challenge) => challenge.prints_records(),
+        SubCommand::Docker(docker) => docker.prints_records(),
+        SubCommand::Service(service) => service.prints_records(),
+        SubCommand::Exploit(exploit) => exploit.prints_records(),
+        SubCommand::Kernel(kernel) => kernel.prints_records(),
+        SubCommand::Flag(flag) => flag.prints_records(),
+        SubCommand::Ad(ad) => ad.prints_records(),
+        SubCommand::Log(_) => true,
+        _ => false,
+    };
+    if !prints_records && opts.output_format != ui::Format::Table {
+        bail!(
+            "--format {} is not supported by this command",
+            opts.output_format.name()
+        );
+    }
     match opts.subcmd {
         SubCommand::Init(init) => commands::init::run(init, current_dir),
-        SubCommand::Remote(remote) => commands::remote::run(remote, current_dir),
+        SubCommand::Remote(remote) => {
+            commands::remote::run(remote, current_dir, ui, opts.output_format)
+        }
         SubCommand::Fetch(fetch) => commands::fetch::run(fetch, current_dir).await,
-        SubCommand::Checkout(checkout) => commands::checkout::run(checkout, current_dir).await,
-        SubCommand::Login(login) => commands::login::run(login, current_dir).await,
-        SubCommand::Binary(binary) => commands::binary::run(binary, current_dir).await,
-        SubCommand::Challenge(challenge) => commands::challenge::run(challenge, current_dir),
-        SubCommand::Docker(docker) => commands::docker::run(docker, current_dir).await,
-        SubCommand::Service(service) => commands::service::run(service, current_dir),
-        SubCommand::Exploit(exploit) => commands::exploit::run(exploit, current_dir),
+        SubCommand::Checkout(checkout) => commands::checkout::run(checkout, current_dir, ui).await,
+        SubCommand::Login(login) => commands::login::run(login, current_dir, ui).await,
+        SubCommand::Binary(binary) => {
+            commands::binary::run(binary, current_dir, ui, opts.output_format).await
+        }
+        SubCommand::Challenge(challenge) => {
+            commands::challenge::run(challenge, current_dir, ui, opts.output_format)
+        }
+        SubCommand::Docker(docker) => {
+            commands::docker::run(docker, current_dir, ui, opts.output_format).await
+        }
+        SubCommand::Service(service) => {
+            commands::service::run(service, current_dir, ui, opts.output_format)
+        }
+        SubCommand::Exploit(exploit) => {
+            commands::exploit::run(exploit, current_dir, ui, opts.output_format).await
+        }
+        SubCommand::Kernel(kernel) => {
+            commands::kernel::run(kernel, current_dir, ui, opts.output_format)
+        }
+        SubCommand::Flag(flag) => commands::flag::run(flag, current_dir, ui, opts.output_format),
+        SubCommand::Ad(ad) => commands::ad::run(ad, current_dir, ui, opts.output_format).await,
+        SubCommand::Sync(sync) => commands::sync::run(sync, current_dir),
+        SubCommand::Log(log) => commands::log::run(log, current_dir, ui, opts.output_format),
+        SubCommand::Undo(undo) => commands::undo::run(undo, current_dir, ui),
+        SubCommand::MergeDriver(merge_driver) => {
+            commands::merge_driver::run(merge_driver, current_dir, ui)
+        }
     }
 }
 
 #[tokio::main]
-pub async fn main_sync<I, T>(args: I, current_dir: PathBuf) -> Result<()>
+pub async fn main_sync<I, T>(args: I, current_dir: PathBuf, ui: &dyn Ui) -> Result<()>
 where
     I: IntoIterator<Item = T>,
     T: Into<OsString> + Clone,
 {
-    main(args, current_dir).await
+    main(args, current_dir, ui).await
 }
 
 static INIT_LOGGING: Once = Once::new();
@@ -0,0 +1,172 @@
+use elf::endian::AnyEndian;
+use elf::ElfBytes;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::collections::BTreeMap;
+
+lazy_static! {
+    /* "1. alloc", "[2] Free", "3) show", "4 - edit". */
+    static ref OPTION_REGEX: Regex =
+        Regex::new(r"^\s*\[?(\d{1,2})\s*[\].):-]\s+([A-Za-z][\w '/-]*?)\s*$").unwrap();
+    static ref MENU_PROMPT_REGEX: Regex =
+        Regex::new(r"(?i)(choice|option|select|command|menu)[^:>]*[:>]\s*$|^\s*>+\s*$").unwrap();
+    static ref INDEX_PROMPT_REGEX: Regex =
+        Regex::new(r"(?i)\b(index|idx|slot|id|which)\b[^:>]*[:>?]\s*$").unwrap();
+    static ref SIZE_PROMPT_REGEX: Regex =
+        Regex::new(r"(?i)\b(size|length|len|how (many|much|long))\b[^:>]*[:>?]\s*$").unwrap();
+    static ref DATA_PROMPT_REGEX: Regex =
+        Regex::new(r"(?i)\b(data|contents?|note|input|payload|message|text)\b[^:>]*[:>?]\s*$")
+            .unwrap();
+}
+
+/// What a menu option does, guessed from its label.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    Alloc,
+    Free,
+    Edit,
+    Show,
+    Exit,
+    Other,
+}
+
+static ROLE_KEYWORDS: &[(Role, &[&str])] = &[
+    (Role::Exit, &["exit", "quit", "bye", "leave"]),
+    (
+        Role::Free,
+        &["free", "delete", "remove", "del", "drop", "destroy"],
+    ),
+    (
+        Role::Edit,
+        &["edit", "update", "modify", "change", "write", "set"],
+    ),
+    (
+        Role::Show,
+        &["show", "view", "print", "display", "read", "get"],
+    ),
+    (
+        Role::Alloc,
+        &[
+            "alloc", "allocate", "add", "create", "new", "malloc", "make", "buy",
+        ],
+    ),
+];
+
+impl Role {
+    fn guess(label: &str) -> Role {
+        let label = label.to_lowercase();
+        let words: Vec<&str> = label
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .collect();
+        for (role, keywords) in ROLE_KEYWORDS {
+            if words.iter().any(|word| keywords.contains(word)) {
+                return *role;
+            }
+        }
+        Role::Other
+    }
+}
+
+pub struct MenuOption {
+    pub number: u32,
+    pub label: String,
+    pub role: Role,
+}
+
+/// Numbered menu found among the strings of a binary.
+pub struct Menu {
+    /// Prompt that asks for the menu choice
+    pub prompt: Option<String>,
+    pub options: Vec<MenuOption>,
+    /// Prompts that ask for option arguments
+    pub index_prompt: Option<String>,
+    pub size_prompt: Option<String>,
+    pub data_prompt: Option<String>,
+}
+
+/// Returns the printable strings from .rodata, in the order in which they appear there.
+pub fn rodata_strings(bytes: &[u8]) -> Vec<String> {
+    let elf = match ElfBytes::<AnyEndian>::minimal_parse(bytes) {
+        Ok(elf) => elf,
+        Err(_) => return Vec::new(),
+    };
+    let data = match elf.section_header_by_name(".rodata") {
+        Ok(Some(shdr)) => match elf.section_data(&shdr) {
+            Ok((data, None)) => data,
+            _ => return Vec::new(),
+        },
+        _ => return Vec::new(),
+    };
+    data.split(|b| *b == 0)
+        .filter(|s| {
+            s.len() >= 2
+                && s.iter()
+                    .all(|b| (0x20..0x7f).contains(b) || *b == b'\n' || *b == b'\t')
+        })
+        .map(|s| String::from_utf8_lossy(s).into_owned())
+        .collect()
+}
+
+/// Returns the last line of a string, which is what a program waits after.
+fn last_line(s: &str) -> &str {
+    s.rsplit('\n').next().unwrap_or(s)
+}
+
+/// Finds the first prompt that matches a regex, but none of the more specific ones.
+fn find_prompt(strings: &[String], regex: &Regex, exclude: &[&Regex]) -> Option<String> {
+    strings
+        .iter()
+        .map(|s| last_line(s))
+        .find(|line| {
+            !line.trim().is_empty()
+                && regex.is_match(line)
+                && !exclude.iter().any(|regex| regex.is_match(line))
+        })
+        .map(|line| line.trim_start().to_string())
+}
+
+/// Looks for a numbered menu, e.g., "1. alloc\n2. free\n3. show\n> ".
+pub fn find_menu(strings: &[String]) -> Option<Menu> {
+    let mut labels = BTreeMap::new();
+    for s in strings {
+        for line in s.lines() {
+            if let Some(captures) = OPTION_REGEX.captures(line) {
+                let number: u32 = captures[1].parse().unwrap();
+                labels
+                    .entry(number)
+                    .or_insert_with(|| captures[2].to_string());
+            }
+        }
+    }
+    /* Only consecutive numbers starting from 0 or 1 form a menu. */
+    let first = *labels.keys().next()?;
+    if first > 1 {
+        return None;
+    }
+    let mut options = Vec::new();
+    for (number, label) in labels {
+        if number != first + options.len() as u32 {
+            break;
+        }
+        options.push(MenuOption {
+            number,
+            role: Role::guess(&label),
+            label,
+        });
+    }
+    if options.len() < 2 {
+        return None;
+    }
+    Some(Menu {
+        prompt: find_prompt(strings, &MENU_PROMPT_REGEX, &[]),
+        options,
+        index_prompt: find_prompt(strings, &INDEX_PROMPT_REGEX, &[]),
+        size_prompt: find_prompt(strings, &SIZE_PROMPT_REGEX, &[&INDEX_PROMPT_REGEX]),
+        data_prompt: find_prompt(
+            strings,
+            &DATA_PROMPT_REGEX,
+            &[&INDEX_PROMPT_REGEX, &SIZE_PROMPT_REGEX],
+        ),
+    })
+}
@@ -0,0 +1,169 @@
+use crate::dockerfile::Image;
+use crate::Ui;
+use anyhow::Result;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Name of the build context through which the Dockerfile accesses the cache.
+pub static BUILD_CONTEXT: &str = "packages";
+/// Subdirectory that holds gef, so that the Dockerfile can bind-mount it on its own.
+static GEF_DIR: &str = "gef";
+static GEF: &str = "gef.py";
+/// Subdirectory that holds one subdirectory per libc version.
+static GLIBC_DIR: &str = "glibc";
+static GEF_URL: &str = "https://gef.blah.cat/py";
+static LAUNCHPAD_FILES_URL: &str = "https://launchpad.net/ubuntu/+archive/primary/+files";
+static LAUNCHPAD_SOURCEFILES_URL: &str =
+    "https://launchpad.net/ubuntu/+archive/primary/+sourcefiles";
+
+/// Returns the Debian architecture of the machine that builds the images.
+fn host_arch() -> &'static str {
+    match env::consts::ARCH {
+        "x86_64" => "amd64",
+        "x86" => "i386",
+        "aarch64" => "arm64",
+        "arm" => "armhf",
+        "powerpc64" => "ppc64el",
+        arch => arch,
+    }
+}
+
+/// Returns the name of a cached package, which the Dockerfile looks up as `$1_$3_$2.deb`.
+fn deb_name(package: &str, version: &str, arch: &str) -> String {
+    format!("{}_{}_{}.deb", package, version, arch)
+}
+
+/// Returns the directory with the packages and the sources of a libc version.
+///
+/// Each image bind-mounts only its own directory, because BuildKit hashes the mounted files into
+/// the layer cache key, and files of other versions must not invalidate the image.
+fn libc_dir(dir: &Path, libc_version: &str) -> PathBuf {
+    dir.join(GLIBC_DIR).join(libc_version)
+}
+
+/// Returns the names of the files that make up a Debian source package.
+fn dsc_files(dsc: &str) -> Vec<String> {
+    let mut result = Vec::new();
+    let mut in_files = false;
+    for line in dsc.lines() {
+        if line.starts_with(' ') {
+            if in_files {
+                result.extend(line.split_whitespace().nth(2).map(String::from));
+            }
+        } else {
+            in_files = line.trim_end() == "Files:";
+        }
+    }
+    result
+}
+
+/// Downloads a file unless it is already cached.
+async fn fetch_file(
+    client: &reqwest::Client,
+    dir: &Path,
+    name: &str,
+    url: &str,
+    ui: &dyn Ui,
+) -> Result<PathBuf> {
+    let path = dir.join(name);
+    if path.exists() {
+        return Ok(path);
+    }
+    ui.message(&format!("Fetching {}", url));
+    let response = client.get(url).send().await?.error_for_status()?;
+    let bytes = response.bytes().await?;
+    /* Do not leave truncated files behind if the connection breaks. */
+    let part = dir.join(format!("{}.part", name));
+    fs::write(&part, &bytes)?;
+    fs::rename(&part, &path)?;
+    Ok(path)
+}
+
+/// Downloads the files that the Dockerfile would otherwise download at build time.
+///
+/// The Dockerfile looks for them in the cache first, so once they are fetched, the libc and gef
+/// layers can be rebuilt without network access.
+pub async fn fetch(
+    dir: &Path,
+    distro: &str,
+    arch: &str,
+    libc_version: &str,
+    ui: &dyn Ui,
+) -> Result<()> {
+    /* The Dockerfile bind-mounts these directories, so they must exist even if nothing is fetched. */
+    let gef_dir = dir.join(GEF_DIR);
+    let dir = libc_dir(dir, libc_version);
+    fs::create_dir_all(&gef_dir)?;
+    fs::create_dir_all(&dir)?;
+    let dir = dir.as_path();
+    let client = reqwest::Client::new();
+    fetch_file(&client, &gef_dir, GEF, GEF_URL, ui).await?;
+    if Image::parse(distro).official_name() != Some("ubuntu") || libc_version == "*" {
+        return Ok(());
+    }
+    for (package, arch) in [
+        ("libc-dev-bin", host_arch()),
+        ("libc6", arch),
+        ("libc6-dbg", arch),
+        ("libc6-dev", arch),
+    ] {
+        let name = deb_name(package, libc_version, arch);
+        let url = format!("{}/{}", LAUNCHPAD_FILES_URL, name);
+        fetch_file(&client, dir, &name, &url, ui).await?;
+    }
+    let source_url = format!("{}/glibc/{}", LAUNCHPAD_SOURCEFILES_URL, libc_version);
+    let dsc = format!("glibc_{}.dsc", libc_version);
+    let dsc_path = fetch_file(&client, dir, &dsc, &format!("{}/{}", source_url, dsc), ui).await?;
+    for name in dsc_files(&fs::read_to_string(dsc_path)?) {
+        let url = format!("{}/{}", source_url, name);
+        fetch_file(&client, dir, &name, &url, ui).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dsc_files() {
+        let dsc = "Format: 3.0 (quilt)
+Source: glibc
+Checksums-Sha256:
+ 0123 17995948 glibc_2.35.orig.tar.xz
+Files:
+ 4567 17995948 glibc_2.35.orig.tar.xz
+ 89ab 842624 glibc_2.35-0ubuntu3.debian.tar.xz
+Original-Maintainer: GNU Libc Maintainers <debian-glibc@lists.debian.org>
+";
+        assert_eq!(
+            dsc_files(dsc),
+            [
+                "glibc_2.35.orig.tar.xz",
+                "glibc_2.35-0ubuntu3.debian.tar.xz"
+            ]
+        );
+        assert!(dsc_files("Source: glibc\n").is_empty());
+    }
+
+    #[test]
+    fn test_cache_names() {
+        /* launchpadlibrarian-dl gets the package, the arch and the version, in that order. */
+        let dockerfile = include_str!("commands/docker/image/Dockerfile");
+        assert!(dockerfile.contains("/var/cache/ctftool/$1_$3_$2.deb"));
+        assert_eq!(
+            deb_name("libc6", "2.35-0ubuntu3", "i386"),
+            "libc6_2.35-0ubuntu3_i386.deb"
+        );
+        assert!(dockerfile.contains(&format!(
+            "source={}/$libc_version,target=/var/cache/ctftool",
+            GLIBC_DIR
+        )));
+        assert_eq!(
+            libc_dir(Path::new("/cache"), "2.35-0ubuntu3"),
+            Path::new("/cache/glibc/2.35-0ubuntu3")
+        );
+        assert!(dockerfile.contains(&format!("source={},target=/var/cache/ctftool", GEF_DIR)));
+    }
+}
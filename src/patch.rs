@@ -0,0 +1,123 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Result};
+use elf::abi::{EM_386, EM_X86_64};
+use elf::endian::AnyEndian;
+use elf::ElfBytes;
+
+use crate::asm;
+use crate::ctf;
+use crate::option;
+use crate::patchelf::{offset_to_vaddr, vaddr_to_offset};
+
+/// Where a patch is applied.
+pub struct Location {
+    pub offset: u64,
+    pub vaddr: u64,
+}
+
+/// Translates a virtual address (or, if `file_offset` is set, a file offset) into a location.
+///
+/// Addresses in non-ELF files are the same as file offsets.
+pub fn locate(bytes: &[u8], at: u64, file_offset: bool) -> Result<Location> {
+    let phdrs = match ElfBytes::<AnyEndian>::minimal_parse(bytes) {
+        Ok(elf) => elf
+            .segments()
+            .map(|segments| segments.iter().collect::<Vec<_>>()),
+        Err(_) => None,
+    };
+    match (phdrs, file_offset) {
+        (Some(phdrs), true) => Ok(Location {
+            offset: at,
+            vaddr: offset_to_vaddr(&phdrs, at).unwrap_or(at),
+        }),
+        (Some(phdrs), false) => Ok(Location {
+            offset: vaddr_to_offset(&phdrs, at)?,
+            vaddr: at,
+        }),
+        (None, _) => Ok(Location {
+            offset: at,
+            vaddr: at,
+        }),
+    }
+}
+
+/// Assembles instructions for the architecture of a file.
+pub fn assemble(bytes: &[u8], location: &Location, s: &str) -> Result<Vec<u8>> {
+    if let Ok(elf) = ElfBytes::<AnyEndian>::minimal_parse(bytes) {
+        if elf.ehdr.e_machine != EM_386 && elf.ehdr.e_machine != EM_X86_64 {
+            bail!("Only x86 instructions are supported");
+        }
+    }
+    asm::assemble(location.vaddr, s)
+}
+
+/// Applies byte patches.
+pub fn apply(bytes: &mut [u8], patches: &[ctf::BytePatch]) -> Result<()> {
+    for patch in patches {
+        let patch_bytes = hex::decode(&patch.bytes)?;
+        let start = patch.offset as usize;
+        bytes
+            .get_mut(start..start + patch_bytes.len())
+            .ok_or_else(|| anyhow!("Patch at 0x{:x} is out of bounds", patch.offset))?
+            .copy_from_slice(&patch_bytes);
+    }
+    Ok(())
+}
+
+/// Returns the alternatives that are patched on top of the given one.
+pub fn dependents<'a>(binary: &'a ctf::Binary, name: &'a str) -> impl Iterator<Item = &'a str> {
+    binary
+        .alternatives
+        .iter()
+        .filter(move |alternative| option::contains(&alternative.base, &name))
+        .map(|alternative| alternative.name.as_str())
+}
+
+/// Orders the alternatives of a binary so that each patched one comes after its base.
+pub fn rebuild_order(binary: &ctf::Binary) -> Result<Vec<&ctf::BinaryAlternative>> {
+    let mut result: Vec<&ctf::BinaryAlternative> = Vec::new();
+    while result.len() < binary.alternatives.len() {
+        let n_ordered = result.len();
+        for alternative in &binary.alternatives {
+            let ordered = |name: &str| result.iter().any(|ordered| ordered.name == name);
+            if !ordered(&alternative.name) && alternative.base.as_deref().is_none_or(ordered) {
+                result.push(alternative);
+            }
+        }
+        if result.len() == n_ordered {
+            bail!(
+                "Alternatives of {} are patched on top of missing or circular bases",
+                binary.name
+            );
+        }
+    }
+    Ok(result)
+}
+
+/// Recreates a patched alternative from its base one.
+pub fn rebuild(
+    root: &Path,
+    challenge_name: &str,
+    binary: &ctf::Binary,
+    alternative: &ctf::BinaryAlternative,
+) -> Result<()> {
+    let base = match &alternative.base {
+        Some(base) => base,
+        None => return Ok(()),
+    };
+    let base_path = ctf::alternative_path(root, challenge_name, &binary.name, base);
+    let mut bytes = fs::read(&base_path)?;
+    apply(&mut bytes, &alternative.patches)?;
+    let path = ctf::alternative_path(root, challenge_name, &binary.name, &alternative.name);
+    fs::write(&path, &bytes)?;
+    fs::set_permissions(&path, fs::metadata(&base_path)?.permissions())?;
+    if option::contains(&binary.default_alternative, &alternative.name) {
+        fs::copy(
+            &path,
+            ctf::default_alternative_path(root, challenge_name, &binary.name),
+        )?;
+    }
+    Ok(())
+}
@@ -0,0 +1,328 @@
+use anyhow::{anyhow, bail, Result};
+use elf::abi::{
+    DT_NULL, DT_RPATH, DT_RUNPATH, DT_STRSZ, DT_STRTAB, PF_R, PF_W, PT_DYNAMIC, PT_INTERP, PT_LOAD,
+    PT_NOTE, SHT_DYNAMIC,
+};
+use elf::endian::{AnyEndian, EndianParse};
+use elf::file::Class;
+use elf::segment::ProgramHeader;
+use elf::ElfBytes;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+lazy_static! {
+    pub static ref LD_REGEX: Regex = Regex::new(r"^ld(64)?(-[\w.-]+)?\.so(\.\d+)?$").unwrap();
+    pub static ref LIBC_REGEX: Regex = Regex::new(r"^libc(-[\d.]+)?\.so(\.\d+)?$").unwrap();
+}
+
+/// Finds a library in a challenge directory, returns its path relative to that directory.
+pub fn find_library(challenge_dir: &Path, regex: &Regex, option: &str) -> Result<PathBuf> {
+    let mut candidates = vec![];
+    for entry in fs::read_dir(challenge_dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+        if let Some(name) = entry.file_name().to_str() {
+            if regex.is_match(name) {
+                candidates.push(name.to_string());
+            }
+        }
+    }
+    candidates.sort();
+    match candidates.as_slice() {
+        [candidate] => Ok(PathBuf::from(candidate)),
+        [] => bail!("Could not find {}, use --{}", option, option),
+        _ => bail!(
+            "Multiple {} candidates: {}, use --{}",
+            option,
+            candidates.join(", "),
+            option
+        ),
+    }
+}
+
+/// Writes ELF structures using the class and the byte order of a particular file.
+struct Writer {
+    class: Class,
+    big_endian: bool,
+}
+
+impl Writer {
+    fn u32(&self, bytes: &mut [u8], offset: usize, value: u32) {
+        let value = if self.big_endian {
+            value.to_be_bytes()
+        } else {
+            value.to_le_bytes()
+        };
+        bytes[offset..offset + 4].copy_from_slice(&value);
+    }
+
+    fn u64(&self, bytes: &mut [u8], offset: usize, value: u64) {
+        let value = if self.big_endian {
+            value.to_be_bytes()
+        } else {
+            value.to_le_bytes()
+        };
+        bytes[offset..offset + 8].copy_from_slice(&value);
+    }
+
+    /// Writes an address, an offset or a size, whose width depends on the class.
+    fn word(&self, bytes: &mut [u8], offset: usize, value: u64) {
+        match self.class {
+            Class::ELF32 => self.u32(bytes, offset, value as u32),
+            Class::ELF64 => self.u64(bytes, offset, value),
+        }
+    }
+
+    fn word_size(&self) -> usize {
+        match self.class {
+            Class::ELF32 => 4,
+            Class::ELF64 => 8,
+        }
+    }
+
+    fn phdr(&self, bytes: &mut [u8], offset: usize, phdr: &ProgramHeader) {
+        match self.class {
+            Class::ELF32 => {
+                self.u32(bytes, offset, phdr.p_type);
+                self.u32(bytes, offset + 4, phdr.p_offset as u32);
+                self.u32(bytes, offset + 8, phdr.p_vaddr as u32);
+                self.u32(bytes, offset + 12, phdr.p_paddr as u32);
+                self.u32(bytes, offset + 16, phdr.p_filesz as u32);
+                self.u32(bytes, offset + 20, phdr.p_memsz as u32);
+                self.u32(bytes, offset + 24, phdr.p_flags);
+                self.u32(bytes, offset + 28, phdr.p_align as u32);
+            }
+            Class::ELF64 => {
+                self.u32(bytes, offset, phdr.p_type);
+                self.u32(bytes, offset + 4, phdr.p_flags);
+                self.u64(bytes, offset + 8, phdr.p_offset);
+                self.u64(bytes, offset + 16, phdr.p_vaddr);
+                self.u64(bytes, offset + 24, phdr.p_paddr);
+                self.u64(bytes, offset + 32, phdr.p_filesz);
+                self.u64(bytes, offset + 40, phdr.p_memsz);
+                self.u64(bytes, offset + 48, phdr.p_align);
+            }
+        }
+    }
+
+    fn dyn_(&self, bytes: &mut Vec<u8>, d_tag: i64, d_un: u64) {
+        let offset = bytes.len();
+        bytes.resize(offset + self.word_size() * 2, 0);
+        self.word(bytes, offset, d_tag as u64);
+        self.word(bytes, offset + self.word_size(), d_un);
+    }
+
+    /// Updates sh_addr, sh_offset and sh_size of a section header.
+    fn shdr_location(&self, bytes: &mut [u8], offset: usize, addr: u64, off: u64, size: u64) {
+        match self.class {
+            Class::ELF32 => {
+                self.u32(bytes, offset + 12, addr as u32);
+                self.u32(bytes, offset + 16, off as u32);
+                self.u32(bytes, offset + 20, size as u32);
+            }
+            Class::ELF64 => {
+                self.u64(bytes, offset + 16, addr);
+                self.u64(bytes, offset + 24, off);
+                self.u64(bytes, offset + 32, size);
+            }
+        }
+    }
+}
+
+fn align_up(value: u64, alignment: u64) -> u64 {
+    value.div_ceil(alignment) * alignment
+}
+
+/// Converts a virtual address to a file offset using program headers.
+pub fn vaddr_to_offset(phdrs: &[ProgramHeader], vaddr: u64) -> Result<u64> {
+    phdrs
+        .iter()
+        .find(|phdr| {
+            phdr.p_type == PT_LOAD && vaddr >= phdr.p_vaddr && vaddr < phdr.p_vaddr + phdr.p_filesz
+        })
+        .map(|phdr| vaddr - phdr.p_vaddr + phdr.p_offset)
+        .ok_or_else(|| anyhow!("Address 0x{:x} is not backed by the file", vaddr))
+}
+
+/// Converts a file offset to a virtual address using program headers.
+pub fn offset_to_vaddr(phdrs: &[ProgramHeader], offset: u64) -> Result<u64> {
+    phdrs
+        .iter()
+        .find(|phdr| {
+            phdr.p_type == PT_LOAD
+                && offset >= phdr.p_offset
+                && offset < phdr.p_offset + phdr.p_filesz
+        })
+        .map(|phdr| offset - phdr.p_offset + phdr.p_vaddr)
+        .ok_or_else(|| anyhow!("Offset 0x{:x} is not mapped", offset))
+}
+
+/// Returns a copy of an ELF file that uses the specified interpreter and RUNPATH.
+///
+/// The new strings, `.dynstr` and `.dynamic` are appended to the end of the file and are mapped
+/// by a new `PT_LOAD` segment, which replaces a `PT_NOTE` one.
+pub fn patch(bytes: &[u8], interpreter: Option<&str>, runpath: Option<&str>) -> Result<Vec<u8>> {
+    let elf = ElfBytes::<AnyEndian>::minimal_parse(bytes)?;
+    let writer = Writer {
+        class: elf.ehdr.class,
+        big_endian: elf.ehdr.endianness.is_big(),
+    };
+    let mut phdrs: Vec<ProgramHeader> = elf
+        .segments()
+        .ok_or_else(|| anyhow!("No program headers"))?
+        .iter()
+        .collect();
+    let page_size = phdrs
+        .iter()
+        .filter(|phdr| phdr.p_type == PT_LOAD)
+        .map(|phdr| phdr.p_align)
+        .max()
+        .unwrap_or(0)
+        .max(0x1000);
+    let end_vaddr = phdrs
+        .iter()
+        .filter(|phdr| phdr.p_type == PT_LOAD)
+        .map(|phdr| phdr.p_vaddr + phdr.p_memsz)
+        .max()
+        .ok_or_else(|| anyhow!("No PT_LOAD segments"))?;
+    let segment_offset = align_up(bytes.len() as u64, page_size);
+    let segment_vaddr = align_up(end_vaddr, page_size);
+    let mut segment = Vec::new();
+    let mut result = bytes.to_vec();
+    let mut shdr_updates = Vec::new();
+    let (shdrs, shstrtab) = elf.section_headers_with_strtab()?;
+
+    if let Some(interpreter) = interpreter {
+        let pos = segment.len() as u64;
+        segment.extend_from_slice(interpreter.as_bytes());
+        segment.push(0);
+        let size = interpreter.len() as u64 + 1;
+        let phdr = phdrs
+            .iter_mut()
+            .find(|phdr| phdr.p_type == PT_INTERP)
+            .ok_or_else(|| anyhow!("No PT_INTERP segment, is the binary static?"))?;
+        phdr.p_offset = segment_offset + pos;
+        phdr.p_vaddr = segment_vaddr + pos;
+        phdr.p_paddr = segment_vaddr + pos;
+        phdr.p_filesz = size;
+        phdr.p_memsz = size;
+        if let (Some(shdrs), Some(shstrtab)) = (&shdrs, &shstrtab) {
+            for (i, shdr) in shdrs.iter().enumerate() {
+                if shstrtab.get(shdr.sh_name as usize)? == ".interp" {
+                    shdr_updates.push((i, segment_vaddr + pos, segment_offset + pos, size));
+                }
+            }
+        }
+    }
+
+    if let Some(runpath) = runpath {
+        let dynamic: Vec<_> = elf
+            .dynamic()?
+            .ok_or_else(|| anyhow!("No dynamic section, is the binary static?"))?
+            .iter()
+            .take_while(|dyn_| dyn_.d_tag != DT_NULL)
+            .collect();
+        let find_dyn = |d_tag| {
+            dynamic
+                .iter()
+                .find(|dyn_| dyn_.d_tag == d_tag)
+                .map(|dyn_| dyn_.clone().d_val())
+                .ok_or_else(|| anyhow!("No dynamic tag 0x{:x}", d_tag))
+        };
+        let strtab_offset = vaddr_to_offset(&phdrs, find_dyn(DT_STRTAB)?)? as usize;
+        let strtab_size = find_dyn(DT_STRSZ)? as usize;
+        let strtab = bytes
+            .get(strtab_offset..strtab_offset + strtab_size)
+            .ok_or_else(|| anyhow!("Dynamic string table is out of bounds"))?;
+        let strtab_pos = segment.len() as u64;
+        segment.extend_from_slice(strtab);
+        let runpath_index = strtab.len() as u64;
+        segment.extend_from_slice(runpath.as_bytes());
+        segment.push(0);
+        let new_strtab_size = segment.len() as u64 - strtab_pos;
+        segment.resize(align_up(segment.len() as u64, 8) as usize, 0);
+
+        let dynamic_pos = segment.len() as u64;
+        for dyn_ in &dynamic {
+            match dyn_.d_tag {
+                DT_RPATH | DT_RUNPATH => {}
+                DT_STRTAB => writer.dyn_(&mut segment, DT_STRTAB, segment_vaddr + strtab_pos),
+                DT_STRSZ => writer.dyn_(&mut segment, DT_STRSZ, new_strtab_size),
+                d_tag => writer.dyn_(&mut segment, d_tag, dyn_.clone().d_val()),
+            }
+        }
+        writer.dyn_(&mut segment, DT_RUNPATH, runpath_index);
+        writer.dyn_(&mut segment, DT_NULL, 0);
+        let dynamic_size = segment.len() as u64 - dynamic_pos;
+        let phdr = phdrs
+            .iter_mut()
+            .find(|phdr| phdr.p_type == PT_DYNAMIC)
+            .ok_or_else(|| anyhow!("No PT_DYNAMIC segment"))?;
+        phdr.p_offset = segment_offset + dynamic_pos;
+        phdr.p_vaddr = segment_vaddr + dynamic_pos;
+        phdr.p_paddr = segment_vaddr + dynamic_pos;
+        phdr.p_filesz = dynamic_size;
+        phdr.p_memsz = dynamic_size;
+        if let Some(shdrs) = &shdrs {
+            for (i, shdr) in shdrs.iter().enumerate() {
+                if shdr.sh_type == SHT_DYNAMIC {
+                    shdr_updates.push((
+                        i,
+                        segment_vaddr + dynamic_pos,
+                        segment_offset + dynamic_pos,
+                        dynamic_size,
+                    ));
+                    shdr_updates.push((
+                        shdr.sh_link as usize,
+                        segment_vaddr + strtab_pos,
+                        segment_offset + strtab_pos,
+                        new_strtab_size,
+                    ));
+                }
+            }
+        }
+    }
+
+    /* PT_LOAD segments must be sorted by address, so the new one goes after the last one. */
+    let note_index = phdrs
+        .iter()
+        .position(|phdr| phdr.p_type == PT_NOTE)
+        .ok_or_else(|| anyhow!("No PT_NOTE segment that could be reused"))?;
+    phdrs.remove(note_index);
+    let load_index = phdrs
+        .iter()
+        .rposition(|phdr| phdr.p_type == PT_LOAD)
+        .map(|i| i + 1)
+        .unwrap_or(phdrs.len());
+    phdrs.insert(
+        load_index,
+        ProgramHeader {
+            p_type: PT_LOAD,
+            p_offset: segment_offset,
+            p_vaddr: segment_vaddr,
+            p_paddr: segment_vaddr,
+            p_filesz: segment.len() as u64,
+            p_memsz: segment.len() as u64,
+            p_flags: PF_R | PF_W,
+            p_align: page_size,
+        },
+    );
+    for (i, phdr) in phdrs.iter().enumerate() {
+        let offset = elf.ehdr.e_phoff as usize + i * elf.ehdr.e_phentsize as usize;
+        writer.phdr(&mut result, offset, phdr);
+    }
+    for (i, addr, offset, size) in shdr_updates {
+        if i >= elf.ehdr.e_shnum as usize {
+            bail!("Section index {} is out of bounds", i);
+        }
+        let shdr_offset = elf.ehdr.e_shoff as usize + i * elf.ehdr.e_shentsize as usize;
+        writer.shdr_location(&mut result, shdr_offset, addr, offset, size);
+    }
+    result.resize(segment_offset as usize, 0);
+    result.extend_from_slice(&segment);
+    Ok(result)
+}
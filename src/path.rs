@@ -1,5 +1,6 @@
 use crate::os_str::os_str_to_str;
 use anyhow::{anyhow, Result};
+use std::fs;
 use std::path::{Path, PathBuf};
 
 pub fn relativize(root: &Path, cwd: &Path, path: PathBuf) -> Result<(PathBuf, PathBuf)> {
@@ -20,3 +21,25 @@ pub fn relativize(root: &Path, cwd: &Path, path: PathBuf) -> Result<(PathBuf, Pa
 pub fn path_to_str(path: &Path) -> Result<&str> {
     os_str_to_str(path.as_os_str())
 }
+
+/// Returns all regular files in a directory and its subdirectories, except hidden ones.
+pub fn walk(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                dirs.push(entry.path());
+            } else if file_type.is_file() {
+                files.push(entry.path());
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
@@ -0,0 +1,287 @@
+use anyhow::{anyhow, bail, Result};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+const LINKTYPE_NULL: u32 = 0;
+const LINKTYPE_ETHERNET: u32 = 1;
+const LINKTYPE_RAW: u32 = 101;
+const LINKTYPE_LINUX_SLL: u32 = 113;
+const LINKTYPE_LINUX_SLL2: u32 = 276;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+
+const IPPROTO_TCP: u8 = 6;
+
+const TCP_FIN: u8 = 0x01;
+const TCP_SYN: u8 = 0x02;
+const TCP_RST: u8 = 0x04;
+const TCP_ACK: u8 = 0x10;
+
+/// TCP segment extracted from a captured packet.
+pub struct Segment {
+    pub src: (IpAddr, u16),
+    pub dst: (IpAddr, u16),
+    pub seq: u32,
+    pub flags: u8,
+    pub payload: Vec<u8>,
+}
+
+fn u16_be(bytes: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(
+        bytes.get(pos..pos + 2)?.try_into().ok()?,
+    ))
+}
+
+fn u32_be(bytes: &[u8], pos: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        bytes.get(pos..pos + 4)?.try_into().ok()?,
+    ))
+}
+
+fn parse_tcp(src: IpAddr, dst: IpAddr, tcp: &[u8]) -> Option<Segment> {
+    let data_offset = (*tcp.get(12)? >> 4) as usize * 4;
+    Some(Segment {
+        src: (src, u16_be(tcp, 0)?),
+        dst: (dst, u16_be(tcp, 2)?),
+        seq: u32_be(tcp, 4)?,
+        flags: *tcp.get(13)?,
+        payload: tcp.get(data_offset..)?.to_vec(),
+    })
+}
+
+fn parse_ip(packet: &[u8]) -> Option<Segment> {
+    match packet.first()? >> 4 {
+        4 => {
+            let header_size = (packet[0] & 0xf) as usize * 4;
+            let total_size = u16_be(packet, 2)? as usize;
+            if *packet.get(9)? != IPPROTO_TCP {
+                return None;
+            }
+            let src: [u8; 4] = packet.get(12..16)?.try_into().ok()?;
+            let dst: [u8; 4] = packet.get(16..20)?.try_into().ok()?;
+            parse_tcp(
+                Ipv4Addr::from(src).into(),
+                Ipv4Addr::from(dst).into(),
+                packet.get(header_size..total_size.min(packet.len()))?,
+            )
+        }
+        6 => {
+            /* Extension headers are not supported. */
+            if *packet.get(6)? != IPPROTO_TCP {
+                return None;
+            }
+            let payload_size = u16_be(packet, 4)? as usize;
+            let src: [u8; 16] = packet.get(8..24)?.try_into().ok()?;
+            let dst: [u8; 16] = packet.get(24..40)?.try_into().ok()?;
+            parse_tcp(
+                Ipv6Addr::from(src).into(),
+                Ipv6Addr::from(dst).into(),
+                packet.get(40..(40 + payload_size).min(packet.len()))?,
+            )
+        }
+        _ => None,
+    }
+}
+
+fn parse_ethertype(ethertype: u16, payload: &[u8]) -> Option<Segment> {
+    match ethertype {
+        ETHERTYPE_IPV4 | ETHERTYPE_IPV6 => parse_ip(payload),
+        ETHERTYPE_VLAN => parse_ethertype(u16_be(payload, 2)?, payload.get(4..)?),
+        _ => None,
+    }
+}
+
+fn parse_packet(linktype: u32, packet: &[u8]) -> Option<Segment> {
+    match linktype {
+        LINKTYPE_NULL => parse_ip(packet.get(4..)?),
+        LINKTYPE_ETHERNET => parse_ethertype(u16_be(packet, 12)?, packet.get(14..)?),
+        LINKTYPE_RAW => parse_ip(packet),
+        LINKTYPE_LINUX_SLL => parse_ethertype(u16_be(packet, 14)?, packet.get(16..)?),
+        LINKTYPE_LINUX_SLL2 => parse_ethertype(u16_be(packet, 0)?, packet.get(20..)?),
+        _ => None,
+    }
+}
+
+/// Parses a pcap file and returns its TCP segments; other packets are skipped.
+pub fn parse(bytes: &[u8]) -> Result<Vec<Segment>> {
+    let header = bytes
+        .get(..24)
+        .ok_or_else(|| anyhow!("Truncated pcap header"))?;
+    let magic = [header[0], header[1], header[2], header[3]];
+    let big_endian = match magic {
+        [0xd4, 0xc3, 0xb2, 0xa1] | [0x4d, 0x3c, 0xb2, 0xa1] => false,
+        [0xa1, 0xb2, 0xc3, 0xd4] | [0xa1, 0xb2, 0x3c, 0x4d] => true,
+        [0x0a, 0x0d, 0x0d, 0x0a] => bail!("pcapng is not supported, use tcpdump -w"),
+        _ => bail!("Not a pcap file"),
+    };
+    let u32_at = |pos: usize| -> Option<u32> {
+        let field: [u8; 4] = bytes.get(pos..pos + 4)?.try_into().ok()?;
+        Some(match big_endian {
+            true => u32::from_be_bytes(field),
+            false => u32::from_le_bytes(field),
+        })
+    };
+    let linktype = u32_at(20).unwrap() & 0xffff;
+    let mut segments = Vec::new();
+    let mut pos = 24;
+    /* The last packet may be truncated if tcpdump is still writing the file. */
+    while let Some(size) = u32_at(pos + 8) {
+        let data_start = pos + 16;
+        let packet = match bytes.get(data_start..data_start + size as usize) {
+            Some(packet) => packet,
+            None => break,
+        };
+        segments.extend(parse_packet(linktype, packet));
+        pos = data_start + size as usize;
+    }
+    Ok(segments)
+}
+
+/// TCP connection, split into the messages that each side sent.
+pub struct Stream {
+    pub client: (IpAddr, u16),
+    pub server: (IpAddr, u16),
+    /// Whether the client sent the message and its data
+    pub messages: Vec<(bool, Vec<u8>)>,
+    /// Whether data is missing from the capture, so that some messages have gaps
+    pub damaged: bool,
+    /// Next expected sequence numbers of the server and of the client
+    next_seq: [Option<u32>; 2],
+    /// Segments of the server and of the client that arrived before the data in front of them
+    pending: [Vec<(u32, Vec<u8>)>; 2],
+    closed: bool,
+}
+
+impl Stream {
+    pub fn data(&self, from_client: bool) -> Vec<u8> {
+        self.messages
+            .iter()
+            .filter(|(client, _)| *client == from_client)
+            .flat_map(|(_, data)| data.iter().copied())
+            .collect()
+    }
+
+    fn push(&mut self, from_client: bool, seq: u32, flags: u8, payload: &[u8]) {
+        let side = from_client as usize;
+        if flags & TCP_SYN != 0 {
+            self.next_seq[side] = Some(seq.wrapping_add(1));
+        }
+        match self.next_seq[side] {
+            Some(next) if !payload.is_empty() && is_ahead(seq, next) => {
+                self.pending[side].push((seq, payload.to_vec()));
+            }
+            _ => {
+                self.place(from_client, seq, payload);
+                self.place_pending(from_client);
+            }
+        }
+        if flags & (TCP_FIN | TCP_RST) != 0 {
+            self.closed = true;
+        }
+    }
+
+    /// Places the held segments that the data in front of them has caught up with.
+    fn place_pending(&mut self, from_client: bool) {
+        let side = from_client as usize;
+        while let Some(next) = self.next_seq[side] {
+            let pending = &mut self.pending[side];
+            match pending.iter().position(|(seq, _)| !is_ahead(*seq, next)) {
+                Some(index) => {
+                    let (seq, payload) = pending.swap_remove(index);
+                    self.place(from_client, seq, &payload);
+                }
+                None => break,
+            }
+        }
+    }
+
+    /// Places the segments that are still held, because the data in front of them was not
+    /// captured, and marks the stream as damaged.
+    fn finish(&mut self) {
+        for from_client in [false, true] {
+            let side = from_client as usize;
+            while !self.pending[side].is_empty() {
+                self.damaged = true;
+                let next = self.next_seq[side].unwrap();
+                let pending = &mut self.pending[side];
+                let index = (0..pending.len())
+                    .min_by_key(|&index| pending[index].0.wrapping_sub(next))
+                    .unwrap();
+                let (seq, payload) = pending.swap_remove(index);
+                self.place(from_client, seq, &payload);
+                self.place_pending(from_client);
+            }
+        }
+    }
+
+    /// Appends a segment to the messages, skipping the data that was already placed.
+    fn place(&mut self, from_client: bool, seq: u32, payload: &[u8]) {
+        let next_seq = &mut self.next_seq[from_client as usize];
+        /* Skip retransmitted data. */
+        let skip = match *next_seq {
+            Some(next) if !payload.is_empty() => {
+                let behind = next.wrapping_sub(seq);
+                match behind < 0x8000_0000 {
+                    true => behind as usize,
+                    false => 0,
+                }
+            }
+            _ => 0,
+        };
+        if skip < payload.len() {
+            let payload = &payload[skip..];
+            match self.messages.last_mut() {
+                Some((client, data)) if *client == from_client => data.extend_from_slice(payload),
+                _ => self.messages.push((from_client, payload.to_vec())),
+            }
+            *next_seq = Some(seq.wrapping_add((skip + payload.len()) as u32));
+        }
+    }
+}
+
+/// Whether sequence number `seq` comes after `next`, taking wrap-around into account.
+fn is_ahead(seq: u32, next: u32) -> bool {
+    seq != next && seq.wrapping_sub(next) < 0x8000_0000
+}
+
+/// Groups segments into streams. The server is the side that uses one of the server ports, or,
+/// if this cannot be determined, the side that received the first packet.
+pub fn split_streams(segments: &[Segment], server_ports: &[u16]) -> Vec<Stream> {
+    let mut streams: Vec<Stream> = Vec::new();
+    for segment in segments {
+        let existing = streams.iter().rposition(|stream| {
+            (stream.client == segment.src && stream.server == segment.dst)
+                || (stream.client == segment.dst && stream.server == segment.src)
+        });
+        let is_syn = segment.flags & (TCP_SYN | TCP_ACK) == TCP_SYN;
+        let index = match existing {
+            Some(index) if !(is_syn && streams[index].closed) => index,
+            _ => {
+                let to_server =
+                    server_ports.contains(&segment.dst.1) || !server_ports.contains(&segment.src.1);
+                let (client, server) = match to_server {
+                    true => (segment.src, segment.dst),
+                    false => (segment.dst, segment.src),
+                };
+                streams.push(Stream {
+                    client,
+                    server,
+                    messages: Vec::new(),
+                    damaged: false,
+                    next_seq: [None, None],
+                    pending: [Vec::new(), Vec::new()],
+                    closed: false,
+                });
+                streams.len() - 1
+            }
+        };
+        let stream = &mut streams[index];
+        let from_client = stream.client == segment.src;
+        stream.push(from_client, segment.seq, segment.flags, &segment.payload);
+    }
+    for stream in &mut streams {
+        stream.finish();
+    }
+    streams
+}
@@ -0,0 +1,152 @@
+use anyhow::Result;
+use elf::abi::{
+    EM_386, EM_AARCH64, EM_ARM, EM_MIPS, EM_PPC, EM_PPC64, EM_RISCV, EM_S390, EM_X86_64,
+};
+use elf::endian::{AnyEndian, EndianParse};
+use elf::file::{Class, FileHeader};
+use serde::Serialize;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use tinytemplate::TinyTemplate;
+
+pub static LAUNCHER: &str = "qemu.sh";
+pub static GDB_SCRIPT: &str = "pwnit.gdb";
+pub static GDB_PORT: u16 = 1234;
+
+/// How to run and debug binaries for a given architecture.
+pub struct Arch {
+    /// pwntools context.arch
+    pub pwntools: &'static str,
+    pub bits: u32,
+    pub endian: &'static str,
+    /// qemu-user binary suffix, None for architectures that run natively
+    pub qemu: Option<&'static str>,
+    /// Debian cross-compilation triple, which is also the cross sysroot name
+    pub triple: &'static str,
+    /// gdb "set architecture" argument
+    pub gdb: &'static str,
+}
+
+pub fn get_arch(ehdr: &FileHeader<AnyEndian>) -> Option<Arch> {
+    let bits = if ehdr.class == Class::ELF64 { 64 } else { 32 };
+    let big = ehdr.endianness.is_big();
+    let (pwntools, qemu, triple, gdb) = if ehdr.e_machine == EM_X86_64 {
+        ("amd64", None, "x86_64-linux-gnu", "i386:x86-64")
+    } else if ehdr.e_machine == EM_386 {
+        ("i386", None, "i386-linux-gnu", "i386")
+    } else if ehdr.e_machine == EM_AARCH64 {
+        ("aarch64", Some("aarch64"), "aarch64-linux-gnu", "aarch64")
+    } else if ehdr.e_machine == EM_ARM {
+        if big {
+            ("arm", Some("armeb"), "arm-linux-gnueabihf", "arm")
+        } else {
+            ("arm", Some("arm"), "arm-linux-gnueabihf", "arm")
+        }
+    } else if ehdr.e_machine == EM_MIPS {
+        match (bits, big) {
+            (32, true) => ("mips", Some("mips"), "mips-linux-gnu", "mips"),
+            (32, false) => ("mips", Some("mipsel"), "mipsel-linux-gnu", "mips"),
+            (_, true) => (
+                "mips64",
+                Some("mips64"),
+                "mips64-linux-gnuabi64",
+                "mips:isa64",
+            ),
+            (_, false) => (
+                "mips64",
+                Some("mips64el"),
+                "mips64el-linux-gnuabi64",
+                "mips:isa64",
+            ),
+        }
+    } else if ehdr.e_machine == EM_RISCV {
+        if bits == 64 {
+            (
+                "riscv64",
+                Some("riscv64"),
+                "riscv64-linux-gnu",
+                "riscv:rv64",
+            )
+        } else {
+            (
+                "riscv32",
+                Some("riscv32"),
+                "riscv32-linux-gnu",
+                "riscv:rv32",
+            )
+        }
+    } else if ehdr.e_machine == EM_S390 {
+        ("s390", Some("s390x"), "s390x-linux-gnu", "s390:64-bit")
+    } else if ehdr.e_machine == EM_PPC {
+        (
+            "powerpc",
+            Some("ppc"),
+            "powerpc-linux-gnu",
+            "powerpc:common",
+        )
+    } else if ehdr.e_machine == EM_PPC64 {
+        if big {
+            (
+                "powerpc64",
+                Some("ppc64"),
+                "powerpc64-linux-gnu",
+                "powerpc:common64",
+            )
+        } else {
+            (
+                "powerpc64",
+                Some("ppc64le"),
+                "powerpc64le-linux-gnu",
+                "powerpc:common64",
+            )
+        }
+    } else {
+        return None;
+    };
+    Some(Arch {
+        pwntools,
+        bits,
+        endian: if big { "big" } else { "little" },
+        qemu,
+        triple,
+        gdb,
+    })
+}
+
+#[derive(Serialize)]
+struct Launcher<'a> {
+    binary: &'a str,
+    qemu: &'a str,
+    triple: &'a str,
+    gdb: &'a str,
+    gdb_port: u16,
+}
+
+/// Writes a script that runs a foreign binary under qemu-user, and a gdb script that attaches
+/// to it. Returns the paths of the written files, which are none for native binaries.
+pub fn write_launcher(dir: &Path, binary: &str, arch: &Arch) -> Result<Vec<PathBuf>> {
+    let qemu = match arch.qemu {
+        Some(qemu) => qemu,
+        None => return Ok(Vec::new()),
+    };
+    let mut templates = TinyTemplate::new();
+    templates.set_default_formatter(&tinytemplate::format_unescaped);
+    templates.add_template(LAUNCHER, include_str!("qemu/qemu.sh"))?;
+    templates.add_template(GDB_SCRIPT, include_str!("qemu/pwnit.gdb"))?;
+    let launcher = Launcher {
+        binary,
+        qemu,
+        triple: arch.triple,
+        gdb: arch.gdb,
+        gdb_port: GDB_PORT,
+    };
+    let path = dir.join(LAUNCHER);
+    fs::write(&path, templates.render(LAUNCHER, &launcher)?)?;
+    let mut permissions = fs::metadata(&path)?.permissions();
+    permissions.set_mode(permissions.mode() | 0o111);
+    fs::set_permissions(&path, permissions)?;
+    let gdb_script = dir.join(GDB_SCRIPT);
+    fs::write(&gdb_script, templates.render(GDB_SCRIPT, &launcher)?)?;
+    Ok(vec![path, gdb_script])
+}
@@ -0,0 +1,12 @@
+#!/bin/sh
+# Runs {binary} under qemu-{qemu}.
+# SYSROOT: directory with the target libraries.
+# GDB_PORT: wait for gdb-multiarch -x pwnit.gdb on this port.
+if [ -z "$SYSROOT" ]; then
+    if [ -d /usr/{triple} ]; then
+        SYSROOT=/usr/{triple}
+    else
+        SYSROOT=/
+    fi
+fi
+exec qemu-{qemu} -L "$SYSROOT" $\{GDB_PORT:+-g "$GDB_PORT"} {binary} "$@"
@@ -0,0 +1,69 @@
+/// Splits a shell script into commands, each of which is a list of words.
+///
+/// Only quoting, escaping, line continuations and comments are supported; there are no
+/// expansions.
+pub fn split(script: &str) -> Vec<Vec<String>> {
+    let mut commands = Vec::new();
+    let mut words: Vec<String> = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = script.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('\n') | None => {}
+                Some(c) => word.get_or_insert_with(String::new).push(c),
+            },
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                for c in chars.by_ref() {
+                    if c == '\'' {
+                        break;
+                    }
+                    word.push(c);
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' if matches!(chars.peek(), Some('"' | '\\' | '$' | '`')) => {
+                            word.push(chars.next().unwrap())
+                        }
+                        '\\' if chars.peek() == Some(&'\n') => {
+                            chars.next();
+                        }
+                        c => word.push(c),
+                    }
+                }
+            }
+            '#' if word.is_none() => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+                words.extend(word.take());
+                commands.push(std::mem::take(&mut words));
+            }
+            ' ' | '\t' => words.extend(word.take()),
+            '\n' | ';' | '&' | '|' | '<' | '>' => {
+                /* A file descriptor number before a redirection is not an argument. */
+                if c == '<' || c == '>' {
+                    if let Some(fd) = &word {
+                        if fd.chars().all(|c| c.is_ascii_digit()) {
+                            word = None;
+                        }
+                    }
+                }
+                words.extend(word.take());
+                commands.push(std::mem::take(&mut words));
+            }
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    commands.push(words);
+    commands.retain(|words| !words.is_empty());
+    commands
+}
@@ -0,0 +1,384 @@
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Error, Result};
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use serde::Serialize;
+
+/// Reports the progress of a long-running operation, e.g., a download.
+pub trait Progress: Send + Sync {
+    fn set_length(&self, length: u64);
+
+    fn inc(&self, delta: u64);
+
+    /// Removes the progress report, leaving the message behind if there is one.
+    fn finish(&self, message: Option<String>);
+}
+
+/// Everything commands need from the user and the terminal.
+///
+/// Commands never touch stdin, stdout or stderr directly, so that the library can be embedded
+/// into other tools.
+pub trait Ui: Send + Sync {
+    /// Shows the result of a command.
+    fn message(&self, text: &str);
+
+    /// Shows a problem that does not make the command fail.
+    fn warn(&self, text: &str);
+
+    /// Shows the output of a program as it arrives, which may end in the middle of a line.
+    fn output(&self, bytes: &[u8]);
+
+    /// Shows a list of records.
+    fn table(&self, headers: &[&str], rows: Vec<Vec<String>>);
+
+    /// Passes the records that a read command produced, e.g., the challenges for `ctf challenge
+    /// show`, to library callers, whatever the output format. They are shown separately.
+    fn records(&self, records: &serde_json::Value);
+
+    /// Asks for a line of text.
+    fn prompt(&self, label: &str) -> Result<String>;
+
+    /// Asks for a line of text without echoing it.
+    fn password(&self, label: &str) -> Result<String>;
+
+    /// Reads the data piped into the command, e.g., for `ctf flag scan -`.
+    fn input(&self) -> Result<Vec<u8>>;
+
+    /// Starts reporting the progress of a long-running operation.
+    fn progress(&self, message: String) -> Box<dyn Progress + '_>;
+}
+
+/// How read commands, e.g., `ctf challenge show`, print records.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Format {
+    /// For humans
+    #[default]
+    Table,
+    /// The `.ctf` schema, for `jq` and scripts
+    Json,
+    Yaml,
+}
+
+impl Format {
+    pub const ALL: [Format; 3] = [Format::Table, Format::Json, Format::Yaml];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::Table => "table",
+            Format::Json => "json",
+            Format::Yaml => "yaml",
+        }
+    }
+
+    /// Passes the records to the UI, then serializes them, or calls `table` to show them to humans.
+    pub fn show<T, F>(self, ui: &dyn Ui, records: &T, table: F) -> Result<()>
+    where
+        T: Serialize + ?Sized,
+        F: FnOnce() -> Result<()>,
+    {
+        let value = serde_json::to_value(records)?;
+        ui.records(&value);
+        match self {
+            Format::Table => table(),
+            Format::Json => {
+                ui.message(&serde_json::to_string_pretty(&value)?);
+                Ok(())
+            }
+            Format::Yaml => {
+                ui.message(serde_yaml::to_string(&value)?.trim_end());
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for Format {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Format> {
+        Format::ALL
+            .into_iter()
+            .find(|format| format.name() == s)
+            .ok_or_else(|| anyhow!("No such format: {}, use table, json or yaml", s))
+    }
+}
+
+/// Formats a table the way `ps` does: left-aligned columns separated by spaces.
+pub fn format_table(headers: &[&str], rows: &[Vec<String>]) -> Vec<String> {
+    let mut widths: Vec<usize> = headers.iter().map(|header| header.len()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let headers = headers.iter().map(|header| header.to_string()).collect();
+    std::iter::once(&headers)
+        .chain(rows)
+        .map(|row: &Vec<String>| {
+            let cells: Vec<String> = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+                .collect();
+            cells.join(" ").trim_end().to_string()
+        })
+        .collect()
+}
+
+/// Talks to the user via the terminal.
+pub struct ConsoleUi {
+    progress: MultiProgress,
+}
+
+impl ConsoleUi {
+    pub fn new() -> Self {
+        ConsoleUi {
+            progress: MultiProgress::new(),
+        }
+    }
+}
+
+impl Default for ConsoleUi {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+struct ConsoleProgress(ProgressBar);
+
+impl Progress for ConsoleProgress {
+    fn set_length(&self, length: u64) {
+        self.0.set_length(length);
+    }
+
+    fn inc(&self, delta: u64) {
+        self.0.inc(delta);
+    }
+
+    fn finish(&self, message: Option<String>) {
+        match message {
+            Some(message) => self.0.finish_with_message(message),
+            None => self.0.finish_and_clear(),
+        }
+    }
+}
+
+impl Ui for ConsoleUi {
+    fn message(&self, text: &str) {
+        /* Do not garble the progress bars. */
+        self.progress.suspend(|| println!("{}", text));
+    }
+
+    fn warn(&self, text: &str) {
+        self.progress.suspend(|| eprintln!("{}", text));
+    }
+
+    fn output(&self, bytes: &[u8]) {
+        self.progress.suspend(|| {
+            let mut stdout = io::stdout().lock();
+            /* There is nobody to report a closed stdout to. */
+            let _ = stdout.write_all(bytes).and_then(|()| stdout.flush());
+        });
+    }
+
+    fn table(&self, headers: &[&str], rows: Vec<Vec<String>>) {
+        for line in format_table(headers, &rows) {
+            self.message(&line);
+        }
+    }
+
+    fn records(&self, _records: &serde_json::Value) {
+        /* The table or the serialized records are all the terminal needs. */
+    }
+
+    fn prompt(&self, label: &str) -> Result<String> {
+        print!("{}", label);
+        io::stdout().flush()?;
+        let mut line = String::new();
+        io::stdin().read_line(&mut line)?;
+        line.truncate(line.trim_end().len());
+        Ok(line)
+    }
+
+    fn password(&self, label: &str) -> Result<String> {
+        Ok(rpassword::prompt_password(label)?)
+    }
+
+    fn input(&self) -> Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        io::stdin().read_to_end(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    fn progress(&self, message: String) -> Box<dyn Progress + '_> {
+        let progress_bar = ProgressBar::hidden().with_message(message);
+        if let Ok(style) = ProgressStyle::default_bar().template("{wide_msg} {bytes}/{total_bytes}")
+        {
+            progress_bar.set_style(style);
+        }
+        Box::new(ConsoleProgress(self.progress.add(progress_bar)))
+    }
+}
+
+/// What a command has shown to the user.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    Message(String),
+    Warning(String),
+    /// A chunk of a program's output.
+    Output(Vec<u8>),
+    Table {
+        headers: Vec<String>,
+        rows: Vec<Vec<String>>,
+    },
+    /// Records of a read command, see `Ui::records`.
+    Records(serde_json::Value),
+    Prompt(String),
+    /// A finished progress report along with its final message.
+    Progress(String, Option<String>),
+}
+
+/// Records what commands show and answers their questions with canned replies.
+///
+/// Without replies, this is a silent UI, which is useful for tests and for embedding.
+#[derive(Default)]
+pub struct RecordingUi {
+    events: Mutex<Vec<Event>>,
+    replies: Mutex<VecDeque<String>>,
+    input: Vec<u8>,
+}
+
+impl RecordingUi {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answers the prompts, including password ones, in order.
+    pub fn with_replies<I, S>(mut self, replies: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.replies = Mutex::new(replies.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Pretends that the data was piped into the command.
+    pub fn with_input(mut self, input: &[u8]) -> Self {
+        self.input = input.to_vec();
+        self
+    }
+
+    pub fn events(&self) -> Vec<Event> {
+        self.events.lock().unwrap().clone()
+    }
+
+    /// Returns the records that the commands produced, in order.
+    pub fn records(&self) -> Vec<serde_json::Value> {
+        self.events()
+            .into_iter()
+            .filter_map(|event| match event {
+                Event::Records(records) => Some(records),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns the messages, the program output, as well as the tables formatted the way the console
+    /// would.
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut output = Vec::new();
+        for event in self.events() {
+            if let Event::Output(bytes) = &event {
+                output.extend_from_slice(bytes);
+                continue;
+            }
+            /* Output chunks are split arbitrarily, so join them before splitting into lines. */
+            lines.extend(String::from_utf8_lossy(&output).lines().map(String::from));
+            output.clear();
+            match event {
+                Event::Message(text) => lines.extend(text.lines().map(String::from)),
+                Event::Table { headers, rows } => {
+                    let headers: Vec<&str> = headers.iter().map(String::as_str).collect();
+                    lines.extend(format_table(&headers, &rows));
+                }
+                _ => {}
+            }
+        }
+        lines.extend(String::from_utf8_lossy(&output).lines().map(String::from));
+        lines
+    }
+
+    fn record(&self, event: Event) {
+        self.events.lock().unwrap().push(event);
+    }
+
+    fn reply(&self, label: &str) -> Result<String> {
+        self.record(Event::Prompt(label.to_string()));
+        self.replies
+            .lock()
+            .unwrap()
+            .pop_front()
+            .ok_or_else(|| anyhow!("No reply to {:?}", label))
+    }
+}
+
+struct RecordingProgress<'a> {
+    ui: &'a RecordingUi,
+    message: String,
+}
+
+impl Progress for RecordingProgress<'_> {
+    fn set_length(&self, _length: u64) {}
+
+    fn inc(&self, _delta: u64) {}
+
+    fn finish(&self, message: Option<String>) {
+        self.ui
+            .record(Event::Progress(self.message.clone(), message));
+    }
+}
+
+impl Ui for RecordingUi {
+    fn message(&self, text: &str) {
+        self.record(Event::Message(text.to_string()));
+    }
+
+    fn warn(&self, text: &str) {
+        self.record(Event::Warning(text.to_string()));
+    }
+
+    fn output(&self, bytes: &[u8]) {
+        self.record(Event::Output(bytes.to_vec()));
+    }
+
+    fn table(&self, headers: &[&str], rows: Vec<Vec<String>>) {
+        self.record(Event::Table {
+            headers: headers.iter().map(|header| header.to_string()).collect(),
+            rows,
+        });
+    }
+
+    fn records(&self, records: &serde_json::Value) {
+        self.record(Event::Records(records.clone()));
+    }
+
+    fn prompt(&self, label: &str) -> Result<String> {
+        self.reply(label)
+    }
+
+    fn password(&self, label: &str) -> Result<String> {
+        self.reply(label)
+    }
+
+    fn input(&self) -> Result<Vec<u8>> {
+        Ok(self.input.clone())
+    }
+
+    fn progress(&self, message: String) -> Box<dyn Progress + '_> {
+        Box::new(RecordingProgress { ui: self, message })
+    }
+}
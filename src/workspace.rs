@@ -0,0 +1,415 @@
+//! Stable API for tools that work with CTF repositories, e.g., bots and dashboards.
+//!
+//! Every mutation stores `.ctf`, updates the files of the affected binaries and commits, so the
+//! repository is never left half-modified.
+
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use crate::ctf::{self, Binary, BinaryAlternative, Challenge, Remote, Service, CTF};
+use crate::git;
+use crate::option;
+use crate::patch;
+
+pub use crate::error::{Error, Kind, Result};
+
+/// Checks that a name is a single path component, which challenge directories and binaries in
+/// them need.
+fn check_name(kind: Kind, name: &str) -> Result<()> {
+    let mut components = Path::new(name).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) if !name.ends_with('/') => Ok(()),
+        _ => Err(Error::InvalidName(kind, name.into())),
+    }
+}
+
+fn find_challenge<'a>(ctf: &'a mut CTF, name: &str) -> Result<&'a mut Challenge> {
+    ctf.challenges
+        .iter_mut()
+        .find(|challenge| challenge.name == name)
+        .ok_or_else(|| Error::NoSuchChallenge(name.into()))
+}
+
+fn find_binary<'a>(challenge: &'a mut Challenge, name: &str) -> Result<&'a mut Binary> {
+    challenge
+        .binaries
+        .iter_mut()
+        .find(|binary| binary.name == name)
+        .ok_or_else(|| Error::NoSuchBinary(format!("{}/{}", challenge.name, name)))
+}
+
+fn new_alternative(name: &str) -> BinaryAlternative {
+    BinaryAlternative {
+        name: name.into(),
+        url: None,
+        checksum: None,
+        base: None,
+        patches: Vec::new(),
+    }
+}
+
+/// Removes a file that is supposed to be there, but tolerates the user having removed it.
+fn remove_file(path: &Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// A CTF repository.
+pub struct Workspace {
+    context: ctf::Context,
+    /// `.ctf` as of the last commit, which failed mutations go back to
+    committed: CTF,
+}
+
+impl From<ctf::Context> for Workspace {
+    fn from(context: ctf::Context) -> Self {
+        let committed = context.ctf.clone();
+        Workspace { context, committed }
+    }
+}
+
+impl Workspace {
+    /// Opens the repository that contains the directory.
+    pub fn open<P: Into<PathBuf>>(dir: P) -> Result<Workspace> {
+        Ok(Workspace::from(ctf::load(dir.into())?))
+    }
+
+    /// Directory that contains `.ctf`.
+    pub fn root(&self) -> &Path {
+        &self.context.root
+    }
+
+    /// Challenge whose directory the workspace was opened from, if any.
+    pub fn current_challenge(&self) -> Option<&str> {
+        self.context.path.first().map(String::as_str)
+    }
+
+    pub fn ctf(&self) -> &CTF {
+        &self.context.ctf
+    }
+
+    pub fn challenges(&self) -> &[Challenge] {
+        &self.context.ctf.challenges
+    }
+
+    pub fn challenge(&self, name: &str) -> Result<&Challenge> {
+        self.context
+            .ctf
+            .challenges
+            .iter()
+            .find(|challenge| challenge.name == name)
+            .ok_or_else(|| Error::NoSuchChallenge(name.into()))
+    }
+
+    pub fn challenge_dir(&self, name: &str) -> Result<PathBuf> {
+        Ok(self.context.root.join(&self.challenge(name)?.name))
+    }
+
+    pub fn binaries(&self, challenge: &str) -> Result<&[Binary]> {
+        Ok(&self.challenge(challenge)?.binaries)
+    }
+
+    pub fn binary(&self, challenge: &str, name: &str) -> Result<&Binary> {
+        self.binaries(challenge)?
+            .iter()
+            .find(|binary| binary.name == name)
+            .ok_or_else(|| Error::NoSuchBinary(format!("{}/{}", challenge, name)))
+    }
+
+    /// Returns the file of an alternative, or of the default one if `alternative` is `None`.
+    pub fn binary_path(
+        &self,
+        challenge: &str,
+        binary: &str,
+        alternative: Option<&str>,
+    ) -> Result<PathBuf> {
+        let found = self.binary(challenge, binary)?;
+        Ok(match alternative {
+            Some(alternative) => {
+                if !found.alternatives.iter().any(|a| a.name == alternative) {
+                    return Err(Error::NoSuchAlternative(format!(
+                        "{}/{}.{}",
+                        challenge, binary, alternative
+                    )));
+                }
+                ctf::alternative_path(&self.context.root, challenge, binary, alternative)
+            }
+            None => ctf::default_alternative_path(&self.context.root, challenge, binary),
+        })
+    }
+
+    pub fn services(&self, challenge: &str) -> Result<&[Service]> {
+        Ok(&self.challenge(challenge)?.services)
+    }
+
+    pub fn service(&self, challenge: &str, name: &str) -> Result<&Service> {
+        self.services(challenge)?
+            .iter()
+            .find(|service| option::contains(&service.name, &name))
+            .ok_or_else(|| Error::NoSuchService(format!("{}/{}", challenge, name)))
+    }
+
+    pub fn remotes(&self) -> &[Remote] {
+        &self.context.ctf.remotes
+    }
+
+    pub fn remote(&self, name: &str) -> Result<&Remote> {
+        self.context
+            .ctf
+            .remotes
+            .iter()
+            .find(|remote| remote.name == name)
+            .ok_or_else(|| Error::NoSuchRemote(name.into()))
+    }
+
+    /// Commits the changes to `.ctf` and the files; if this fails, the changes to `.ctf` are
+    /// undone, so that the workspace stays in sync with the repository.
+    fn commit(&mut self, message: &str, paths: &[PathBuf]) -> Result<()> {
+        match git::commit_files(&self.context, message, paths) {
+            Ok(_) => {
+                self.committed = self.context.ctf.clone();
+                Ok(())
+            }
+            Err(e) => {
+                self.context.ctf = self.committed.clone();
+                /* The error at hand is more interesting than a failure to restore. */
+                let _ = ctf::store(&self.context);
+                Err(e.into())
+            }
+        }
+    }
+
+    /// Adds a challenge, creating its directory if needed.
+    pub fn add_challenge(&mut self, name: &str) -> Result<()> {
+        check_name(Kind::Challenge, name)?;
+        if self.challenge(name).is_ok() {
+            return Err(Error::AlreadyExists(Kind::Challenge, name.into()));
+        }
+        fs::create_dir_all(self.context.root.join(name))?;
+        self.context.ctf.challenges.push(Challenge {
+            name: name.into(),
+            description: "".into(),
+            binaries: Vec::new(),
+            services: Vec::new(),
+            flags: Vec::new(),
+            claims: Vec::new(),
+            status: None,
+            notes: Vec::new(),
+        });
+        self.commit(&format!("Add challenge {}", name), &[])
+    }
+
+    /// Removes a challenge; its directory is left alone.
+    pub fn remove_challenge(&mut self, name: &str) -> Result<()> {
+        self.challenge(name)?;
+        self.context
+            .ctf
+            .challenges
+            .retain(|challenge| challenge.name != name);
+        self.commit(&format!("Remove challenge {}", name), &[])
+    }
+
+    pub fn set_description(&mut self, challenge: &str, description: &str) -> Result<()> {
+        find_challenge(&mut self.context.ctf, challenge)?.description = description.into();
+        self.commit(
+            &format!("Set challenge {} description to {}", challenge, description),
+            &[],
+        )
+    }
+
+    /// Registers a file in the challenge directory as a binary. The file is copied to its `orig`
+    /// alternative, which becomes the default one.
+    pub fn add_binary(&mut self, challenge: &str, name: &str) -> Result<()> {
+        check_name(Kind::Binary, name)?;
+        let path = ctf::default_alternative_path(&self.context.root, challenge, name);
+        let challenge = find_challenge(&mut self.context.ctf, challenge)?;
+        if challenge.binaries.iter().any(|binary| binary.name == name) {
+            return Err(Error::AlreadyExists(
+                Kind::Binary,
+                format!("{}/{}", challenge.name, name),
+            ));
+        }
+        if !path.is_file() {
+            return Err(Error::MissingFile(path));
+        }
+        let orig = ctf::alternative_path(&self.context.root, &challenge.name, name, "orig");
+        fs::copy(&path, &orig)?;
+        challenge.binaries.push(Binary {
+            name: name.into(),
+            alternatives: vec![new_alternative("orig")],
+            default_alternative: Some("orig".into()),
+        });
+        let message = format!("Add {}/{}.orig", challenge.name, name);
+        self.commit(&message, &[path, orig])
+    }
+
+    /// Registers a `binary.alternative` file in the challenge directory as an alternative of an
+    /// existing binary.
+    pub fn add_alternative(&mut self, challenge: &str, binary: &str, name: &str) -> Result<()> {
+        check_name(Kind::Alternative, name)?;
+        let path = ctf::alternative_path(&self.context.root, challenge, binary, name);
+        let binary = find_binary(find_challenge(&mut self.context.ctf, challenge)?, binary)?;
+        let full_name = format!("{}/{}.{}", challenge, binary.name, name);
+        if binary.alternatives.iter().any(|a| a.name == name) {
+            return Err(Error::AlreadyExists(Kind::Alternative, full_name));
+        }
+        if !path.is_file() {
+            return Err(Error::MissingFile(path));
+        }
+        binary.alternatives.push(new_alternative(name));
+        self.commit(&format!("Add {}", full_name), &[path])
+    }
+
+    /// Removes an alternative along with its file; removing the last one removes the binary.
+    pub fn remove_alternative(&mut self, challenge: &str, binary: &str, name: &str) -> Result<()> {
+        let root = self.context.root.clone();
+        let challenge = find_challenge(&mut self.context.ctf, challenge)?;
+        let challenge_name = challenge.name.clone();
+        let found = find_binary(challenge, binary)?;
+        let full_name = format!("{}/{}.{}", challenge_name, binary, name);
+        if let Some(patched) = patch::dependents(found, name).next() {
+            let patched = format!("{}/{}.{}", challenge_name, binary, patched);
+            return Err(Error::BaseOfPatched(full_name, patched));
+        }
+        if !found.alternatives.iter().any(|a| a.name == name) {
+            return Err(Error::NoSuchAlternative(full_name));
+        }
+        let is_default = option::contains(&found.default_alternative, &name);
+        let mut paths = vec![ctf::alternative_path(&root, &challenge_name, binary, name)];
+        if is_default {
+            paths.push(ctf::default_alternative_path(
+                &root,
+                &challenge_name,
+                binary,
+            ));
+        }
+        /* Remove the files first, so that a failure leaves .ctf alone. */
+        for path in &paths {
+            remove_file(path)?;
+        }
+        found.alternatives.retain(|a| a.name != name);
+        if is_default {
+            found.default_alternative = None;
+        }
+        if found.alternatives.is_empty() {
+            challenge.binaries.retain(|b| b.name != binary);
+        }
+        self.commit(&format!("Remove {}", full_name), &paths)
+    }
+
+    /// Removes a binary along with the files of all its alternatives.
+    pub fn remove_binary(&mut self, challenge: &str, name: &str) -> Result<()> {
+        let root = self.context.root.clone();
+        let challenge = find_challenge(&mut self.context.ctf, challenge)?;
+        let challenge_name = challenge.name.clone();
+        let found = find_binary(challenge, name)?;
+        let mut paths: Vec<PathBuf> = found
+            .alternatives
+            .iter()
+            .map(|a| ctf::alternative_path(&root, &challenge_name, name, &a.name))
+            .collect();
+        if found.default_alternative.is_some() {
+            paths.push(ctf::default_alternative_path(&root, &challenge_name, name));
+        }
+        for path in &paths {
+            remove_file(path)?;
+        }
+        challenge.binaries.retain(|b| b.name != name);
+        let message = format!("Remove binary {}/{}", challenge_name, name);
+        self.commit(&message, &paths)
+    }
+
+    /// Copies an alternative to the binary file.
+    pub fn set_default_alternative(
+        &mut self,
+        challenge: &str,
+        binary: &str,
+        alternative: &str,
+    ) -> Result<()> {
+        let root = self.context.root.clone();
+        let challenge = find_challenge(&mut self.context.ctf, challenge)?;
+        let challenge_name = challenge.name.clone();
+        let found = find_binary(challenge, binary)?;
+        if !found.alternatives.iter().any(|a| a.name == alternative) {
+            return Err(Error::NoSuchAlternative(format!(
+                "{}/{}.{}",
+                challenge_name, binary, alternative
+            )));
+        }
+        ctf::set_default_alternative(&root, &challenge_name, found, alternative)?;
+        self.commit(
+            &format!(
+                "Select binary {}/{}.{}",
+                challenge_name, binary, alternative
+            ),
+            &[ctf::default_alternative_path(
+                &root,
+                &challenge_name,
+                binary,
+            )],
+        )
+    }
+
+    pub fn add_service(&mut self, challenge: &str, name: &str, url: &str) -> Result<()> {
+        let challenge = find_challenge(&mut self.context.ctf, challenge)?;
+        if ctf::try_find_service_mut(challenge, name).is_some() {
+            return Err(Error::AlreadyExists(
+                Kind::Service,
+                format!("{}/{}", challenge.name, name),
+            ));
+        }
+        challenge.services.push(Service {
+            name: Some(name.into()),
+            url: url.into(),
+        });
+        let message = format!("Add service {} to challenge {}", name, challenge.name);
+        self.commit(&message, &[])
+    }
+
+    pub fn set_service_url(&mut self, challenge: &str, name: &str, url: &str) -> Result<()> {
+        let challenge = find_challenge(&mut self.context.ctf, challenge)?;
+        let message = format!(
+            "Set service {} URL to {} in challenge {}",
+            name, url, challenge.name
+        );
+        ctf::find_service_mut(challenge, name)?.url = url.into();
+        self.commit(&message, &[])
+    }
+
+    pub fn remove_service(&mut self, challenge: &str, name: &str) -> Result<()> {
+        let challenge = find_challenge(&mut self.context.ctf, challenge)?;
+        let n_services = challenge.services.len();
+        challenge
+            .services
+            .retain(|service| !option::contains(&service.name, &name));
+        if challenge.services.len() == n_services {
+            return Err(Error::NoSuchService(format!("{}/{}", challenge.name, name)));
+        }
+        let message = format!("Remove service {} from challenge {}", name, challenge.name);
+        self.commit(&message, &[])
+    }
+
+    pub fn add_remote(&mut self, name: &str, url: &str) -> Result<()> {
+        if self.remote(name).is_ok() {
+            return Err(Error::AlreadyExists(Kind::Remote, name.into()));
+        }
+        self.context.ctf.remotes.push(Remote {
+            name: name.into(),
+            url: url.into(),
+            engine: ctf::default_engine(),
+            rewrite_rules: Vec::new(),
+        });
+        self.commit(&format!("Add remote {} pointing to {}", name, url), &[])
+    }
+
+    pub fn remove_remote(&mut self, name: &str) -> Result<()> {
+        self.remote(name)?;
+        self.context
+            .ctf
+            .remotes
+            .retain(|remote| remote.name != name);
+        self.commit(&format!("Remove remote {}", name), &[])
+    }
+}
@@ -1,12 +1,17 @@
 extern crate ctftool;
 
 use std::fs::create_dir;
+use std::os::unix::fs::PermissionsExt;
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicI32, Ordering};
 
 use assert_cmd::cargo::cargo_bin;
 use assert_cmd::Command;
-use ctftool::{ctf, git};
+use ctftool::ui::{Event, RecordingUi};
+use ctftool::{ctf, git, Ui, Workspace};
+use elf::abi::{DT_RUNPATH, PT_INTERP, PT_LOAD};
+use elf::endian::AnyEndian;
+use elf::ElfBytes;
 use hyper::server::Server;
 use hyper::service::{make_service_fn, service_fn};
 use hyper::{Body, Request, Response};
@@ -44,10 +49,14 @@ fn test_exe() -> Result<()> {
     Ok(())
 }
 
-async fn main(current_dir: PathBuf, args: &[&str]) -> Result<()> {
+async fn main_ui(current_dir: PathBuf, args: &[&str], ui: &dyn Ui) -> Result<()> {
     let mut args_with_0 = vec!["ctftool"];
     args_with_0.extend(args);
-    ctftool::main(args_with_0.iter(), current_dir).await
+    ctftool::main(args_with_0.iter(), current_dir, ui).await
+}
+
+async fn main(current_dir: PathBuf, args: &[&str]) -> Result<()> {
+    main_ui(current_dir, args, &RecordingUi::new()).await
 }
 
 #[tokio::main]
@@ -55,12 +64,59 @@ async fn main_sync(current_dir: PathBuf, args: &[&str]) -> Result<()> {
     main(current_dir, args).await
 }
 
+#[tokio::main]
+async fn main_sync_ui(current_dir: PathBuf, args: &[&str], ui: &dyn Ui) -> Result<()> {
+    main_ui(current_dir, args, ui).await
+}
+
 #[test]
 fn test_init() -> Result<()> {
     ctftool::init_logging();
     let work_dir = WorkDir::new()?;
     main_sync(work_dir.to_path_buf(), &["init"])?;
     assert!(main_sync(work_dir.to_path_buf(), &["init"]).is_err());
+    /* Initializing again does not reset .ctf. */
+    create_dir(work_dir.to_path_buf().join("chal"))?;
+    main_sync(work_dir.to_path_buf(), &["challenge", "add", "chal"])?;
+    assert!(main_sync(work_dir.to_path_buf(), &["init"]).is_err());
+    let ctx = ctf::load(work_dir.to_path_buf())?;
+    assert!(ctf::find_challenge(&ctx.ctf, "chal").is_ok());
+    Ok(())
+}
+
+#[test]
+fn test_git_commit() -> Result<()> {
+    ctftool::init_logging();
+    let work_dir = WorkDir::new()?;
+    main_sync(work_dir.to_path_buf(), &["init"])?;
+    let chal = work_dir.to_path_buf().join("chal");
+    create_dir(&chal)?;
+    main_sync(work_dir.to_path_buf(), &["challenge", "add", "chal"])?;
+    let ctx = ctf::load(work_dir.to_path_buf())?;
+    /* Nothing changed. */
+    assert!(git::commit(&ctx, "Nothing")?.is_none());
+    let pwnit = chal.join("pwnit.py");
+    std::fs::write(&pwnit, "print('v1')\n")?;
+    let oid = git::commit_files(&ctx, "Add pwnit.py", std::slice::from_ref(&pwnit))?.unwrap();
+    let repo = git2::Repository::open(work_dir.to_path_buf())?;
+    assert_eq!(repo.head()?.peel_to_commit()?.id(), oid);
+    let revision = git::revision(&ctx.root, std::slice::from_ref(&pwnit))?.unwrap();
+    assert!(oid.to_string().starts_with(&revision));
+    /* Other commands do not commit the changes they did not make. */
+    std::fs::write(&pwnit, "print('v2')\n")?;
+    main_sync(
+        work_dir.to_path_buf(),
+        &["challenge", "set-description", "chal", "pwn"],
+    )?;
+    let tree = repo.head()?.peel_to_tree()?;
+    let blob = tree
+        .get_path(Path::new("chal/pwnit.py"))?
+        .to_object(&repo)?;
+    assert_eq!(blob.as_blob().unwrap().content(), b"print('v1')\n");
+    assert_eq!(
+        git::revision(&ctx.root, &[pwnit])?,
+        Some(format!("{}-dirty", revision))
+    );
     Ok(())
 }
 
@@ -158,7 +214,8 @@ impl StaticServer {
     }
 }
 
-async fn test_fetch(engine: &str) -> Result<()> {
+/// Serves the engine fixtures and fetches them into a new CTF repository.
+async fn serve_and_fetch(engine: &str) -> Result<(StaticServer, WorkDir)> {
     let mut root = cargo_bin("ctf");
     root.pop();
     root.pop();
@@ -178,6 +235,11 @@ async fn test_fetch(engine: &str) -> Result<()> {
     });
     git::commit(&ctx, "Add rewrite rules")?;
     main(work_dir.to_path_buf(), &["fetch"]).await?;
+    Ok((server, work_dir))
+}
+
+async fn test_fetch(engine: &str) -> Result<()> {
+    let (server, _work_dir) = serve_and_fetch(engine).await?;
     server.shutdown().await?;
     Ok(())
 }
@@ -232,6 +294,423 @@ fn test_challenge() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_challenge_board() -> Result<()> {
+    ctftool::init_logging();
+    let work_dir = WorkDir::new()?;
+    main_sync(work_dir.to_path_buf(), &["init"])?;
+    let challenge_dir = work_dir.to_path_buf().join("test");
+    create_dir(&challenge_dir)?;
+    main_sync(work_dir.to_path_buf(), &["challenge", "add", "test"])?;
+    /* Not in a challenge directory. */
+    assert!(main_sync(work_dir.to_path_buf(), &["challenge", "claim"]).is_err());
+    main_sync(challenge_dir.clone(), &["challenge", "claim"])?;
+    assert!(main_sync(challenge_dir.clone(), &["challenge", "claim"]).is_err());
+    main_sync(
+        work_dir.to_path_buf(),
+        &["challenge", "note", "UAF in delete()", "test"],
+    )?;
+    main_sync(work_dir.to_path_buf(), &["challenge", "show"])?;
+    let ctx = ctf::load(work_dir.to_path_buf())?;
+    let challenge = ctf::find_challenge(&ctx.ctf, "test")?;
+    assert_eq!(challenge.claims[0].author, "ctf <ctf@localhost>");
+    assert!(challenge.status.as_ref().unwrap().value == ctf::Status::Wip);
+    assert_eq!(challenge.notes[0].text, "UAF in delete()");
+    assert!(main_sync(challenge_dir.clone(), &["challenge", "status", "pwned"]).is_err());
+    main_sync(challenge_dir.clone(), &["challenge", "status", "solved"])?;
+    main_sync(challenge_dir.clone(), &["challenge", "claim", "--release"])?;
+
+    /* Fetching does not lose the board. */
+    let mut ctx = ctf::load(work_dir.to_path_buf())?;
+    let fetched = ctf::CTF {
+        challenges: vec![ctf::Challenge {
+            name: "test".into(),
+            description: "fetched description".into(),
+            binaries: Vec::new(),
+            services: Vec::new(),
+            flags: Vec::new(),
+            claims: Vec::new(),
+            status: None,
+            notes: Vec::new(),
+        }],
+        ..ctf::CTF::default()
+    };
+    ctf::merge(&mut ctx.ctf, fetched);
+    let challenge = ctf::find_challenge(&ctx.ctf, "test")?;
+    assert_eq!(challenge.description, "fetched description");
+    assert!(challenge.claims.is_empty());
+    assert!(challenge.status.as_ref().unwrap().value == ctf::Status::Solved);
+    assert_eq!(challenge.notes.len(), 1);
+    Ok(())
+}
+
+#[test]
+fn test_ui() -> Result<()> {
+    ctftool::init_logging();
+    let work_dir = WorkDir::new()?;
+    main_sync(work_dir.to_path_buf(), &["init"])?;
+    create_dir(work_dir.to_path_buf().join("test"))?;
+    main_sync(work_dir.to_path_buf(), &["challenge", "add", "test"])?;
+    main_sync(
+        work_dir.to_path_buf(),
+        &["challenge", "set-description", "test", "heap"],
+    )?;
+    let ui = RecordingUi::new();
+    main_sync_ui(work_dir.to_path_buf(), &["challenge", "show"], &ui)?;
+    assert_eq!(ui.lines(), ["todo:", "  nobody:", "    test - heap"]);
+
+    /* Standard input comes from the UI too. */
+    let ui = RecordingUi::new().with_input(b"leaked flag{st4nd4rd_1nput}\n");
+    main_sync_ui(work_dir.to_path_buf(), &["flag", "scan", "-"], &ui)?;
+    assert_eq!(
+        ui.events(),
+        [
+            Event::Records(serde_json::json!([
+                {"location": "-", "flag": "flag{st4nd4rd_1nput}"}
+            ])),
+            Event::Message("-: flag{st4nd4rd_1nput}".into())
+        ]
+    );
+
+    /* Prompts without replies fail instead of blocking. */
+    main_sync(
+        work_dir.to_path_buf(),
+        &["remote", "add", "origin", "http://127.0.0.1:1"],
+    )?;
+    let ui = RecordingUi::new();
+    assert!(main_sync_ui(work_dir.to_path_buf(), &["login"], &ui).is_err());
+    assert_eq!(ui.events(), [Event::Prompt("Login: ".into())]);
+    Ok(())
+}
+
+#[test]
+fn test_format() -> Result<()> {
+    ctftool::init_logging();
+    let work_dir = WorkDir::new()?;
+    let root = work_dir.to_path_buf();
+    main_sync(root.clone(), &["init"])?;
+    let mut workspace = Workspace::open(root.clone())?;
+    workspace.add_challenge("test")?;
+    workspace.set_description("test", "Heap notes\nnc 127.0.0.1 1337\n")?;
+    workspace.add_service("test", "main", "nc://127.0.0.1:1337")?;
+    workspace.add_remote("origin", "http://127.0.0.1:1")?;
+    std::fs::write(root.join("test").join("chall"), "orig")?;
+    workspace.add_binary("test", "chall")?;
+
+    /* Multi-line descriptions do not break the layout. */
+    let ui = RecordingUi::new();
+    main_sync_ui(root.clone(), &["challenge", "show"], &ui)?;
+    assert_eq!(
+        ui.lines(),
+        [
+            "todo:",
+            "  nobody:",
+            "    test - Heap notes",
+            "      nc 127.0.0.1 1337"
+        ]
+    );
+    let ui = RecordingUi::new();
+    main_sync_ui(
+        root.clone(),
+        &["challenge", "show", "--format", "json"],
+        &ui,
+    )?;
+    let json: serde_json::Value = serde_json::from_str(&ui.lines().join("\n"))?;
+    assert_eq!(json[0]["name"], "test");
+    assert_eq!(json[0]["services"][0]["url"], "nc://127.0.0.1:1337");
+    let ui = RecordingUi::new();
+    main_sync_ui(
+        root.clone(),
+        &["--format", "yaml", "challenge", "show"],
+        &ui,
+    )?;
+    let challenges: Vec<ctf::Challenge> = serde_yaml::from_str(&ui.lines().join("\n"))?;
+    assert_eq!(challenges[0].description, "Heap notes\nnc 127.0.0.1 1337\n");
+
+    let ui = RecordingUi::new();
+    main_sync_ui(
+        root.join("test"),
+        &["binary", "show", "--format", "json"],
+        &ui,
+    )?;
+    let json: serde_json::Value = serde_json::from_str(&ui.lines().join("\n"))?;
+    assert_eq!(json[0]["default_alternative"], "orig");
+    let ui = RecordingUi::new();
+    main_sync_ui(root.join("test"), &["binary", "show"], &ui)?;
+    assert_eq!(ui.lines(), ["NAME       DEFAULT BASE", "chall.orig yes"]);
+
+    let ui = RecordingUi::new();
+    main_sync_ui(
+        root.join("test"),
+        &["service", "show", "--format", "json"],
+        &ui,
+    )?;
+    let json: serde_json::Value = serde_json::from_str(&ui.lines().join("\n"))?;
+    assert_eq!(json[0]["name"], "main");
+    let ui = RecordingUi::new();
+    main_sync_ui(root.clone(), &["remote", "show"], &ui)?;
+    assert_eq!(
+        ui.lines(),
+        [
+            "NAME   URL                ENGINE",
+            "origin http://127.0.0.1:1 auto"
+        ]
+    );
+    assert!(main_sync(root.clone(), &["remote", "show", "--format", "xml"]).is_err());
+
+    /* Other read commands print records as well. */
+    let json = |dir: PathBuf, args: &[&str]| -> Result<serde_json::Value> {
+        let ui = RecordingUi::new();
+        let mut args = args.to_vec();
+        args.extend(["--format", "json"]);
+        main_sync_ui(dir, &args, &ui)?;
+        Ok(serde_json::from_str(&ui.lines().join("\n"))?)
+    };
+    let log = json(root.clone(), &["log", "-n", "1"])?;
+    assert_eq!(log[0]["summary"], "Add test/chall.orig");
+    assert_eq!(log[0]["changes"][0], "+binary test/chall.orig");
+    let flag_format = json(root.clone(), &["flag", "format"])?;
+    assert_eq!(flag_format["default"], true);
+    std::fs::write(root.join("flag.txt"), "hxp{f0rm4t}")?;
+    let flags = json(root.clone(), &["flag", "scan", "flag.txt"])?;
+    assert_eq!(flags[0]["flag"], "hxp{f0rm4t}");
+    let templates = json(root.clone(), &["exploit", "templates"])?;
+    assert_eq!(templates[0]["name"], "linux-user");
+    assert!(templates[0].get("files").is_none());
+    main_sync(root.clone(), &["ad", "init", "--teams", "10.60.1-2.1"])?;
+    let targets = json(root.clone(), &["ad", "targets"])?;
+    assert_eq!(targets, serde_json::json!(["10.60.1.1", "10.60.2.1"]));
+    let engine = json(root.clone(), &["remote", "get-engine", "origin"])?;
+    assert_eq!(engine, "auto");
+    /* Library callers get the records typed, whatever the format. */
+    let ui = RecordingUi::new();
+    main_sync_ui(root.clone(), &["challenge", "show"], &ui)?;
+    assert_eq!(ui.records()[0][0]["name"], "test");
+    let ui = RecordingUi::new();
+    main_sync_ui(root.clone(), &["exploit", "templates"], &ui)?;
+    assert_eq!(ui.records()[0][0]["name"], "linux-user");
+    assert!(ui.lines()[0].starts_with("NAME"));
+    /* Subcommands that do not print records reject --format, even if their siblings accept it. */
+    for args in [
+        &["docker", "shell"][..],
+        &["exploit", "run", "--local"],
+        &["ad", "run", "test", "--once"],
+        &["binary", "patch", "chall", "--at", "0", "--bytes", "90"],
+    ] {
+        let ui = RecordingUi::new();
+        let mut args = args.to_vec();
+        args.extend(["--format", "json"]);
+        let e = main_sync_ui(root.join("test"), &args, &ui).unwrap_err();
+        assert!(e.to_string().contains("is not supported"), "{}", e);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_workspace() -> Result<()> {
+    use ctftool::workspace::{Error, Kind};
+    ctftool::init_logging();
+    let work_dir = WorkDir::new()?;
+    let root = work_dir.to_path_buf();
+    assert!(matches!(
+        Workspace::open(root.clone()),
+        Err(Error::NotAWorkspace(_))
+    ));
+    main_sync(root.clone(), &["init"])?;
+    let mut workspace = Workspace::open(root.clone())?;
+    workspace.add_challenge("test")?;
+    assert!(root.join("test").is_dir());
+    assert!(matches!(
+        workspace.add_challenge("test"),
+        Err(Error::AlreadyExists(Kind::Challenge, _))
+    ));
+    assert!(matches!(
+        workspace.add_challenge("../test"),
+        Err(Error::InvalidName(Kind::Challenge, _))
+    ));
+    assert!(matches!(
+        workspace.challenge("nope"),
+        Err(Error::NoSuchChallenge(_))
+    ));
+    workspace.set_description("test", "heap")?;
+
+    /* Binaries keep their files in sync. */
+    assert!(matches!(
+        workspace.add_binary("test", "chall"),
+        Err(Error::MissingFile(_))
+    ));
+    std::fs::write(root.join("test").join("chall"), "orig")?;
+    assert!(matches!(
+        workspace.add_binary("test", "../test/chall"),
+        Err(Error::InvalidName(Kind::Binary, _))
+    ));
+    workspace.add_binary("test", "chall")?;
+    assert_eq!(
+        std::fs::read(root.join("test").join("chall.orig"))?,
+        b"orig"
+    );
+    std::fs::write(root.join("test").join("chall.patched"), "patched")?;
+    assert!(matches!(
+        workspace.add_alternative("test", "chall", "patched/../../../x"),
+        Err(Error::InvalidName(Kind::Alternative, _))
+    ));
+    workspace.add_alternative("test", "chall", "patched")?;
+    workspace.set_default_alternative("test", "chall", "patched")?;
+    assert_eq!(
+        std::fs::read(workspace.binary_path("test", "chall", None)?)?,
+        b"patched"
+    );
+    /* Failed operations leave .ctf alone. */
+    let patched = root.join("test").join("chall.patched");
+    std::fs::remove_file(&patched)?;
+    std::fs::create_dir(&patched)?;
+    std::fs::write(patched.join("file"), "")?;
+    assert!(workspace
+        .remove_alternative("test", "chall", "patched")
+        .is_err());
+    assert_eq!(workspace.binary("test", "chall")?.alternatives.len(), 2);
+    std::fs::remove_dir_all(&patched)?;
+    std::fs::write(&patched, "patched")?;
+    let index_lock = root.join(".git").join("index.lock");
+    std::fs::write(&index_lock, "")?;
+    assert!(workspace
+        .add_remote("locked", "http://127.0.0.1:3")
+        .is_err());
+    std::fs::remove_file(&index_lock)?;
+    assert!(workspace.remote("locked").is_err());
+    assert!(Workspace::open(root.clone())?.remote("locked").is_err());
+    workspace.remove_alternative("test", "chall", "patched")?;
+    assert!(!root.join("test").join("chall").exists());
+    assert_eq!(workspace.binary("test", "chall")?.default_alternative, None);
+    workspace.remove_binary("test", "chall")?;
+    assert!(!root.join("test").join("chall.orig").exists());
+    assert!(matches!(
+        workspace.binary("test", "chall"),
+        Err(Error::NoSuchBinary(_))
+    ));
+
+    workspace.add_service("test", "web", "http://127.0.0.1:8080")?;
+    workspace.set_service_url("test", "web", "nc://127.0.0.1:1337")?;
+    assert!(matches!(
+        workspace.remove_service("test", "db"),
+        Err(Error::NoSuchService(_))
+    ));
+    workspace.add_remote("origin", "http://127.0.0.1:1")?;
+    assert!(matches!(
+        workspace.add_remote("origin", "http://127.0.0.1:2"),
+        Err(Error::AlreadyExists(Kind::Remote, _))
+    ));
+
+    /* Everything is stored and committed. */
+    let workspace = Workspace::open(root.join("test"))?;
+    assert_eq!(workspace.current_challenge(), Some("test"));
+    assert_eq!(workspace.challenge("test")?.description, "heap");
+    assert_eq!(workspace.service("test", "web")?.url, "nc://127.0.0.1:1337");
+    assert_eq!(workspace.remote("origin")?.url, "http://127.0.0.1:1");
+    let repo = git2::Repository::open(&root)?;
+    let mut options = git2::StatusOptions::new();
+    options.include_ignored(false);
+    assert!(repo.statuses(Some(&mut options))?.is_empty());
+
+    /* Commands fail with the same typed errors. */
+    let e = main_sync(root.clone(), &["remote", "rm", "nope"]).unwrap_err();
+    assert!(matches!(
+        e.downcast_ref::<Error>(),
+        Some(Error::NoSuchRemote(_))
+    ));
+    Ok(())
+}
+
+fn git(current_dir: &Path, args: &[&str]) -> Result<()> {
+    let status = std::process::Command::new("git")
+        .args(args)
+        .current_dir(current_dir)
+        .status()?;
+    if !status.success() {
+        return Err(anyhow!("git {:?} failed: {}", args, status));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_sync() -> Result<()> {
+    ctftool::init_logging();
+    /* git runs ctf merge-driver. */
+    let bin_dir = cargo_bin("ctf").parent().unwrap().to_path_buf();
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    let mut paths = vec![bin_dir];
+    paths.extend(std::env::split_paths(&path));
+    std::env::set_var("PATH", std::env::join_paths(paths)?);
+
+    let work_dir = WorkDir::new()?;
+    let origin = work_dir.to_path_buf().join("origin.git");
+    create_dir(&origin)?;
+    git(&origin, &["init", "--bare", "--initial-branch=master"])?;
+    let alice = work_dir.to_path_buf().join("alice");
+    create_dir(&alice)?;
+    main_sync(alice.clone(), &["init"])?;
+    create_dir(alice.join("chal"))?;
+    main_sync(alice.clone(), &["challenge", "add", "chal"])?;
+    git(
+        &alice,
+        &["remote", "add", "origin", origin.to_str().unwrap()],
+    )?;
+    main_sync(alice.clone(), &["sync"])?;
+
+    let bob = work_dir.to_path_buf().join("bob");
+    git(
+        &work_dir.to_path_buf(),
+        &["clone", origin.to_str().unwrap(), "bob"],
+    )?;
+    git::set_option(&bob, "user.name", "bob")?;
+    git::set_option(&bob, "user.email", "bob@localhost")?;
+    create_dir(bob.join("chal"))?;
+    main_sync(bob.join("chal"), &["challenge", "claim"])?;
+    main_sync(
+        bob.clone(),
+        &["challenge", "set-description", "chal", "heap"],
+    )?;
+    create_dir(bob.join("chal3"))?;
+    main_sync(bob.clone(), &["challenge", "add", "chal3"])?;
+    main_sync(bob.clone(), &["sync"])?;
+
+    main_sync(alice.clone(), &["challenge", "note", "tcache", "chal"])?;
+    create_dir(alice.join("chal2"))?;
+    main_sync(alice.clone(), &["challenge", "add", "chal2"])?;
+    main_sync(alice.clone(), &["sync"])?;
+    main_sync(bob.clone(), &["sync"])?;
+    for root in [&alice, &bob] {
+        let ctx = ctf::load(root.clone())?;
+        let names: Vec<&str> = ctx
+            .ctf
+            .challenges
+            .iter()
+            .map(|challenge| challenge.name.as_str())
+            .collect();
+        assert_eq!(names, ["chal", "chal3", "chal2"]);
+        let challenge = ctf::find_challenge(&ctx.ctf, "chal")?;
+        assert_eq!(challenge.description, "heap");
+        assert_eq!(challenge.claims[0].name(), "bob");
+        assert_eq!(challenge.notes[0].text, "tcache");
+    }
+
+    /* Real conflicts are reported. */
+    let load = |description: &str| -> Result<ctf::CTF> {
+        let mut ctx = ctf::load(alice.clone())?;
+        ctf::find_challenge_mut(&mut ctx.ctf, "chal")?.description = description.into();
+        Ok(ctx.ctf)
+    };
+    let (_, conflicts) = ctf::merge3(load("heap")?, load("uaf")?, load("tcache")?);
+    assert_eq!(
+        conflicts,
+        ["challenges/chal/description: changed on both sides"]
+    );
+    let (merged, conflicts) = ctf::merge3(load("heap")?, load("heap")?, load("tcache")?);
+    assert!(conflicts.is_empty());
+    assert_eq!(merged.challenges[0].description, "tcache");
+    Ok(())
+}
+
 fn contains(haystack: &[u8], needle: &[u8]) -> bool {
     haystack
         .windows(needle.len())
@@ -266,6 +745,50 @@ fn test_binary() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_undo() -> Result<()> {
+    ctftool::init_logging();
+    let work_dir = WorkDir::new()?;
+    main_sync(work_dir.to_path_buf(), &["init"])?;
+    assert!(main_sync(work_dir.to_path_buf(), &["undo"]).is_err());
+    let chal = work_dir.to_path_buf().join("chal");
+    create_dir(&chal)?;
+    main_sync(work_dir.to_path_buf(), &["challenge", "add", "chal"])?;
+    std::fs::write(chal.join("exe"), "exe")?;
+    main_sync(chal.clone(), &["binary", "add", "exe"])?;
+    main_sync(
+        work_dir.to_path_buf(),
+        &["challenge", "set-description", "chal", "pwn"],
+    )?;
+    main_sync(work_dir.to_path_buf(), &["log"])?;
+    let ctx = ctf::load(work_dir.to_path_buf())?;
+    let operations = git::history(&ctx.root, 2)?;
+    let before = ctf::parse(&operations[1].ctf)?;
+    let after = ctf::parse(&operations[0].ctf)?;
+    assert_eq!(ctf::diff(&before, &after), ["~description chal"]);
+
+    main_sync(work_dir.to_path_buf(), &["undo", "2"])?;
+    let ctx = ctf::load(work_dir.to_path_buf())?;
+    let challenge = ctf::find_challenge(&ctx.ctf, "chal")?;
+    assert!(challenge.binaries.is_empty());
+    assert_eq!(challenge.description, "");
+    /* The original file stays, the copy is deleted. */
+    assert_eq!(std::fs::read(chal.join("exe"))?, b"exe");
+    assert!(!chal.join("exe.orig").exists());
+
+    /* Undo the undo. */
+    main_sync(work_dir.to_path_buf(), &["undo"])?;
+    let ctx = ctf::load(work_dir.to_path_buf())?;
+    assert_eq!(ctf::find_challenge(&ctx.ctf, "chal")?.description, "pwn");
+    assert_eq!(std::fs::read(chal.join("exe.orig"))?, b"exe");
+
+    /* Uncommitted changes are not overwritten. */
+    std::fs::write(chal.join("exe.orig"), "patched")?;
+    assert!(main_sync(work_dir.to_path_buf(), &["undo"]).is_err());
+    assert_eq!(std::fs::read(chal.join("exe.orig"))?, b"patched");
+    Ok(())
+}
+
 #[test]
 fn test_service() -> Result<()> {
     ctftool::init_logging();
@@ -288,3 +811,1112 @@ fn test_service() -> Result<()> {
     assert!(main_sync(chal.clone(), &["service", "rm", "default"]).is_err());
     Ok(())
 }
+
+#[test]
+fn test_binary_patchelf() -> Result<()> {
+    ctftool::init_logging();
+    let work_dir = WorkDir::new()?;
+    main_sync(work_dir.to_path_buf(), &["init"])?;
+    let chal = work_dir.to_path_buf().join("chal");
+    create_dir(&chal)?;
+    main_sync(work_dir.to_path_buf(), &["challenge", "add", "chal"])?;
+    std::fs::copy("/bin/true", chal.join("exe"))?;
+    /* Cannot patch without a loader and libc. */
+    main_sync(chal.clone(), &["binary", "add", "exe"])?;
+    assert!(main_sync(chal.clone(), &["binary", "patchelf", "exe"]).is_err());
+    std::fs::write(chal.join("ld-linux-x86-64.so.2"), "")?;
+    std::fs::write(chal.join("libc.so.6"), "")?;
+    main_sync(chal.clone(), &["binary", "patchelf", "exe"])?;
+    let bytes = std::fs::read(chal.join("exe"))?;
+    assert_eq!(bytes, std::fs::read(chal.join("exe.patched"))?);
+    let elf = ElfBytes::<AnyEndian>::minimal_parse(&bytes)?;
+    let interp = elf
+        .segments()
+        .unwrap()
+        .iter()
+        .find(|phdr| phdr.p_type == PT_INTERP)
+        .unwrap();
+    assert_eq!(elf.segment_data(&interp)?, b"./ld-linux-x86-64.so.2\0");
+    let runpath = elf
+        .dynamic()?
+        .unwrap()
+        .iter()
+        .find(|dyn_| dyn_.d_tag == DT_RUNPATH)
+        .unwrap();
+    let dynstr = elf.section_header_by_name(".dynstr")?.unwrap();
+    assert_eq!(
+        elf.section_data_as_strtab(&dynstr)?
+            .get(runpath.d_val() as usize)?,
+        "$ORIGIN"
+    );
+    /* Byte patches go on top of the patched loader and libc. */
+    let at = format!("{:#x}", elf.ehdr.e_entry);
+    main_sync(
+        chal.clone(),
+        &["binary", "patch", "exe", "--at", &at, "--bytes", "90"],
+    )?;
+    let ctx = ctf::load(work_dir.to_path_buf())?;
+    let binary = ctf::find_binary(&ctx.ctf.challenges[0], "exe")?;
+    let alternative = ctf::find_alternative(binary, "bytepatched")?;
+    assert_eq!(alternative.base.as_deref(), Some("patched"));
+    let bytes = std::fs::read(chal.join("exe.bytepatched"))?;
+    let elf = ElfBytes::<AnyEndian>::minimal_parse(&bytes)?;
+    assert!(elf
+        .segments()
+        .unwrap()
+        .iter()
+        .any(|phdr| phdr.p_type == PT_INTERP
+            && elf.segment_data(&phdr).ok() == Some(b"./ld-linux-x86-64.so.2\0")));
+    Ok(())
+}
+
+#[test]
+fn test_binary_patch() -> Result<()> {
+    ctftool::init_logging();
+    let work_dir = WorkDir::new()?;
+    main_sync(work_dir.to_path_buf(), &["init"])?;
+    let chal = work_dir.to_path_buf().join("chal");
+    create_dir(&chal)?;
+    main_sync(work_dir.to_path_buf(), &["challenge", "add", "chal"])?;
+    std::fs::copy("/bin/true", chal.join("exe"))?;
+    main_sync(chal.clone(), &["binary", "add", "exe"])?;
+    let orig = std::fs::read(chal.join("exe.orig"))?;
+    let elf = ElfBytes::<AnyEndian>::minimal_parse(&orig)?;
+    let entry = elf.ehdr.e_entry;
+    let entry_offset = elf
+        .segments()
+        .unwrap()
+        .iter()
+        .find(|phdr| {
+            phdr.p_type == PT_LOAD && entry >= phdr.p_vaddr && entry < phdr.p_vaddr + phdr.p_filesz
+        })
+        .map(|phdr| (entry - phdr.p_vaddr + phdr.p_offset) as usize)
+        .unwrap();
+    let at = format!("{:#x}", entry);
+    main_sync(
+        chal.clone(),
+        &["binary", "patch", "exe", "--at", &at, "--bytes", "cc"],
+    )?;
+    let jmp = format!("nop; jmp {:#x}", entry + 0x100);
+    main_sync(
+        chal.clone(),
+        &["binary", "patch", "exe", "--at", &at, "--asm", &jmp],
+    )?;
+    /* Cannot patch an alternative in place. */
+    assert!(main_sync(
+        chal.clone(),
+        &[
+            "binary",
+            "patch",
+            "exe.bytepatched",
+            "--at",
+            &at,
+            "--bytes",
+            "90"
+        ],
+    )
+    .is_err());
+    let patched = std::fs::read(chal.join("exe.bytepatched"))?;
+    assert_eq!(
+        &patched[entry_offset..entry_offset + 6],
+        &[0x90, 0xe9, 0xfa, 0x00, 0x00, 0x00]
+    );
+    assert_eq!(patched.len(), orig.len());
+    assert_eq!(std::fs::read(chal.join("exe"))?, patched);
+    /* Patched alternatives are reproducible. */
+    std::fs::remove_file(chal.join("exe.bytepatched"))?;
+    main_sync(chal.clone(), &["checkout"])?;
+    assert_eq!(std::fs::read(chal.join("exe.bytepatched"))?, patched);
+    /* Bases are rebuilt first, whatever the order of the alternatives. */
+    main_sync(
+        chal.clone(),
+        &[
+            "binary",
+            "patch",
+            "exe",
+            "--at",
+            &at,
+            "--bytes",
+            "c3",
+            "--alternative",
+            "p3",
+        ],
+    )?;
+    let mut expected = patched.clone();
+    expected[entry_offset] = 0xc3;
+    let mut ctx = ctf::load(work_dir.to_path_buf())?;
+    let challenge = ctf::find_challenge_mut(&mut ctx.ctf, "chal")?;
+    ctf::find_binary_mut(&mut challenge.binaries, "exe")?
+        .alternatives
+        .reverse();
+    ctf::store(&ctx)?;
+    std::fs::remove_file(chal.join("exe.bytepatched"))?;
+    std::fs::remove_file(chal.join("exe.p3"))?;
+    main_sync(chal.clone(), &["checkout"])?;
+    assert_eq!(std::fs::read(chal.join("exe.bytepatched"))?, patched);
+    assert_eq!(std::fs::read(chal.join("exe.p3"))?, expected);
+    /* Bases can only be removed together with the alternatives patched on top of them. */
+    assert!(main_sync(chal.clone(), &["binary", "rm", "exe.bytepatched"]).is_err());
+    assert!(chal.join("exe.bytepatched").exists());
+    main_sync(chal.clone(), &["binary", "rm", "exe.bytepatched", "exe.p3"])?;
+    main_sync(chal.clone(), &["checkout"])?;
+    Ok(())
+}
+
+#[test]
+fn test_docker_init_distros() -> Result<()> {
+    ctftool::init_logging();
+    let work_dir = WorkDir::new()?;
+    main_sync(work_dir.to_path_buf(), &["init"])?;
+    let chal = work_dir.to_path_buf().join("chal");
+    create_dir(&chal)?;
+    main_sync(work_dir.to_path_buf(), &["challenge", "add", "chal"])?;
+    /* gcc alone matches both 23.10 and 24.04, libc breaks the tie. */
+    std::fs::write(chal.join("exe"), "GCC: (Ubuntu 13.2.0-23ubuntu4) 13.2.0")?;
+    std::fs::write(
+        chal.join("libc.so.6"),
+        "GNU C Library (Ubuntu GLIBC 2.39-0ubuntu8.3) stable release version 2.39.",
+    )?;
+    main_sync(chal.clone(), &["binary", "add", "exe", "libc.so.6"])?;
+    main_sync(chal.clone(), &["docker", "init"])?;
+    let compose = std::fs::read(chal.join("docker-compose.yml"))?;
+    assert!(contains(&compose, b"distro: ubuntu:24.04"));
+    assert!(contains(&compose, b"libc_version: 2.39-0ubuntu8.3"));
+    std::fs::write(chal.join("exe"), "GCC: (Debian 14.2.0-19) 14.2.0")?;
+    std::fs::write(
+        chal.join("libc.so.6"),
+        "GNU C Library (Debian GLIBC 2.41-12) stable release version 2.41.",
+    )?;
+    main_sync(chal.clone(), &["docker", "init"])?;
+    let compose = std::fs::read(chal.join("docker-compose.yml"))?;
+    assert!(contains(&compose, b"distro: debian:13"));
+    Ok(())
+}
+
+#[test]
+fn test_docker_init_musl() -> Result<()> {
+    ctftool::init_logging();
+    let work_dir = WorkDir::new()?;
+    main_sync(work_dir.to_path_buf(), &["init"])?;
+    let chal = work_dir.to_path_buf().join("chal");
+    create_dir(&chal)?;
+    main_sync(work_dir.to_path_buf(), &["challenge", "add", "chal"])?;
+    std::fs::write(chal.join("exe"), "\0No error information\0")?;
+    main_sync(chal.clone(), &["binary", "add", "exe"])?;
+    /* Only musl is known. */
+    main_sync(chal.clone(), &["docker", "init"])?;
+    let compose = std::fs::read(chal.join("docker-compose.yml"))?;
+    assert!(contains(&compose, b"distro: alpine:latest"));
+    assert!(contains(
+        &std::fs::read(chal.join("image").join("Dockerfile"))?,
+        b"apk add"
+    ));
+    /* gcc alone matches both 3.19 and 3.20, musl breaks the tie. */
+    std::fs::write(
+        chal.join("exe"),
+        "\0No error information\0GCC: (Alpine 13.2.1_git20240309) 13.2.1 20240309",
+    )?;
+    std::fs::write(
+        chal.join("ld-musl-x86_64.so.1"),
+        "musl libc (x86_64)\nVersion %s\n\x001.2.5\0",
+    )?;
+    main_sync(chal.clone(), &["binary", "add", "ld-musl-x86_64.so.1"])?;
+    main_sync(chal.clone(), &["docker", "init"])?;
+    let compose = std::fs::read(chal.join("docker-compose.yml"))?;
+    assert!(contains(&compose, b"distro: alpine:3.20"));
+    assert!(contains(&compose, b"libc_version: 1.2.5"));
+    assert!(!contains(&compose, b"qemu-"));
+    /* Foreign binaries need the emulator. */
+    let mut elf = elf_with_sections(&[(".rodata", b"\0No error information\0")]);
+    /* ET_EXEC, EM_AARCH64 */
+    elf[16..18].copy_from_slice(&2u16.to_le_bytes());
+    elf[18..20].copy_from_slice(&183u16.to_le_bytes());
+    std::fs::write(chal.join("exe"), elf)?;
+    main_sync(chal.clone(), &["docker", "init"])?;
+    let compose = std::fs::read(chal.join("docker-compose.yml"))?;
+    assert!(contains(&compose, b"distro: alpine:"));
+    assert!(contains(&compose, b"packages: qemu-aarch64"));
+    assert!(contains(&compose, b"EXEC:./qemu.sh,stderr"));
+    Ok(())
+}
+
+fn gzip(bytes: &[u8]) -> Result<Vec<u8>> {
+    use std::io::Write;
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(bytes)?;
+    Ok(encoder.finish()?)
+}
+
+fn cpio_entry(cpio: &mut Vec<u8>, name: &str, mode: u32, data: &[u8]) {
+    let fields = [
+        0,
+        mode,
+        0,
+        0,
+        1,
+        0,
+        data.len() as u32,
+        0,
+        0,
+        0,
+        0,
+        name.len() as u32 + 1,
+        0,
+    ];
+    cpio.extend_from_slice(b"070701");
+    for field in fields {
+        cpio.extend_from_slice(format!("{:08x}", field).as_bytes());
+    }
+    cpio.extend_from_slice(name.as_bytes());
+    cpio.push(0);
+    cpio.resize((cpio.len() + 3) & !3, 0);
+    cpio.extend_from_slice(data);
+    cpio.resize((cpio.len() + 3) & !3, 0);
+}
+
+/// Builds a relocatable ELF64 file with the given sections.
+fn elf_with_sections(sections: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut shstrtab = b"\0.shstrtab\0".to_vec();
+    let mut bytes = vec![0; 64];
+    let mut shdrs = vec![[0u64; 8]];
+    for (name, data) in sections {
+        let sh_name = shstrtab.len() as u64;
+        shstrtab.extend_from_slice(name.as_bytes());
+        shstrtab.push(0);
+        /* sh_name | sh_type << 32, sh_flags, sh_addr, sh_offset, sh_size, ... */
+        shdrs.push([
+            sh_name | 1 << 32,
+            2,
+            0,
+            bytes.len() as u64,
+            data.len() as u64,
+            0,
+            1,
+            0,
+        ]);
+        bytes.extend_from_slice(data);
+    }
+    shdrs.push([
+        1 | 3 << 32,
+        0,
+        0,
+        bytes.len() as u64,
+        shstrtab.len() as u64,
+        0,
+        1,
+        0,
+    ]);
+    bytes.extend_from_slice(&shstrtab);
+    bytes.resize((bytes.len() + 7) & !7, 0);
+    let shoff = bytes.len() as u64;
+    for shdr in &shdrs {
+        for word in shdr {
+            bytes.extend_from_slice(&word.to_le_bytes());
+        }
+    }
+    bytes[..16].copy_from_slice(b"\x7fELF\x02\x01\x01\0\0\0\0\0\0\0\0\0");
+    bytes[16..18].copy_from_slice(&1u16.to_le_bytes());
+    bytes[18..20].copy_from_slice(&62u16.to_le_bytes());
+    bytes[20..24].copy_from_slice(&1u32.to_le_bytes());
+    bytes[40..48].copy_from_slice(&shoff.to_le_bytes());
+    bytes[52..54].copy_from_slice(&64u16.to_le_bytes());
+    bytes[58..60].copy_from_slice(&64u16.to_le_bytes());
+    bytes[60..62].copy_from_slice(&(shdrs.len() as u16).to_le_bytes());
+    bytes[62..64].copy_from_slice(&(shdrs.len() as u16 - 1).to_le_bytes());
+    bytes
+}
+
+#[test]
+fn test_kernel_info() -> Result<()> {
+    ctftool::init_logging();
+    let work_dir = WorkDir::new()?;
+    main_sync(work_dir.to_path_buf(), &["init"])?;
+    let chal = work_dir.to_path_buf().join("chal");
+    create_dir(&chal)?;
+    main_sync(work_dir.to_path_buf(), &["challenge", "add", "chal"])?;
+
+    let config =
+        "CONFIG_RANDOMIZE_BASE=y\n# CONFIG_X86_SMAP is not set\nCONFIG_HARDENED_USERCOPY=y\n";
+    let mut vmlinux = std::fs::read("/bin/true")?;
+    vmlinux.extend_from_slice(b"Linux version 6.1.0-ctf (gcc 12.2.0)\n\0IKCFG_ST");
+    vmlinux.extend_from_slice(&gzip(config.as_bytes())?);
+    vmlinux.extend_from_slice(b"IKCFG_ED");
+    let mut bzimage = b"MZ\x00\x00HdrS\x1f\x8b\x07".to_vec();
+    bzimage.extend_from_slice(&gzip(&vmlinux)?);
+    bzimage.extend_from_slice(&[0xff; 64]);
+    std::fs::write(chal.join("bzImage"), &bzimage)?;
+
+    let module = elf_with_sections(&[
+        (
+            ".modinfo",
+            b"license=GPL\0name=vuln\0vermagic=6.1.0-ctf SMP mod_unload \0",
+        ),
+        ("__ksymtab_strings", b"vuln_ioctl\0vuln_read\0"),
+    ]);
+    let mut cpio = Vec::new();
+    cpio_entry(&mut cpio, "init", 0o100755, b"#!/bin/sh\n");
+    cpio_entry(&mut cpio, "vuln.ko", 0o100644, &module);
+    cpio_entry(&mut cpio, "TRAILER!!!", 0, b"");
+    cpio.resize((cpio.len() + 511) & !511, 0);
+    std::fs::write(chal.join("rootfs.cpio.gz"), gzip(&cpio)?)?;
+
+    let ui = RecordingUi::new();
+    main_sync_ui(chal.clone(), &["kernel", "info", "--format", "json"], &ui)?;
+    assert_eq!(std::fs::read(chal.join("vmlinux"))?, vmlinux);
+    let info: serde_json::Value = serde_json::from_str(&ui.lines().join("\n"))?;
+    assert_eq!(info["kernels"][0]["path"], "bzImage");
+    assert_eq!(info["kernels"][0]["vmlinux"]["extracted_to"], "vmlinux");
+    assert_eq!(info["initramfses"][0]["modules"][0]["path"], "vuln.ko");
+    assert_eq!(
+        info["initramfses"][0]["modules"][0]["exports"],
+        serde_json::json!(["vuln_ioctl", "vuln_read"])
+    );
+    let ui = RecordingUi::new();
+    main_sync_ui(chal.clone(), &["kernel", "info"], &ui)?;
+    assert!(ui
+        .lines()
+        .contains(&"    Exports: vuln_ioctl, vuln_read".to_string()));
+    assert!(ui
+        .lines()
+        .contains(&"    SMEP: depends on the CPU".to_string()));
+
+    /* SMEP and SMAP are taken from the QEMU command line. */
+    std::fs::write(
+        chal.join("run.sh"),
+        "qemu-system-x86_64 -kernel bzImage -cpu qemu64,+smep,+smap -initrd rootfs.cpio.gz\n",
+    )?;
+    let ui = RecordingUi::new();
+    main_sync_ui(chal.clone(), &["kernel", "info", "--format", "json"], &ui)?;
+    let info = &ui.records()[0];
+    assert_eq!(info["script"], "run.sh");
+    assert_eq!(
+        info["kernels"][0]["mitigations"][0],
+        serde_json::json!({"name": "SMEP", "status": "enabled"})
+    );
+    /* The kernel lacks CONFIG_X86_SMAP. */
+    assert_eq!(info["kernels"][0]["mitigations"][1]["status"], "disabled");
+    let smap = &ctftool::kernel::MITIGATIONS[1];
+    let smap_config = ctftool::kernel::parse_config("CONFIG_X86_SMAP=y\n");
+    let qemu_args = |line: &str| ctftool::kernel::parse_run_script(line).unwrap();
+    for (line, enabled) in [
+        ("qemu-system-x86_64 -kernel bzImage", Some(false)),
+        ("qemu-system-x86_64 -cpu host", None),
+        ("qemu-system-x86_64 -cpu kvm64,smap=on", Some(true)),
+        ("qemu-system-x86_64 -cpu kvm64,+smap,-smap", Some(false)),
+        (
+            "qemu-system-x86_64 -cpu max,+smap -append 'console=ttyS0 nosmap'",
+            Some(false),
+        ),
+    ] {
+        assert_eq!(
+            smap.is_enabled(&smap_config, Some(&qemu_args(line))),
+            enabled,
+            "{}",
+            line
+        );
+    }
+    assert_eq!(smap.is_enabled(&smap_config, None), None);
+    let extracted = ctftool::kernel::extract_vmlinux(&bzimage).unwrap();
+    assert_eq!(
+        ctftool::kernel::version(&extracted.bytes).as_deref(),
+        Some("Linux version 6.1.0-ctf (gcc 12.2.0)")
+    );
+    let config = ctftool::kernel::parse_config(
+        &ctftool::kernel::extract_ikconfig(&extracted.bytes)?.unwrap(),
+    );
+    let enabled: Vec<_> = ctftool::kernel::MITIGATIONS
+        .iter()
+        .filter(|mitigation| mitigation.is_enabled(&config, None) == Some(true))
+        .map(|mitigation| mitigation.name)
+        .collect();
+    assert_eq!(enabled, ["KASLR", "Hardened usercopy"]);
+    let entries = ctftool::kernel::parse_cpio(&std::fs::read(chal.join("rootfs.cpio.gz"))?)?;
+    assert_eq!(entries.len(), 2);
+    let module = ctftool::kernel::analyze_module(&entries[1].data)?;
+    assert_eq!(
+        module.vermagic.as_deref(),
+        Some("6.1.0-ctf SMP mod_unload ")
+    );
+    assert_eq!(module.exports, ["vuln_ioctl", "vuln_read"]);
+
+    /* The last entry is not padded to 4 bytes. */
+    let mut unpadded = Vec::new();
+    cpio_entry(&mut unpadded, "flag", 0o100644, b"hxp{x}");
+    unpadded.truncate(unpadded.len() - 2);
+    let entries = ctftool::kernel::parse_cpio(&unpadded)?;
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].data, b"hxp{x}");
+    Ok(())
+}
+
+#[test]
+fn test_kernel_run() -> Result<()> {
+    ctftool::init_logging();
+    let work_dir = WorkDir::new()?;
+    main_sync(work_dir.to_path_buf(), &["init"])?;
+    let chal = work_dir.to_path_buf().join("chal");
+    create_dir(&chal)?;
+    main_sync(work_dir.to_path_buf(), &["challenge", "add", "chal"])?;
+
+    let script = "#!/bin/sh\n# Start the VM\nexec timeout 60 qemu-system-x86_64-missing \\\n    -m 128M \\\n    -kernel ./bzImage \\\n    -initrd \"./rootfs.cpio.gz\" \\\n    -append 'console=ttyS0 oops=panic' \\\n    -nographic 2>/dev/null\n";
+    assert_eq!(
+        ctftool::kernel::parse_run_script(script).unwrap(),
+        [
+            "qemu-system-x86_64-missing",
+            "-m",
+            "128M",
+            "-kernel",
+            "./bzImage",
+            "-initrd",
+            "./rootfs.cpio.gz",
+            "-append",
+            "console=ttyS0 oops=panic",
+            "-nographic"
+        ]
+    );
+    std::fs::write(chal.join("run.sh"), script)?;
+    let mut cpio = Vec::new();
+    cpio_entry(&mut cpio, "init", 0o100755, b"#!/bin/sh\n");
+    cpio_entry(&mut cpio, "pwnit", 0o100755, b"old");
+    cpio_entry(&mut cpio, "TRAILER!!!", 0, b"");
+    std::fs::write(chal.join("rootfs.cpio.gz"), gzip(&cpio)?)?;
+    std::fs::write(chal.join("pwnit"), b"\x7fELF exploit")?;
+
+    /* QEMU does not exist, but the initramfs is prepared before starting it. */
+    assert!(main_sync(chal.clone(), &["kernel", "run", "--no-build", "-s"]).is_err());
+    let entries = ctftool::kernel::parse_cpio(&std::fs::read(chal.join("pwnit.cpio.gz"))?)?;
+    let names: Vec<_> = entries.iter().map(|entry| entry.name.as_str()).collect();
+    assert_eq!(names, ["init", "pwnit"]);
+    assert_eq!(entries[1].data, b"\x7fELF exploit");
+    assert_eq!(entries[1].mode, 0o100755);
+    Ok(())
+}
+
+#[test]
+fn test_docker_init_dockerfile() -> Result<()> {
+    ctftool::init_logging();
+    let work_dir = WorkDir::new()?;
+    main_sync(work_dir.to_path_buf(), &["init"])?;
+    let chal = work_dir.to_path_buf().join("chal");
+    create_dir(&chal)?;
+    main_sync(work_dir.to_path_buf(), &["challenge", "add", "chal"])?;
+    std::fs::write(
+        chal.join("exe"),
+        "GCC: (Ubuntu 11.4.0-1ubuntu1~22.04) 11.4.0\0GLIBC 2.35-0ubuntu3.8",
+    )?;
+    main_sync(chal.clone(), &["binary", "add", "exe"])?;
+    let dist = chal.join("dist");
+    create_dir(&dist)?;
+    std::fs::write(
+        dist.join("Dockerfile"),
+        "# syntax=docker/dockerfile:1\n\
+         ARG BASE=ubuntu:22.04@sha256:0123456789abcdef\n\
+         FROM --platform=linux/amd64 $BASE AS app\n\
+         RUN apt-get update && \\\n    apt-get install -y --no-install-recommends \\\n        socat xinetd\n\
+         FROM pwn.red/jail\n\
+         COPY --from=app / /srv\n",
+    )?;
+    /* Unreadable or malformed files are skipped with a warning. */
+    std::fs::write(dist.join("Dockerfile.bin"), b"FROM \xff\xfe\n")?;
+    std::fs::write(dist.join("compose.yaml"), "services: [\n")?;
+    let ui = RecordingUi::new();
+    main_sync_ui(chal.clone(), &["docker", "init"], &ui)?;
+    let warnings = ui
+        .events()
+        .into_iter()
+        .filter(|event| matches!(event, Event::Warning(_)))
+        .count();
+    assert_eq!(warnings, 2);
+    std::fs::remove_file(dist.join("Dockerfile.bin"))?;
+    std::fs::remove_file(dist.join("compose.yaml"))?;
+    let compose = std::fs::read(chal.join("docker-compose.yml"))?;
+    assert!(contains(
+        &compose,
+        b"distro: ubuntu:22.04@sha256:0123456789abcdef"
+    ));
+    assert!(contains(&compose, b"packages: socat xinetd"));
+
+    /* Deployment image differs from the heuristics, so the libc version is unknown. */
+    std::fs::remove_file(dist.join("Dockerfile"))?;
+    std::fs::write(
+        dist.join("docker-compose.yml"),
+        "services:\n  chal:\n    image: debian:bookworm\n    platform: linux/arm64\n",
+    )?;
+    main_sync(chal.clone(), &["docker", "init"])?;
+    let compose = std::fs::read(chal.join("docker-compose.yml"))?;
+    assert!(contains(&compose, b"distro: debian:bookworm"));
+    assert!(contains(&compose, b"arch: arm64"));
+    assert!(contains(&compose, b"libc_version: '*'"));
+    Ok(())
+}
+
+#[test]
+fn test_docker_init_service() -> Result<()> {
+    ctftool::init_logging();
+    let work_dir = WorkDir::new()?;
+    main_sync(work_dir.to_path_buf(), &["init"])?;
+    let chal = work_dir.to_path_buf().join("chal");
+    create_dir(&chal)?;
+    main_sync(work_dir.to_path_buf(), &["challenge", "add", "chal"])?;
+    std::fs::copy("/bin/true", chal.join("vuln"))?;
+    main_sync(chal.clone(), &["binary", "add", "vuln"])?;
+    main_sync(chal.clone(), &["docker", "init"])?;
+    let compose = std::fs::read(chal.join("docker-compose.yml"))?;
+    assert!(contains(&compose, b"1337:1337"));
+    assert!(contains(&compose, b"EXEC:./vuln,stderr"));
+    assert!(contains(
+        &compose,
+        b"additional_contexts:\n        packages: "
+    ));
+
+    main_sync(
+        chal.clone(),
+        &["service", "add", "default", "nc://chal.example.com:31337"],
+    )?;
+    main_sync(chal.clone(), &["docker", "init"])?;
+    let compose = std::fs::read(chal.join("docker-compose.yml"))?;
+    assert!(contains(&compose, b"31337:31337"));
+    assert!(contains(&compose, b"TCP-LISTEN:31337,reuseaddr,fork"));
+    main_sync(chal.clone(), &["exploit", "init"])?;
+    assert!(contains(
+        &std::fs::read(chal.join("pwnit.py"))?,
+        b"remote(\"localhost\", 31337)"
+    ));
+
+    main_sync(chal.clone(), &["docker", "init", "--no-gui"])?;
+    let compose = std::fs::read(chal.join("docker-compose.yml"))?;
+    assert!(contains(&compose, b"gui: '0'"));
+    assert!(!contains(&compose, b"X11"));
+    assert!(!contains(&compose, b"DISPLAY"));
+    Ok(())
+}
+
+#[test]
+fn test_qemu_user() -> Result<()> {
+    ctftool::init_logging();
+    let work_dir = WorkDir::new()?;
+    main_sync(work_dir.to_path_buf(), &["init"])?;
+    let chal = work_dir.to_path_buf().join("chal");
+    create_dir(&chal)?;
+    main_sync(work_dir.to_path_buf(), &["challenge", "add", "chal"])?;
+    let mut elf = elf_with_sections(&[]);
+    /* ET_EXEC, EM_AARCH64 */
+    elf[16..18].copy_from_slice(&2u16.to_le_bytes());
+    elf[18..20].copy_from_slice(&183u16.to_le_bytes());
+    std::fs::write(chal.join("vuln"), elf)?;
+    main_sync(chal.clone(), &["binary", "add", "vuln"])?;
+    /* Templates that do not run the binary do not need the launcher. */
+    main_sync(chal.clone(), &["exploit", "init", "--template", "crypto"])?;
+    assert!(!chal.join("qemu.sh").exists());
+    main_sync(chal.clone(), &["exploit", "init"])?;
+    let pwnit = std::fs::read(chal.join("pwnit.py"))?;
+    assert!(contains(
+        &pwnit,
+        b"context.update(arch=\"aarch64\", bits=64, endian=\"little\")"
+    ));
+    assert!(contains(&pwnit, b"process([\"./qemu.sh\"])"));
+    let launcher = std::fs::read(chal.join("qemu.sh"))?;
+    assert!(contains(
+        &launcher,
+        b"exec qemu-aarch64 -L \"$SYSROOT\" ${GDB_PORT:+-g \"$GDB_PORT\"} ./vuln \"$@\""
+    ));
+    assert!(contains(&launcher, b"SYSROOT=/usr/aarch64-linux-gnu"));
+    let gdb = std::fs::read(chal.join("pwnit.gdb"))?;
+    assert!(contains(&gdb, b"set architecture aarch64"));
+    assert!(contains(&gdb, b"target remote localhost:1234"));
+    let repo = git2::Repository::open(work_dir.to_path_buf())?;
+    let index = repo.index()?;
+    for path in ["chal/pwnit.py", "chal/qemu.sh", "chal/pwnit.gdb"] {
+        assert!(index.get_path(Path::new(path), 0).is_some(), "{}", path);
+    }
+
+    std::fs::remove_file(chal.join("qemu.sh"))?;
+    main_sync(chal.clone(), &["docker", "init"])?;
+    assert!(chal.join("qemu.sh").exists());
+    let compose = std::fs::read(chal.join("docker-compose.yml"))?;
+    assert!(contains(&compose, b"EXEC:./qemu.sh,stderr"));
+    let compose = std::fs::read_to_string(chal.join("docker-compose.yml"))?;
+    assert!(compose.contains("arch: arm64"));
+    Ok(())
+}
+
+#[test]
+fn test_exploit_templates() -> Result<()> {
+    ctftool::init_logging();
+    let work_dir = WorkDir::new()?;
+    main_sync(work_dir.to_path_buf(), &["init"])?;
+    let chal = work_dir.to_path_buf().join("chal");
+    create_dir(&chal)?;
+    main_sync(work_dir.to_path_buf(), &["challenge", "add", "chal"])?;
+    main_sync(
+        chal.clone(),
+        &["service", "add", "default", "https://chal.example.com/"],
+    )?;
+    main_sync(chal.clone(), &["exploit", "init"])?;
+    assert!(contains(
+        &std::fs::read(chal.join("pwnit.py"))?,
+        b"default=\"https://chal.example.com/\""
+    ));
+
+    std::fs::copy("/bin/true", chal.join("vuln"))?;
+    main_sync(chal.clone(), &["binary", "add", "vuln"])?;
+    main_sync(chal.clone(), &["exploit", "init", "--template", "heap"])?;
+    let pwnit = std::fs::read(chal.join("pwnit.py"))?;
+    assert!(contains(&pwnit, b"def alloc(size, data):"));
+    assert!(contains(&pwnit, b"def demangle(value):"));
+    main_sync(chal.clone(), &["exploit", "init", "--template", "rust"])?;
+    let main_rs = std::fs::read(chal.join("pwnit").join("src").join("main.rs"))?;
+    assert!(contains(&main_rs, b"const BINARY: &str = \"./vuln\";"));
+    assert!(contains(&main_rs, b"impl Read for Process {"));
+    let tracked = std::process::Command::new("git")
+        .args(["ls-files"])
+        .current_dir(work_dir.to_path_buf())
+        .output()?
+        .stdout;
+    assert!(contains(&tracked, b"chal/pwnit/src/main.rs"));
+
+    let custom = work_dir.to_path_buf().join(".templates").join("custom");
+    create_dir(work_dir.to_path_buf().join(".templates"))?;
+    create_dir(&custom)?;
+    std::fs::write(
+        custom.join("notes.md.tmpl"),
+        "# {challenge}\n{{ for service in services }}* {service.url}\n{{ endfor }}",
+    )?;
+    main_sync(chal.clone(), &["exploit", "init", "--template", "custom"])?;
+    assert_eq!(
+        std::fs::read_to_string(chal.join("notes.md"))?,
+        "# chal\n* https://chal.example.com/\n"
+    );
+    assert!(main_sync(
+        chal.clone(),
+        &["exploit", "init", "--template", "nonexistent"]
+    )
+    .is_err());
+    Ok(())
+}
+
+#[test]
+fn test_exploit_menu() -> Result<()> {
+    ctftool::init_logging();
+    let work_dir = WorkDir::new()?;
+    main_sync(work_dir.to_path_buf(), &["init"])?;
+    let chal = work_dir.to_path_buf().join("chal");
+    create_dir(&chal)?;
+    main_sync(work_dir.to_path_buf(), &["challenge", "add", "chal"])?;
+    let mut elf = elf_with_sections(&[(
+        ".rodata",
+        b"===== NOTES =====\0\
+          1. Add note\n2. Delete note\n3. Print note\n4. Exit\nYour choice: \0\
+          Index: \0\
+          Size of the note: \0\
+          Content: \0",
+    )]);
+    /* ET_EXEC */
+    elf[16..18].copy_from_slice(&2u16.to_le_bytes());
+    std::fs::write(chal.join("vuln"), elf)?;
+    main_sync(chal.clone(), &["binary", "add", "vuln"])?;
+    main_sync(chal.clone(), &["exploit", "init"])?;
+    let pwnit = std::fs::read_to_string(chal.join("pwnit.py"))?;
+    assert!(pwnit.contains(
+        "def alloc(size, data):\n    tube.sendlineafter(b\"Your choice: \", b\"1\")\n    \
+         tube.sendlineafter(b\"Size of the note: \", str(size).encode())\n    \
+         tube.sendafter(b\"Content: \", data)\n"
+    ));
+    assert!(pwnit.contains(
+        "def free(idx):\n    tube.sendlineafter(b\"Your choice: \", b\"2\")\n    \
+         tube.sendlineafter(b\"Index: \", str(idx).encode())\n"
+    ));
+    assert!(pwnit.contains("def show(idx):"));
+    assert!(!pwnit.contains("def exit"));
+    assert!(pwnit.contains("    global tube\n"));
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+async fn test_exploit_run() -> Result<()> {
+    ctftool::init_logging();
+    let (server, work_dir) = serve_and_fetch("ctfd").await?;
+    let chal = work_dir.to_path_buf().join("web-WYSINWYG");
+    create_dir(&chal)?;
+    let pwnit = chal.join("pwnit.py");
+    std::fs::write(&pwnit, "#!/bin/sh\necho \"$@\"\necho flag{nope\n")?;
+    std::fs::set_permissions(&pwnit, std::fs::Permissions::from_mode(0o755))?;
+    /* Fails without flags. */
+    assert!(main(chal.clone(), &["exploit", "run", "--local"])
+        .await
+        .is_err());
+    std::fs::write(
+        &pwnit,
+        "#!/bin/sh\n[ \"$*\" = \"LOCAL DEBUG\" ] && echo 'Flag: CTF{y0u_g0t_1t}'\nprintf '> '\n",
+    )?;
+    let ui = RecordingUi::new();
+    main_ui(
+        chal.clone(),
+        &["exploit", "run", "--local", "--submit", "--", "DEBUG"],
+        &ui,
+    )
+    .await?;
+    /* Prompts are shown before a newline arrives. */
+    assert_eq!(ui.lines()[..2], ["Flag: CTF{y0u_g0t_1t}", "> "]);
+    server.shutdown().await?;
+    let ctx = ctf::load(work_dir.to_path_buf())?;
+    let flags = &ctf::find_challenge(&ctx.ctf, "web-WYSINWYG")?.flags;
+    assert_eq!(flags.len(), 1);
+    assert_eq!(flags[0].value, "CTF{y0u_g0t_1t}");
+    assert_eq!(flags[0].target, "local");
+    assert_eq!(flags[0].submission.as_deref(), Some("correct"));
+    /* Flags that could not be submitted are still recorded. */
+    std::fs::write(&pwnit, "#!/bin/sh\necho 'CTF{0ffl1n3}'\n")?;
+    let ui = RecordingUi::new();
+    main_ui(
+        chal.clone(),
+        &["exploit", "run", "--local", "--submit"],
+        &ui,
+    )
+    .await?;
+    assert!(ui
+        .events()
+        .iter()
+        .any(|event| matches!(event, Event::Warning(text) if text.contains("CTF{0ffl1n3}"))));
+    let ctx = ctf::load(work_dir.to_path_buf())?;
+    let flags = &ctf::find_challenge(&ctx.ctf, "web-WYSINWYG")?.flags;
+    assert_eq!(flags.len(), 2);
+    assert_eq!(flags[1].value, "CTF{0ffl1n3}");
+    assert_eq!(flags[1].submission, None);
+    Ok(())
+}
+
+fn tar_entry(tar: &mut Vec<u8>, name: &str, data: &[u8]) {
+    let mut header = [0u8; 512];
+    header[..name.len()].copy_from_slice(name.as_bytes());
+    header[124..135].copy_from_slice(format!("{:011o}", data.len()).as_bytes());
+    header[156] = b'0';
+    header[257..263].copy_from_slice(b"ustar\0");
+    tar.extend_from_slice(&header);
+    tar.extend_from_slice(data);
+    tar.resize(tar.len().div_ceil(512) * 512, 0);
+}
+
+/// Creates a zip archive with a single stored file.
+fn zip(name: &str, data: &[u8]) -> Vec<u8> {
+    let mut zip = b"PK\x03\x04".to_vec();
+    zip.extend_from_slice(&[0; 14]);
+    zip.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    zip.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    zip.extend_from_slice(&(name.len() as u16).to_le_bytes());
+    zip.extend_from_slice(&[0; 2]);
+    zip.extend_from_slice(name.as_bytes());
+    zip.extend_from_slice(data);
+    let central_directory = zip.len() as u32;
+    zip.extend_from_slice(b"PK\x01\x02");
+    zip.extend_from_slice(&[0; 16]);
+    zip.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    zip.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    zip.extend_from_slice(&(name.len() as u16).to_le_bytes());
+    zip.extend_from_slice(&[0; 16]);
+    zip.extend_from_slice(name.as_bytes());
+    let central_directory_size = zip.len() as u32 - central_directory;
+    zip.extend_from_slice(b"PK\x05\x06\0\0\0\0\x01\0\x01\0");
+    zip.extend_from_slice(&central_directory_size.to_le_bytes());
+    zip.extend_from_slice(&central_directory.to_le_bytes());
+    zip.extend_from_slice(&[0; 2]);
+    zip
+}
+
+#[test]
+fn test_flag() -> Result<()> {
+    ctftool::init_logging();
+    let work_dir = WorkDir::new()?;
+    main_sync(work_dir.to_path_buf(), &["init"])?;
+    let mut tar = Vec::new();
+    tar_entry(&mut tar, "README", b"No flags here");
+    tar_entry(&mut tar, "flag.zip", &zip("flag.txt", b"hxp{z1pp3d}\n"));
+    tar.resize(tar.len() + 1024, 0);
+    let mut cpio = Vec::new();
+    cpio_entry(&mut cpio, "flag", 0o100644, b"hxp{1n1tramfs} fake{flag}");
+    cpio_entry(&mut cpio, "TRAILER!!!", 0, b"");
+    std::fs::write(work_dir.to_path_buf().join("dist.tar.gz"), gzip(&tar)?)?;
+    std::fs::write(work_dir.to_path_buf().join("rootfs.cpio.gz"), gzip(&cpio)?)?;
+    let detector = ctftool::flag::Detector::new(ctftool::flag::DEFAULT_FORMAT)?;
+    let matches = detector.scan("dist.tar.gz", &gzip(&tar)?);
+    assert_eq!(matches.len(), 1);
+    assert_eq!(matches[0].location, "dist.tar.gz:<gzip>:flag.zip:flag.txt");
+    assert_eq!(matches[0].flag, "hxp{z1pp3d}");
+    main_sync(
+        work_dir.to_path_buf(),
+        &["flag", "scan", "dist.tar.gz", "rootfs.cpio.gz"],
+    )?;
+    assert!(main_sync(work_dir.to_path_buf(), &["flag", "scan", ".ctf"]).is_err());
+
+    /* Corrupt archives are scanned as plain data. */
+    let mut bad_offset = zip("flag.txt", b"hxp{z1pp3d}\n");
+    let eocd = bad_offset.len() - 22;
+    bad_offset[eocd + 16..eocd + 20].copy_from_slice(&0xffffu32.to_le_bytes());
+    assert!(ctftool::archive::extract(&bad_offset).is_err());
+    let mut bad_local = zip("flag.txt", b"hxp{z1pp3d}\n");
+    let eocd = bad_local.len() - 22;
+    let central_directory =
+        u32::from_le_bytes(bad_local[eocd + 16..eocd + 20].try_into()?) as usize;
+    bad_local[central_directory + 42..central_directory + 46]
+        .copy_from_slice(&0xfff0u32.to_le_bytes());
+    assert!(ctftool::archive::extract(&bad_local).is_err());
+    let truncated = zip("flag.txt", b"hxp{z1pp3d}\n")[..40].to_vec();
+    std::fs::write(work_dir.to_path_buf().join("truncated.zip"), &truncated)?;
+    assert!(ctftool::archive::extract(&truncated).is_err());
+    assert!(main_sync(work_dir.to_path_buf(), &["flag", "scan", "truncated.zip"]).is_err());
+    std::fs::write(work_dir.to_path_buf().join("bad.zip"), &bad_offset)?;
+    main_sync(work_dir.to_path_buf(), &["flag", "scan", "bad.zip"])?;
+
+    assert!(main_sync(work_dir.to_path_buf(), &["flag", "set-format", "hxp{"]).is_err());
+    main_sync(
+        work_dir.to_path_buf(),
+        &["flag", "set-format", r"hxp\{[^}]+\}"],
+    )?;
+    let ctx = ctf::load(work_dir.to_path_buf())?;
+    let detector = ctftool::flag::Detector::for_ctf(&ctx.ctf)?;
+    let matches = detector.scan("rootfs.cpio.gz", &gzip(&cpio)?);
+    assert_eq!(matches.len(), 1);
+    assert_eq!(matches[0].flag, "hxp{1n1tramfs}");
+
+    assert_eq!(
+        ctftool::flag::guess_format([
+            "Flag format: hxp{...}",
+            "Connect with nc, the flag is in /flag",
+            "Example flag: hxp{th1s_1s_n0t_4_fl4g} (not CTF{...})",
+        ])?
+        .as_deref(),
+        Some(r"hxp\{[^}\s]+\}")
+    );
+    assert_eq!(ctftool::flag::guess_format(["No flags"])?, None);
+    /* Braces in code and formulas are not example flags. */
+    assert_eq!(
+        ctftool::flag::guess_format([
+            r"Compute $\frac{p}{q}$ and get the flag",
+            "int main() { puts(flag); }",
+        ])?,
+        None
+    );
+    assert_eq!(
+        ctftool::flag::guess_format(["The flag is in the format kalmar{...}"])?.as_deref(),
+        Some(r"kalmar\{[^}\s]+\}")
+    );
+    Ok(())
+}
+
+#[test]
+fn test_ad() -> Result<()> {
+    ctftool::init_logging();
+    assert_eq!(
+        ctftool::ad::expand_range("10.60.1-3.1")?,
+        ["10.60.1.1", "10.60.2.1", "10.60.3.1"]
+    );
+    assert!(ctftool::ad::expand_range("10.60.3-1.1").is_err());
+    assert_eq!(
+        ctftool::ad::parse_teams(r#"{"teams": [{"id": 1, "ip": "10.60.1.1"}, {"id": 2}]}"#)?,
+        ["10.60.1.1"]
+    );
+    assert_eq!(
+        ctftool::ad::parse_teams(r#"["10.60.1.1", "10.60.2.1"]"#)?,
+        ["10.60.1.1", "10.60.2.1"]
+    );
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let address = listener.local_addr()?.to_string();
+    let server = std::thread::spawn(move || -> Result<Vec<String>> {
+        use std::io::{BufRead, Write};
+        let (mut stream, _) = listener.accept()?;
+        stream.write_all(b"Welcome to the flag submission service\n\n")?;
+        let mut flags = Vec::new();
+        for line in std::io::BufReader::new(stream.try_clone()?).lines() {
+            let line = line?;
+            stream.write_all(format!("{} OK\n", line).as_bytes())?;
+            flags.push(line);
+        }
+        Ok(flags)
+    });
+
+    let work_dir = WorkDir::new()?;
+    main_sync(work_dir.to_path_buf(), &["init"])?;
+    let chal = work_dir.to_path_buf().join("chal");
+    create_dir(&chal)?;
+    main_sync(work_dir.to_path_buf(), &["challenge", "add", "chal"])?;
+    main_sync(
+        chal.clone(),
+        &["service", "add", "default", "nc://chal.example.com:31337"],
+    )?;
+    assert!(main_sync(work_dir.to_path_buf(), &["ad", "run", "chal", "--once"]).is_err());
+    main_sync(
+        work_dir.to_path_buf(),
+        &[
+            "ad",
+            "init",
+            "--teams",
+            "127.0.0.1-3",
+            "--exclude",
+            "127.0.0.2",
+            "--timeout",
+            "1",
+            "--submit-tcp",
+            &address,
+        ],
+    )?;
+    let pwnit = chal.join("pwnit.py");
+    std::fs::write(
+        &pwnit,
+        "#!/bin/sh\n[ \"$1\" = HOST=127.0.0.3 ] && exec sleep 10\necho \"FLAG{$1_$2_$3}\"\n",
+    )?;
+    std::fs::set_permissions(&pwnit, std::fs::Permissions::from_mode(0o755))?;
+    for _ in 0..2 {
+        main_sync(
+            work_dir.to_path_buf(),
+            &["ad", "run", "chal", "--once", "--jobs", "1", "--", "DEBUG"],
+        )?;
+    }
+    let log = std::fs::read_to_string(chal.join("ad-flags.log"))?;
+    let lines: Vec<Vec<&str>> = log.lines().map(|line| line.split('\t').collect()).collect();
+    assert_eq!(lines.len(), 1);
+    assert_eq!(
+        lines[0][1..],
+        ["127.0.0.1", "FLAG{HOST=127.0.0.1_PORT=31337_DEBUG}", "OK"]
+    );
+    assert_eq!(
+        server.join().unwrap()?,
+        ["FLAG{HOST=127.0.0.1_PORT=31337_DEBUG}"]
+    );
+
+    main_sync(
+        work_dir.to_path_buf(),
+        &[
+            "ad",
+            "init",
+            "--submit-http",
+            "http://127.0.0.1:1/flags",
+            "--token",
+            "s3cr3t",
+        ],
+    )?;
+    let ctf = std::fs::read_to_string(work_dir.to_path_buf().join(".ctf"))?;
+    assert!(ctf.contains("http://127.0.0.1:1/flags"));
+    assert!(!ctf.contains("s3cr3t"));
+    let ctx = ctf::load(work_dir.to_path_buf())?;
+    assert_eq!(ctx.credentials.team_token.as_deref(), Some("s3cr3t"));
+    Ok(())
+}
+
+/// Creates an Ethernet/IPv4/TCP packet record for a little-endian pcap.
+fn pcap_packet(
+    src: ([u8; 4], u16),
+    dst: ([u8; 4], u16),
+    seq: u32,
+    flags: u8,
+    data: &[u8],
+) -> Vec<u8> {
+    let mut tcp = Vec::new();
+    tcp.extend_from_slice(&src.1.to_be_bytes());
+    tcp.extend_from_slice(&dst.1.to_be_bytes());
+    tcp.extend_from_slice(&seq.to_be_bytes());
+    tcp.extend_from_slice(&[0, 0, 0, 0, 0x50, flags, 0xff, 0xff, 0, 0, 0, 0]);
+    tcp.extend_from_slice(data);
+    let mut ip = vec![0x45, 0, 0, 0, 0, 0, 0, 0, 64, 6, 0, 0];
+    ip[2..4].copy_from_slice(&(20 + tcp.len() as u16).to_be_bytes());
+    ip.extend_from_slice(&src.0);
+    ip.extend_from_slice(&dst.0);
+    ip.extend_from_slice(&tcp);
+    let mut frame = vec![0; 12];
+    frame.extend_from_slice(&[0x08, 0x00]);
+    frame.extend_from_slice(&ip);
+    let mut record = vec![0; 8];
+    record.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+    record.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+    record.extend_from_slice(&frame);
+    record
+}
+
+#[test]
+fn test_ad_replay() -> Result<()> {
+    ctftool::init_logging();
+    let client = ([10, 60, 2, 1], 40000);
+    let server = ([10, 60, 1, 1], 31337);
+    let mut capture = vec![0xd4, 0xc3, 0xb2, 0xa1, 2, 0, 4, 0];
+    capture.extend_from_slice(&[0; 8]);
+    capture.extend_from_slice(&[0xff, 0xff, 0, 0, 1, 0, 0, 0]);
+    capture.extend(pcap_packet(client, server, 100, 0x02, b""));
+    capture.extend(pcap_packet(server, client, 500, 0x12, b""));
+    capture.extend(pcap_packet(server, client, 501, 0x18, b"Welcome!\n> "));
+    capture.extend(pcap_packet(client, server, 101, 0x18, b"cat flag\n"));
+    /* Retransmission. */
+    capture.extend(pcap_packet(client, server, 101, 0x18, b"cat flag\n"));
+    capture.extend(pcap_packet(server, client, 512, 0x18, b"FLAG{pwned}\n"));
+    capture.extend(pcap_packet(client, server, 110, 0x11, b""));
+    capture.extend(pcap_packet(client, ([10, 60, 1, 1], 22), 7, 0x02, b""));
+
+    let segments = ctftool::pcap::parse(&capture)?;
+    assert_eq!(segments.len(), 8);
+    let streams = ctftool::pcap::split_streams(&segments, &[31337]);
+    assert_eq!(streams.len(), 2);
+    assert_eq!(streams[0].data(true), b"cat flag\n");
+    assert_eq!(streams[0].data(false), b"Welcome!\n> FLAG{pwned}\n");
+    assert!(!streams[0].damaged);
+
+    /* Out-of-order segments are reordered, and gaps that are never filled damage the stream. */
+    let mut reordered = capture[..24].to_vec();
+    reordered.extend(pcap_packet(client, server, 100, 0x02, b""));
+    reordered.extend(pcap_packet(client, server, 105, 0x18, b"flag\n"));
+    reordered.extend(pcap_packet(client, server, 101, 0x18, b"cat "));
+    reordered.extend(pcap_packet(server, client, 500, 0x12, b""));
+    reordered.extend(pcap_packet(server, client, 505, 0x18, b"lost?\n"));
+    let streams = ctftool::pcap::split_streams(&ctftool::pcap::parse(&reordered)?, &[31337]);
+    assert_eq!(streams[0].data(true), b"cat flag\n");
+    assert_eq!(streams[0].data(false), b"lost?\n");
+    assert!(streams[0].damaged);
+
+    let work_dir = WorkDir::new()?;
+    main_sync(work_dir.to_path_buf(), &["init"])?;
+    let chal = work_dir.to_path_buf().join("chal");
+    create_dir(&chal)?;
+    main_sync(work_dir.to_path_buf(), &["challenge", "add", "chal"])?;
+    main_sync(
+        chal.clone(),
+        &["service", "add", "default", "nc://chal.example.com:31337"],
+    )?;
+    let captures = work_dir.to_path_buf().join("captures");
+    create_dir(&captures)?;
+    std::fs::write(captures.join("20261019-120000.pcap"), &capture)?;
+    main_sync(work_dir.to_path_buf(), &["ad", "streams", "--flags"])?;
+    assert!(main_sync(
+        work_dir.to_path_buf(),
+        &["ad", "replay", "20261019-120000/3"]
+    )
+    .is_err());
+    main_sync(
+        work_dir.to_path_buf(),
+        &["ad", "replay", "20261019-120000/1"],
+    )?;
+    let script = std::fs::read_to_string(chal.join("replay-20261019-120000-1.py"))?;
+    assert!(script.contains(r#"remote(args.HOST or "10.60.1.1", int(args.PORT or 31337))"#));
+    assert!(script.contains(r#"tube.recvuntil(b"Welcome!\n> ")"#));
+    assert!(script.contains(r#"tube.send(b"cat flag\n")"#));
+    assert!(script.contains("print(tube.recvrepeat(1)"));
+    Ok(())
+}
+
+#[test]
+fn test_container_ps() -> Result<()> {
+    let ndjson = r#"{"Service":"main","State":"running","Image":"chal-main","Publishers":[]}
+{"Service":"service","State":"exited","Image":"chal-service","Publishers":[{"URL":"0.0.0.0","TargetPort":1337,"PublishedPort":1337,"Protocol":"tcp"},{"URL":"::","TargetPort":1337,"PublishedPort":1337,"Protocol":"tcp"}]}
+"#;
+    let containers = ctftool::container::parse_ps(ndjson)?;
+    assert_eq!(containers.len(), 2);
+    assert_eq!(containers[0].state, "running");
+    assert_eq!(containers[1].service, "service");
+    assert_eq!(containers[1].ports, ["1337->1337"]);
+    let array = r#"[{"Service":"main","State":"running","Image":"chal-main"}]"#;
+    assert_eq!(ctftool::container::parse_ps(array)?[0].image, "chal-main");
+    assert_eq!(
+        ctftool::container::parse_created("2024-05-11T10:20:30.123456789Z")?,
+        ctftool::container::parse_created("2024-05-11 12:20:30.123456789 +0200 CEST")?
+    );
+    assert_eq!(ctftool::container::format_age(3 * 86400 + 5), "3d");
+    Ok(())
+}